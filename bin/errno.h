@@ -0,0 +1,25 @@
+// generated from src/errno.rs, don't edit
+#pragma once
+
+#define EPERM 1 // operation not permitted
+#define ENOENT 2 // no such file or directory
+#define ESRCH 3 // no such process
+#define EINTR 4 // interrupted system call
+#define EIO 5 // input/output error
+#define E2BIG 7 // argument list too long
+#define ENOEXEC 8 // exec format error
+#define EBADF 9 // bad file descriptor
+#define EAGAIN 11 // resource temporarily unavailable
+#define ENOMEM 12 // cannot allocate memory
+#define EFAULT 14 // bad address
+#define EBUSY 16 // device or resource busy
+#define ENODEV 19 // no such device
+#define ENOTDIR 20 // not a directory
+#define EISDIR 21 // is a directory
+#define EINVAL 22 // invalid argument
+#define EMFILE 24 // too many open files
+#define ENOSPC 28 // no space left on device
+#define ENAMETOOLONG 36 // file name too long
+#define ENOSYS 38 // function not implemented
+#define EIDRM 43 // identifier removed
+#define ETIMEDOUT 110 // connection timed out
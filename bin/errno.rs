@@ -0,0 +1,24 @@
+// generated from src/errno.rs, don't edit
+
+pub const EPERM: isize = 1; // operation not permitted
+pub const ENOENT: isize = 2; // no such file or directory
+pub const ESRCH: isize = 3; // no such process
+pub const EINTR: isize = 4; // interrupted system call
+pub const EIO: isize = 5; // input/output error
+pub const E2BIG: isize = 7; // argument list too long
+pub const ENOEXEC: isize = 8; // exec format error
+pub const EBADF: isize = 9; // bad file descriptor
+pub const EAGAIN: isize = 11; // resource temporarily unavailable
+pub const ENOMEM: isize = 12; // cannot allocate memory
+pub const EFAULT: isize = 14; // bad address
+pub const EBUSY: isize = 16; // device or resource busy
+pub const ENODEV: isize = 19; // no such device
+pub const ENOTDIR: isize = 20; // not a directory
+pub const EISDIR: isize = 21; // is a directory
+pub const EINVAL: isize = 22; // invalid argument
+pub const EMFILE: isize = 24; // too many open files
+pub const ENOSPC: isize = 28; // no space left on device
+pub const ENAMETOOLONG: isize = 36; // file name too long
+pub const ENOSYS: isize = 38; // function not implemented
+pub const EIDRM: isize = 43; // identifier removed
+pub const ETIMEDOUT: isize = 110; // connection timed out
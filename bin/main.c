@@ -2,23 +2,22 @@ extern int write(int fd, char *buf, int count);
 extern int sleep(int delay);
 extern int create_window(char *title, int title_len, int x, int y, int width,
                          int height);
-extern int map_window(int window_id, unsigned long vaddr);
+extern unsigned long map_window(int window_id, unsigned long vaddr);
 extern int sync_window(int window_id);
 extern int fork();
 extern int wait_exit();
 extern int read(int fd, char *buf, int count);
 extern int seek(int fd, long offset, int whence);
 extern int open(char *path);
-extern int execve(char *path);
+extern int execve(char *path, char **argv, char **envp);
 
 char buf[4096];
 
-int main(void) {
+int main(int argc, char **argv, char **envp) {
   int width = 640;
   int height = 480;
   int window_id = create_window("window", 6, 10, 10, width, height);
-  unsigned long buf_addr = 0x10000000;
-  int err = map_window(window_id, buf_addr);
+  unsigned long buf_addr = map_window(window_id, 0);
   for (int y = 0; y < height; y++) {
     for (int x = 0; x < width; x++) {
       int x0 = x - width / 2;
@@ -35,7 +34,8 @@ int main(void) {
   //   char *msg = "Hello, world!\n";
   //   write(0, msg, 14);
   // } else {
-  //   // execve("/bin/app");
+  //   // char *argv[] = {"/bin/app", 0};
+  //   // execve("/bin/app", argv, 0);
   //   char *msg = "Goodbye, world!\n";
   //   write(0, msg, 16);
   // }
@@ -8,11 +8,21 @@ double fabs(double x) {
   }
 }
 
-int main() {
+#define NTHREADS 4
+#define THREAD_STACK_SIZE 0x1000
+
+const int iXmax = 500;
+const int iYmax = 500;
+static unsigned long buf_addr;
+
+static unsigned char stacks[NTHREADS][THREAD_STACK_SIZE]
+    __attribute__((aligned(16)));
+
+/* every thread draws the rows iY = n, n + NTHREADS, n + 2 * NTHREADS, ... */
+void draw_rows(void *arg) {
+  long n = (long)arg;
   /* screen ( integer) coordinate */
   int iX, iY;
-  const int iXmax = 500;
-  const int iYmax = 500;
   /* world ( double) coordinate = parameter plane*/
   double Cx, Cy;
   const double CxMin = -2.5;
@@ -24,8 +34,7 @@ int main() {
   double PixelHeight = (CyMax - CyMin) / iYmax;
   /* color component ( R or G or B) is coded from 0 to 255 */
   /* it is 24 bit color RGB file */
-  const int MaxColorComponentValue = 255;
-  static unsigned char color[3];
+  unsigned char color[3];
   /* Z=Zx+Zy*i  ;   Z0 = 0 */
   double Zx, Zy;
   double Zx2, Zy2; /* Zx2=Zx*Zx;  Zy2=Zy*Zy  */
@@ -37,13 +46,8 @@ int main() {
   double ER2 = EscapeRadius * EscapeRadius;
 
   int width = iXmax;
-  int height = iYmax;
-  int window_id = create_window("mandelbrot", 10, 10, 10, width, height);
-  unsigned long buf_addr = 0x10000000;
-  int err = map_window(window_id, buf_addr);
 
-  /*create new file,give it a name and open it in binary mode  */
-  for (iY = 0; iY < iYmax; iY++) {
+  for (iY = n; iY < iYmax; iY += NTHREADS) {
     Cy = CyMin + iY * PixelHeight;
     if (fabs(Cy) < PixelHeight / 2)
       Cy = 0.0; /* Main antenna */
@@ -80,6 +84,23 @@ int main() {
       *((unsigned char *)(buf_addr + (iY * width + iX) * 4) + 3) = 0x00;
     }
   }
+}
+
+int main(int argc, char **argv, char **envp) {
+  int width = iXmax;
+  int height = iYmax;
+  int window_id = create_window("mandelbrot", 10, 10, 10, width, height);
+  buf_addr = map_window(window_id, 0);
+
+  int tids[NTHREADS];
+  for (long n = 0; n < NTHREADS; n++) {
+    tids[n] = thread_create(draw_rows, (void *)n,
+                            stacks[n] + THREAD_STACK_SIZE, 0);
+  }
+  for (int n = 0; n < NTHREADS; n++) {
+    thread_join(tids[n]);
+  }
+
   sync_window(window_id);
   return 0;
-}
\ No newline at end of file
+}
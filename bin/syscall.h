@@ -1,12 +1,198 @@
+#include "errno.h"
+
+// every system call returns a negated errno on failure
+// returns the bytes written, which may be fewer than `count`
 int write(int fd, char *buf, int count);
 int sleep(int delay);
 int create_window(char *title, int title_len, int x, int y, int width,
                   int height);
-int map_window(int window_id, unsigned long vaddr);
+// maps the frame buffer of the window at `vaddr`, or where the kernel chooses if `vaddr` is 0,
+// returns the address
+unsigned long map_window(int window_id, unsigned long vaddr);
 int sync_window(int window_id);
 int fork();
 int wait_exit();
 int read(int fd, char *buf, int count);
 int seek(int fd, long offset, int whence);
 int open(char *path);
-int execve(char *path);
\ No newline at end of file
+// `argv` and `envp` are NULL terminated, either can be NULL for none
+int execve(char *path, char **argv, char **envp);
+
+#define CLONE_VM 0x100
+#define CLONE_FILES 0x400
+#define CLONE_SIGHAND 0x800
+#define CLONE_THREAD 0x10000
+#define CLONE_SETTLS 0x80000
+
+int clone(unsigned long flags, void *stack, void *tls);
+// runs fn(arg) in a new thread on the stack ending at `stack`, returns the thread id
+int thread_create(void (*fn)(void *), void *arg, void *stack, void *tls);
+int thread_join(int tid);
+int getpid();
+int gettid();
+
+#define FUTEX_WAIT 0
+#define FUTEX_WAKE 1
+#define FUTEX_REQUEUE 3
+#define FUTEX_PRIVATE_FLAG 128
+
+// FUTEX_WAIT: arg3 is the timeout in ticks (0 waits forever)
+// FUTEX_REQUEUE: arg3 is the number of waiters moved to uaddr2
+// returns a negated errno on failure
+int futex(int *uaddr, int op, int val, unsigned long arg3, int *uaddr2);
+
+#define SIGHUP 1
+#define SIGINT 2
+#define SIGQUIT 3
+#define SIGILL 4
+#define SIGTRAP 5
+#define SIGABRT 6
+#define SIGBUS 7
+#define SIGFPE 8
+#define SIGKILL 9
+#define SIGUSR1 10
+#define SIGSEGV 11
+#define SIGUSR2 12
+#define SIGPIPE 13
+#define SIGALRM 14
+#define SIGTERM 15
+#define SIGCHLD 17
+#define SIGCONT 18
+#define SIGSTOP 19
+#define SIGTSTP 20
+#define SIGXCPU 24
+
+#define SIG_DFL ((void (*)(int))0)
+#define SIG_IGN ((void (*)(int))1)
+
+#define SA_NODEFER 0x40000000
+#define SA_RESETHAND 0x80000000
+
+#define SIG_BLOCK 0
+#define SIG_UNBLOCK 1
+#define SIG_SETMASK 2
+
+typedef unsigned long sigset_t;
+
+struct sigaction {
+  void (*sa_handler)(int);
+  unsigned long sa_flags;
+  sigset_t sa_mask;
+};
+
+int kill(int pid, int sig);
+int sigaction(int sig, const struct sigaction *act, struct sigaction *oldact);
+int sigprocmask(int how, const sigset_t *set, sigset_t *oldset);
+
+// semaphores: `name` is NULL for an anonymous one, shared with forked children only
+// the functions return a negated errno on failure
+int sem_open(char *name, int count);
+int sem_wait(int sid);
+// `timeout` is in ticks, fails with -ETIMEDOUT
+int sem_timedwait(int sid, unsigned long timeout);
+// fails with -EAGAIN instead of blocking
+int sem_trywait(int sid);
+int sem_post(int sid);
+int sem_close(int sid);
+// waiters fail with -EIDRM
+int sem_delete(int sid);
+
+#define RLIMIT_CPU 0
+#define RLIMIT_NPROC 6
+#define RLIMIT_NOFILE 7
+#define RLIMIT_AS 9
+
+#define RLIM_INFINITY (~0UL)
+
+struct rlimit {
+  unsigned long rlim_cur;
+  unsigned long rlim_max;
+};
+
+// RLIMIT_CPU is in seconds, RLIMIT_AS in bytes
+// the functions return a negated errno on failure, a hard limit can only be lowered
+int getrlimit(int resource, struct rlimit *rlim);
+int setrlimit(int resource, const struct rlimit *rlim);
+
+// deadline scheduling: `runtime` ticks of CPU in every `period` ticks, within `deadline` ticks
+// of the start of each period (runtime <= deadline <= period <= 2^40)
+struct sched_deadline {
+  unsigned long runtime;
+  unsigned long deadline;
+  unsigned long period;
+};
+
+// reserves CPU time for the calling thread, NULL drops the reservation
+// fails with -EBUSY when the reservations would take more than 95% of the CPU
+int sched_deadline(const struct sched_deadline *params);
+
+// saves `pid`, which is stopped (SIGSTOP) or the caller, to the file `path`
+// returns 0, and 1 in the process later restored from a checkpoint of itself
+int checkpoint(int pid, char *path);
+// starts a copy of the process saved in `path`, returns its pid
+int restore(char *path);
+
+// a process killed by SIGSEGV, SIGILL, SIGBUS, SIGABRT, ... leaves an ELF core file
+// /core.<name>.<pid> (on by default), returns the previous setting
+int coredump(int enable);
+
+#define PTRACE_PEEKDATA 2
+#define PTRACE_POKEDATA 5
+#define PTRACE_CONT 7
+#define PTRACE_KILL 8
+#define PTRACE_SINGLESTEP 9
+#define PTRACE_GETREGS 12
+#define PTRACE_SETREGS 13
+#define PTRACE_ATTACH 16
+#define PTRACE_DETACH 17
+
+// registers of a traced process, the pc takes the place of x0
+struct user_regs_struct {
+  unsigned long pc, ra, sp, gp, tp, t0, t1, t2, s0, s1, a0, a1, a2, a3, a4, a5, a6, a7, s2, s3,
+      s4, s5, s6, s7, s8, s9, s10, s11, t3, t4, t5, t6;
+};
+
+// PTRACE_PEEKDATA and PTRACE_GETREGS store the result at `data`, PTRACE_POKEDATA writes the
+// word `data` and PTRACE_CONT, PTRACE_SINGLESTEP and PTRACE_DETACH deliver the signal `data`
+// (0 for none). memory and registers are accessed only while the process is stopped
+long ptrace(long request, int pid, void *addr, void *data);
+
+// waits for the next stop of a traced process, or for its exit, and returns its pid
+// `status` may be NULL
+int ptrace_wait(int pid, int *status);
+
+#define WIFEXITED(status) (((status)&0x7f) == 0)
+#define WIFSTOPPED(status) (((status)&0xff) == 0x7f)
+#define WSTOPSIG(status) (((status) >> 8) & 0xff)
+
+#define STRACE_SYSCALLS 1
+#define STRACE_CHILDREN 2
+
+// logs the system calls of `pid` (0 for the caller) to the kernel log. with STRACE_CHILDREN,
+// processes and threads created afterwards are traced as well. flags 0 switches tracing off
+int strace(int pid, unsigned long flags);
+
+// the vDSO: code mapped into every process which reads the time and the pid without a system
+// call. the clocks count from boot, CLOCK_PROCESS_CPUTIME_ID in ticks of the scheduler
+#define CLOCK_REALTIME 0
+#define CLOCK_MONOTONIC 1
+#define CLOCK_PROCESS_CPUTIME_ID 2
+#define CLOCK_MONOTONIC_RAW 4
+#define CLOCK_REALTIME_COARSE 5
+#define CLOCK_MONOTONIC_COARSE 6
+#define CLOCK_BOOTTIME 7
+
+struct timespec {
+  long tv_sec;
+  long tv_nsec;
+};
+
+#define VDSO_CLOCK_GETTIME 0x3fffffa000UL
+#define VDSO_GETPID 0x3fffffa004UL
+
+// fails with -EINVAL for other clocks
+static inline int clock_gettime(int clock, struct timespec *tp) {
+  return ((int (*)(int, struct timespec *))VDSO_CLOCK_GETTIME)(clock, tp);
+}
+
+static inline int vdso_getpid() { return ((int (*)(void))VDSO_GETPID)(); }
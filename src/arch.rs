@@ -19,12 +19,15 @@ pub mod target {
     pub use super::riscv64::paging;
     pub use super::riscv64::plic;
     pub use super::riscv64::process;
+    pub use super::riscv64::ptrace;
     pub use super::riscv64::serial;
+    pub use super::riscv64::signal;
     pub use super::riscv64::start;
     pub use super::riscv64::syscall;
     pub use super::riscv64::trampoline;
     pub use super::riscv64::trap;
     pub use super::riscv64::virtio;
+    pub use super::riscv64::watchdog;
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -7,15 +7,20 @@ pub mod graphics;
 pub mod init;
 pub mod interrupt;
 pub mod layout;
+pub mod linux;
 pub mod loader;
 pub mod nullproc;
 pub mod paging;
 pub mod plic;
 pub mod process;
+pub mod ptrace;
 pub mod serial;
+pub mod signal;
 pub mod start;
 pub mod syscall;
 pub mod trampoline;
 pub mod trap;
 pub mod uart;
+pub mod vdso;
 pub mod virtio;
+pub mod watchdog;
@@ -1,66 +1,142 @@
 use super::csr::Csr;
 use super::*;
-use core::arch::asm;
+use core::arch::global_asm;
 
 pub const INTERVAL: usize = 100000;
+// frequency of mtime on the qemu virt machine
+pub const TIMEBASE_FREQ: usize = 10000000;
+pub const TICKS_PER_SEC: usize = TIMEBASE_FREQ / INTERVAL;
 pub const MSIP: usize = 0x0;
 pub const MTIME: usize = 0xbff8;
 pub const MTIMECMP: usize = 0x4000;
-static mut TIMER_SCRATCH: [usize; 5] = [0; 5];
-
-#[repr(align(4))]
-pub unsafe extern "C" fn timervec() {
-    #[cfg(target_pointer_width = "32")]
-    asm!(
-        ".align 4"
-        "csrrw a0, mscratch, a0",
-        "sw a1, 0(a0)",
-        "sw a2, 8(a0)",
-        "sw a3, 16(a0)",
-        "lw a1, 24(a0)",
-        "lw a2, 32(a0)",
-        "lw a3, 0(a1)",
-        "add a3, a3, a2",
-        "sw a3, 0(a1)",
-        "li a1, 2",
-        "csrw sip, a1",
-        "lw a3, 16(a0)",
-        "lw a2, 8(a0)",
-        "lw a1, 0(a0)",
-        "csrrw a0, mscratch, a0",
-        "mret",
-    );
-
-    #[cfg(target_pointer_width = "64")]
-    asm!(
-        "csrrw a0, mscratch, a0",
-        "sd a1, 0(a0)",
-        "sd a2, 8(a0)",
-        "sd a3, 16(a0)",
-        "ld a1, 24(a0)",
-        "ld a2, 32(a0)",
-        "ld a3, 0(a1)",
-        "add a3, a3, a2",
-        "sd a3, 0(a1)",
-        "li a1, 2",
-        "csrw sip, a1",
-        "ld a3, 16(a0)",
-        "ld a2, 8(a0)",
-        "ld a1, 0(a0)",
-        "csrrw a0, mscratch, a0",
-        "mret",
-    );
+
+const MACHINE_STACK_SIZE: usize = 0x2000;
+
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+struct MachineStack([u8; MACHINE_STACK_SIZE]);
+
+// the timer interrupt is taken in machine mode even while the kernel runs with interrupts disabled
+static mut MACHINE_STACKS: [MachineStack; watchdog::MAX_HARTS] =
+    [MachineStack([0; MACHINE_STACK_SIZE]); watchdog::MAX_HARTS];
+
+extern "C" {
+    pub fn timervec();
+}
+
+// caller saved registers of the interrupted code
+#[repr(C)]
+pub struct MachineFrame {
+    pub ra: usize, // 0
+    pub t0: usize, // 8
+    pub t1: usize, // 16
+    pub t2: usize, // 24
+    pub a0: usize, // 32
+    pub a1: usize, // 40
+    pub a2: usize, // 48
+    pub a3: usize, // 56
+    pub a4: usize, // 64
+    pub a5: usize, // 72
+    pub a6: usize, // 80
+    pub a7: usize, // 88
+    pub t3: usize, // 96
+    pub t4: usize, // 104
+    pub t5: usize, // 112
+    pub t6: usize, // 120
+}
+
+global_asm!(
+    ".globl timervec",
+    ".align 4",
+    "timervec:",
+    // mscratch holds the top of the machine mode stack of this hart
+    "  csrrw sp, mscratch, sp",
+    "  addi sp, sp, -128",
+    "  sd ra, 0(sp)",
+    "  sd t0, 8(sp)",
+    "  sd t1, 16(sp)",
+    "  sd t2, 24(sp)",
+    "  sd a0, 32(sp)",
+    "  sd a1, 40(sp)",
+    "  sd a2, 48(sp)",
+    "  sd a3, 56(sp)",
+    "  sd a4, 64(sp)",
+    "  sd a5, 72(sp)",
+    "  sd a6, 80(sp)",
+    "  sd a7, 88(sp)",
+    "  sd t3, 96(sp)",
+    "  sd t4, 104(sp)",
+    "  sd t5, 112(sp)",
+    "  sd t6, 120(sp)",
+    "  mv a0, sp",
+    "  call machine_timer",
+    "  ld ra, 0(sp)",
+    "  ld t0, 8(sp)",
+    "  ld t1, 16(sp)",
+    "  ld t2, 24(sp)",
+    "  ld a0, 32(sp)",
+    "  ld a1, 40(sp)",
+    "  ld a2, 48(sp)",
+    "  ld a3, 56(sp)",
+    "  ld a4, 64(sp)",
+    "  ld a5, 72(sp)",
+    "  ld a6, 80(sp)",
+    "  ld a7, 88(sp)",
+    "  ld t3, 96(sp)",
+    "  ld t4, 104(sp)",
+    "  ld t5, 112(sp)",
+    "  ld t6, 120(sp)",
+    "  addi sp, sp, 128",
+    "  csrrw sp, mscratch, sp",
+    "  mret",
+);
+
+fn mtimecmp(hart: usize) -> *mut usize {
+    (layout::_clint_start as usize + MTIMECMP + hart * 8) as *mut usize
+}
+
+fn mtime() -> *mut usize {
+    (layout::_clint_start as usize + MTIME) as *mut usize
+}
+
+// mtime when hart 0 started the kernel
+static mut BOOT_TIME: usize = 0;
+
+// time since reset, counted at TIMEBASE_FREQ
+pub fn now() -> usize {
+    unsafe { mtime().read_volatile() }
+}
+
+pub fn boot_time() -> usize {
+    unsafe { BOOT_TIME }
+}
+
+// time since boot, counted at TIMEBASE_FREQ
+pub fn uptime() -> usize {
+    now() - boot_time()
+}
+
+// runs in machine mode, the interrupted stack pointer is in mscratch
+#[no_mangle]
+pub unsafe extern "C" fn machine_timer(frame: &MachineFrame) {
+    let hart = Csr::Mhartid.read();
+    let mtimecmp = mtimecmp(hart);
+    mtimecmp.write_volatile(mtimecmp.read_volatile() + INTERVAL);
+
+    // the supervisor software interrupt drives the scheduler tick
+    Csr::Sip.write(Csr::Sip.read() | 1 << 1);
+
+    watchdog::check(hart, frame);
 }
 
 pub unsafe extern "C" fn init() {
-    let mtimecmp = (layout::_clint_start as usize + MTIMECMP) as *mut usize;
-    let mtime = (layout::_clint_start as usize + MTIME) as *mut usize;
-    *mtimecmp = *mtime + INTERVAL;
-    let scratch = TIMER_SCRATCH.as_mut_ptr();
-    // Save context
-    *(scratch.add(3)) = layout::_clint_start as usize + MTIMECMP;
-    *(scratch.add(4)) = INTERVAL;
-
-    Csr::Mscratch.write(scratch as usize);
+    let hart = Csr::Mhartid.read();
+    if hart == 0 {
+        BOOT_TIME = mtime().read_volatile();
+    }
+    mtimecmp(hart).write_volatile(mtime().read_volatile() + INTERVAL);
+
+    let stack = MACHINE_STACKS[hart].0.as_ptr() as usize;
+    Csr::Mscratch.write(stack + MACHINE_STACK_SIZE);
     Csr::Mtvec.write(timervec as usize);
 }
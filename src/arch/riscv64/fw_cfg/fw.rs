@@ -2,8 +2,14 @@ use super::super::layout;
 use crate::*;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 // see qemu/docs/specs/fw_cfg.txt
+// the selector and the numbers in the file directory are big endian, the data register reads
+// the item as a stream of bytes
+
+// the file with the kernel options, see boot_options
+const CMDLINE_FILE: &str = "opt/citron/cmdline";
 
 // Selector Register address: Base + 8 (2 bytes)
 // Data Register address:     Base + 0 (8 bytes)
@@ -68,7 +74,7 @@ impl FwCfg {
         unsafe {
             // (*self.regs).selector = selector;
             let select = (self.base_addr + 8) as *mut u16;
-            *select = selector;
+            *select = selector.to_be();
         }
     }
 
@@ -92,13 +98,12 @@ impl FwCfg {
     pub fn map_files(&mut self) -> BTreeMap<String, FwCfgFile> {
         // FW_CFG_FILE_DIR
         self.set_selector(0x0019);
-        let count = self.read_data32();
-        println!("{}", count);
+        let count = u32::from_be(self.read_data32());
 
         let mut map = BTreeMap::new();
         for _ in 0..count {
-            let size = self.read_data32();
-            let select = self.read_data16();
+            let size = u32::from_be(self.read_data32());
+            let select = u16::from_be(self.read_data16());
             let _reserved = self.read_data16();
 
             let mut name = String::new();
@@ -115,4 +120,23 @@ impl FwCfg {
 
         map
     }
+
+    pub fn read_file(&mut self, name: &str) -> Option<Vec<u8>> {
+        let file = self.map_files().remove(name)?;
+        self.set_selector(file.select);
+        Some((0..file.size).map(|_| self.read_data8()).collect())
+    }
+}
+
+// options given to qemu with -fw_cfg name=opt/citron/cmdline,string="<options>"
+pub fn boot_options() -> String {
+    let mut fw_cfg = FwCfg::new();
+    match fw_cfg.read_file(CMDLINE_FILE) {
+        Some(data) => data
+            .iter()
+            .take_while(|ch| **ch != 0)
+            .map(|ch| *ch as char)
+            .collect(),
+        None => String::new(),
+    }
 }
@@ -0,0 +1,371 @@
+use super::clint;
+use super::paging::EntryBits;
+use super::syscall::{user_path, RiscvSysCallInfo};
+use crate::arch::syscall::SysCallInfo;
+use crate::errno::Errno;
+use crate::fs::file_system;
+use crate::fs::FileTable;
+use crate::process::*;
+use crate::rlimit::*;
+use crate::uaccess::*;
+use crate::*;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use core::cell::RefCell;
+
+// Linux system calls
+// a program without the citron note (see arch::target::loader) makes system calls the Linux
+// way: the number in a7, the arguments in a0 to a5 and -errno on failure. a core subset is
+// implemented, enough for static musl programs:
+// - files are read only, descriptors 0 to 2 are the console, which has no input
+// - mmap makes anonymous private mappings only
+// - the real time clock counts from boot
+// - exit statuses are dropped, as with the native exit
+// everything else fails with ENOSYS.
+
+pub const SYS_OPENAT: usize = 56;
+pub const SYS_CLOSE: usize = 57;
+pub const SYS_LSEEK: usize = 62;
+pub const SYS_READ: usize = 63;
+pub const SYS_WRITE: usize = 64;
+pub const SYS_WRITEV: usize = 66;
+pub const SYS_FSTAT: usize = 80;
+pub const SYS_EXIT: usize = 93;
+pub const SYS_EXIT_GROUP: usize = 94;
+pub const SYS_SET_TID_ADDRESS: usize = 96; // made by musl at startup
+pub const SYS_CLOCK_GETTIME: usize = 113;
+pub const SYS_UNAME: usize = 160;
+pub const SYS_GETPID: usize = 172;
+pub const SYS_BRK: usize = 214;
+pub const SYS_MUNMAP: usize = 215;
+pub const SYS_MMAP: usize = 222;
+
+const AT_FDCWD: usize = -100_isize as usize;
+const O_ACCMODE: usize = 3;
+const O_CREAT: usize = 0o100;
+const O_TRUNC: usize = 0o1000;
+
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+const CLOCK_MONOTONIC_RAW: usize = 4;
+const CLOCK_REALTIME_COARSE: usize = 5;
+const CLOCK_MONOTONIC_COARSE: usize = 6;
+const CLOCK_BOOTTIME: usize = 7;
+
+const S_IFCHR: u32 = 0o020000;
+const S_IFREG: u32 = 0o100000;
+const STAT_SIZE: usize = 128; // struct stat of riscv64
+const UTSNAME_FIELD: usize = 65;
+const IOV_MAX: usize = 1024;
+
+// the Linux release whose system calls are followed, C libraries check it
+const UTS_RELEASE: &str = "5.15.0-citron";
+
+fn files(pm: &mut ProcessManager) -> Result<Rc<RefCell<FileTable>>, ProcessError> {
+    let running = pm.running;
+    Ok(get_process!(pm.ptable_lock(), running)?.files.clone())
+}
+
+fn is_console(fd: usize) -> bool {
+    fd <= 2
+}
+
+fn max_memory(pm: &mut ProcessManager) -> Result<usize, ProcessError> {
+    let running = pm.running;
+    Ok(get_process!(pm.ptable_lock(), running)?
+        .rlimits
+        .cur(RLIMIT_AS))
+}
+
+fn linux_read(
+    pm: &mut ProcessManager,
+    fd: usize,
+    buf: usize,
+    count: usize,
+) -> Result<usize, Errno> {
+    if is_console(fd) {
+        return Ok(0);
+    }
+    let files = files(pm)?;
+    let mut data = vec![0; count.min(MAX_RW_COUNT)];
+    let read = unsafe { file_system() }
+        .lock()
+        .read_in(&mut files.borrow_mut(), fd, &mut data)?;
+    copy_to_user(pm, buf, &data[..read])?;
+    Ok(read)
+}
+
+fn linux_write(
+    pm: &mut ProcessManager,
+    fd: usize,
+    buf: usize,
+    count: usize,
+) -> Result<usize, Errno> {
+    if !is_console(fd) {
+        return Err(Errno::EBADF);
+    }
+    let mut data = vec![0; count.min(MAX_RW_COUNT)];
+    copy_from_user(pm, buf, &mut data)?;
+    for ch in data.iter() {
+        print!("{}", *ch as char);
+    }
+    Ok(data.len())
+}
+
+fn linux_writev(
+    pm: &mut ProcessManager,
+    fd: usize,
+    iov: usize,
+    iovcnt: usize,
+) -> Result<usize, Errno> {
+    if iovcnt > IOV_MAX {
+        return Err(Errno::EINVAL);
+    }
+    let mut written = 0;
+    for index in 0..iovcnt {
+        // struct iovec { void *iov_base; size_t iov_len; }
+        let addr = iov.checked_add(index * 16).ok_or(Errno::EFAULT)?;
+        let [base, len]: [usize; 2] = get_user(pm, addr)?;
+        written += linux_write(pm, fd, base, len)?;
+        if written >= MAX_RW_COUNT {
+            break;
+        }
+    }
+    Ok(written)
+}
+
+fn linux_openat(
+    pm: &mut ProcessManager,
+    dirfd: usize,
+    path: usize,
+    flags: usize,
+) -> Result<usize, Errno> {
+    let path = user_path(pm, path)?;
+    if flags & O_ACCMODE != 0 || flags & (O_CREAT | O_TRUNC) != 0 {
+        return Err(Errno::EPERM);
+    }
+    // there are no directory descriptors, relative paths start at the root
+    let path = if path.starts_with('/') {
+        path
+    } else if dirfd == AT_FDCWD {
+        format!("/{}", path)
+    } else {
+        return Err(Errno::EBADF);
+    };
+
+    let files = files(pm)?;
+    let fd = unsafe { file_system() }
+        .lock()
+        .open_file_in(&mut files.borrow_mut(), &path)?;
+    Ok(fd)
+}
+
+fn linux_close(pm: &mut ProcessManager, fd: usize) -> Result<usize, Errno> {
+    if is_console(fd) {
+        return Ok(0);
+    }
+    files(pm)?.borrow_mut().close(fd)?;
+    Ok(0)
+}
+
+fn linux_lseek(
+    pm: &mut ProcessManager,
+    fd: usize,
+    offset: usize,
+    whence: usize,
+) -> Result<usize, Errno> {
+    if is_console(fd) {
+        return Err(Errno::EINVAL);
+    }
+    let offset = files(pm)?
+        .borrow_mut()
+        .seek(fd, offset as isize, whence as u32)?;
+    Ok(offset)
+}
+
+fn linux_fstat(pm: &mut ProcessManager, fd: usize, statbuf: usize) -> Result<usize, Errno> {
+    let (mode, size) = if is_console(fd) {
+        (S_IFCHR | 0o620, 0)
+    } else {
+        let size = files(pm)?.borrow().get_file_size(fd)?;
+        (S_IFREG | 0o444, size)
+    };
+
+    let mut stat = [0; STAT_SIZE];
+    stat[16..20].copy_from_slice(&mode.to_le_bytes()); // st_mode
+    stat[20..24].copy_from_slice(&1_u32.to_le_bytes()); // st_nlink
+    stat[48..56].copy_from_slice(&(size as u64).to_le_bytes()); // st_size
+    stat[56..60].copy_from_slice(&512_u32.to_le_bytes()); // st_blksize
+    stat[64..72].copy_from_slice(&((size as u64 + 511) / 512).to_le_bytes()); // st_blocks
+    copy_to_user(pm, statbuf, &stat)?;
+    Ok(0)
+}
+
+fn linux_exit(pm: &mut ProcessManager) -> Result<usize, Errno> {
+    let running = pm.running;
+    pm.kill(running)?;
+    Ok(0)
+}
+
+fn linux_exit_group(pm: &mut ProcessManager) -> Result<usize, Errno> {
+    let running = pm.running;
+    pm.kill_group(running)?;
+    Ok(0)
+}
+
+fn linux_clock_gettime(pm: &mut ProcessManager, clock: usize, tp: usize) -> Result<usize, Errno> {
+    let (sec, nsec) = match clock {
+        CLOCK_REALTIME
+        | CLOCK_MONOTONIC
+        | CLOCK_MONOTONIC_RAW
+        | CLOCK_REALTIME_COARSE
+        | CLOCK_MONOTONIC_COARSE
+        | CLOCK_BOOTTIME => {
+            let now = clint::uptime();
+            let sec = now / clint::TIMEBASE_FREQ;
+            let nsec = now % clint::TIMEBASE_FREQ * (1_000_000_000 / clint::TIMEBASE_FREQ);
+            (sec, nsec)
+        }
+        CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {
+            let running = pm.running;
+            let ticks = match clock {
+                CLOCK_PROCESS_CPUTIME_ID => pm.get_resource_usage(running)?.cpu,
+                _ => get_process!(pm.ptable_lock(), running)?.cpu_time,
+            };
+            let sec = ticks / clint::TICKS_PER_SEC;
+            let nsec = ticks % clint::TICKS_PER_SEC * (1_000_000_000 / clint::TICKS_PER_SEC);
+            (sec, nsec)
+        }
+        _ => return Err(Errno::EINVAL),
+    };
+
+    // struct timespec { time_t tv_sec; long tv_nsec; }
+    let mut timespec = [0; 16];
+    timespec[..8].copy_from_slice(&(sec as u64).to_le_bytes());
+    timespec[8..].copy_from_slice(&(nsec as u64).to_le_bytes());
+    copy_to_user(pm, tp, &timespec)?;
+    Ok(0)
+}
+
+fn linux_uname(pm: &mut ProcessManager, buf: usize) -> Result<usize, Errno> {
+    // sysname, nodename, release, version, machine and domainname
+    let fields = ["citron", "citron", UTS_RELEASE, "#1", "riscv64", "(none)"];
+    let mut utsname = [0; UTSNAME_FIELD * 6];
+    for (index, field) in fields.iter().enumerate() {
+        let start = index * UTSNAME_FIELD;
+        utsname[start..(start + field.len())].copy_from_slice(field.as_bytes());
+    }
+    copy_to_user(pm, buf, &utsname)?;
+    Ok(0)
+}
+
+fn linux_getpid(pm: &mut ProcessManager) -> Result<usize, Errno> {
+    let running = pm.running;
+    Ok(get_process!(pm.ptable_lock(), running)?.tgid)
+}
+
+// the heap doesn't move on failure, which returns its end as well
+fn linux_brk(pm: &mut ProcessManager, brk: usize) -> Result<usize, Errno> {
+    let max_memory = max_memory(pm)?;
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let mut addr_space = get_process!(ptable, running)?
+        .arch_proc
+        .addr_space()
+        .borrow_mut();
+    Ok(addr_space.set_brk(brk, max_memory))
+}
+
+fn linux_mmap(
+    pm: &mut ProcessManager,
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+) -> Result<usize, Errno> {
+    if flags & MAP_ANONYMOUS == 0 || flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE {
+        return Err(Errno::ENODEV);
+    }
+    if len == 0 || (flags & MAP_FIXED != 0 && (addr == 0 || addr % 0x1000 != 0)) {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut entry_flags = EntryBits::U.val();
+    if prot & (PROT_READ | PROT_WRITE) != 0 {
+        entry_flags |= EntryBits::R.val();
+    }
+    if prot & PROT_WRITE != 0 {
+        entry_flags |= EntryBits::W.val();
+    }
+    if prot & PROT_EXEC != 0 {
+        entry_flags |= EntryBits::X.val();
+    }
+
+    let max_memory = max_memory(pm)?;
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let mut addr_space = get_process!(ptable, running)?
+        .arch_proc
+        .addr_space()
+        .borrow_mut();
+    // a fixed mapping replaces the mappings under it, the address is only a hint otherwise
+    if flags & MAP_FIXED != 0 {
+        Ok(addr_space.map_anonymous_fixed(addr, len, entry_flags, max_memory)?)
+    } else {
+        Ok(addr_space.map_anonymous(0, len, entry_flags, max_memory)?)
+    }
+}
+
+fn linux_munmap(pm: &mut ProcessManager, addr: usize, len: usize) -> Result<usize, Errno> {
+    if len == 0 || addr % 0x1000 != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    get_process!(ptable, running)?
+        .arch_proc
+        .addr_space()
+        .borrow_mut()
+        .unmap_anonymous(addr, len);
+    Ok(0)
+}
+
+pub fn dispatch(pm: &mut ProcessManager, info: &RiscvSysCallInfo) -> usize {
+    let number = info.get_arg_raw(7);
+    let arg = |idx: usize| info.get_arg_raw(idx);
+    let result = match number {
+        SYS_OPENAT => linux_openat(pm, arg(0), arg(1), arg(2)),
+        SYS_CLOSE => linux_close(pm, arg(0)),
+        SYS_LSEEK => linux_lseek(pm, arg(0), arg(1), arg(2)),
+        SYS_READ => linux_read(pm, arg(0), arg(1), arg(2)),
+        SYS_WRITE => linux_write(pm, arg(0), arg(1), arg(2)),
+        SYS_WRITEV => linux_writev(pm, arg(0), arg(1), arg(2)),
+        SYS_FSTAT => linux_fstat(pm, arg(0), arg(1)),
+        SYS_EXIT => linux_exit(pm),
+        SYS_EXIT_GROUP => linux_exit_group(pm),
+        SYS_SET_TID_ADDRESS => Ok(pm.running),
+        SYS_CLOCK_GETTIME => linux_clock_gettime(pm, arg(0), arg(1)),
+        SYS_UNAME => linux_uname(pm, arg(0)),
+        SYS_GETPID => linux_getpid(pm),
+        SYS_BRK => linux_brk(pm, arg(0)),
+        SYS_MUNMAP => linux_munmap(pm, arg(0), arg(1)),
+        SYS_MMAP => linux_mmap(pm, arg(0), arg(1), arg(2), arg(3)),
+        _ => Err(Errno::ENOSYS),
+    };
+    match result {
+        Ok(ret) => ret,
+        Err(err) => err.ret(),
+    }
+}
@@ -1,13 +1,69 @@
 use super::paging::*;
+use super::trampoline::KERNEL_MAPPED_START;
+use crate::aslr;
 use crate::fs::*;
 use crate::*;
-use alloc::alloc::alloc_zeroed;
+use alloc::collections::BTreeMap;
 use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::ops::Range;
-use core::slice;
-use goblin::Object;
+use goblin::elf::header::{EM_RISCV, ET_DYN, ET_EXEC};
+use goblin::elf::program_header::{PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE, PT_PHDR};
+use goblin::elf::Elf;
+
+// program loading
+// only PT_LOAD segments are loaded, as VM areas with the permissions of the segment: text R|X,
+// rodata R and data R|W. nothing is mapped up front, a page is filled from the file contents
+// kept with its area when it is first touched (see AddressSpace::fault). the part of a segment
+// past its file contents (bss) is zero filled, and a page shared by two segments gets the
+// permissions of both.
+// segments must lie below the kernel mapped pages, so they can't reach the user stack or its
+// guard page either.
+// a position independent program (ET_DYN) is moved to a random page above PIE_BASE and its
+// R_RISCV_RELATIVE relocations are applied; other relocations need a dynamic linker.
+// a program with PT_INTERP is loaded as it is, together with the interpreter it names at a
+// random page above INTERP_BASE. the process starts in the interpreter, which finds the program
+// through the auxiliary vector (AT_PHDR, AT_ENTRY, AT_BASE) and relocates it.
+// a program with a "citron" note (NT_CITRON_ABI) makes the system calls of this kernel, any
+// other one runs with the Linux system call numbers, see arch::target::linux.
+
+const PAGE_SIZE: usize = 0x1000;
+const PHENT_SIZE: usize = 56;
+
+pub const PIE_BASE: usize = 0x2a_aaaa_a000;
+pub const INTERP_BASE: usize = 0x30_0000_0000;
+pub const PIE_RANDOM_PAGES: usize = 0x1_0000;
+
+const R_RISCV_RELATIVE: u32 = 3;
+
+// AT_HWCAP has bit (c - 'a') for each extension letter c, the kernel runs on rv64imafdc
+const HWCAP: usize = 1 | (1 << 2) | (1 << 3) | (1 << 5) | (1 << 8) | (1 << 12); // a c d f i m
+
+// the note which marks a program for the native system calls, the bin/ programs have it
+pub const NOTE_CITRON: &str = "citron";
+pub const NT_CITRON_ABI: u32 = 1;
+
+// system calls a program makes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Abi {
+    Native,
+    Linux,
+}
+
+// keys of the auxiliary vector
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_HWCAP: usize = 16;
+pub const AT_RANDOM: usize = 25;
 
 type EntryPoint = usize;
 
@@ -30,96 +86,537 @@ impl Segment {
     }
 }
 
+// the file contents of a program or an interpreter, relocated, which its pages are filled from
+// they are kept until every area of the image has its pages with contents mapped
+pub struct ImageContents {
+    segments: Vec<(usize, Vec<u8>)>, // where the file contents of each segment go
+    relocations: BTreeMap<usize, usize>, // the words written over them
+}
+
+impl ImageContents {
+    // whether the page at `vaddr` has anything but zeros
+    pub fn has_contents(&self, vaddr: usize) -> bool {
+        let end = vaddr + PAGE_SIZE;
+        self.segments
+            .iter()
+            .any(|(start, bytes)| *start < end && vaddr < start + bytes.len())
+            || self
+                .relocations
+                .range(vaddr.saturating_sub(7)..end)
+                .next()
+                .is_some()
+    }
+
+    // write the contents of the page at `vaddr` to `page`, which is zeroed
+    pub unsafe fn fill(&self, vaddr: usize, page: *mut u8) {
+        let end = vaddr + PAGE_SIZE;
+        for (start, bytes) in self.segments.iter() {
+            let copy_start = vaddr.max(*start);
+            let copy_end = end.min(start + bytes.len());
+            if copy_start < copy_end {
+                let src = &bytes[(copy_start - start)..(copy_end - start)];
+                page.add(copy_start - vaddr)
+                    .copy_from_nonoverlapping(src.as_ptr(), src.len());
+            }
+        }
+        // a word may cross into the page from the one before
+        for (addr, value) in self.relocations.range(vaddr.saturating_sub(7)..end) {
+            for (index, byte) in value.to_le_bytes().iter().enumerate() {
+                let addr = addr + index;
+                if addr >= vaddr && addr < end {
+                    page.add(addr - vaddr).write(*byte);
+                }
+            }
+        }
+    }
+}
+
+// where the pages of a VM area come from
+#[derive(Clone)]
+pub enum Backing {
+    Zero,                     // zero filled: the heap, the stack and anonymous mappings
+    Image(Rc<ImageContents>), // a program or an interpreter
+}
+
+// a range of user memory, its pages are mapped when they are first touched
+#[derive(Clone)]
+pub struct VmArea {
+    pub range: Range<usize>, // page aligned
+    pub flags: usize,        // EntryBits of its pages
+    pub backing: Backing,
+}
+
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct ExecutableInfo {
-    pub entry: EntryPoint,
-    pub segment_buffers: Vec<Segment>,
+    pub entry: EntryPoint, // where the process starts, in the interpreter if there is one
+    pub bias: usize, // added to the addresses of the program, 0 unless it is position independent
+    pub program_entry: EntryPoint,
+    pub phdr: usize, // where the program headers are, 0 if they aren't loaded
+    pub phnum: usize,
+    pub interp_base: usize, // 0 without an interpreter
+    pub program_end: usize, // end of the last segment of the program, the heap goes past it
+    pub abi: Abi,
+    pub segment_buffers: Vec<Segment>, // the pages of the address space mapped so far
 }
 
 impl ExecutableInfo {
     pub fn new() -> Self {
         ExecutableInfo {
             entry: 0,
+            bias: 0,
+            program_entry: 0,
+            phdr: 0,
+            phnum: 0,
+            interp_base: 0,
+            program_end: 0,
+            abi: Abi::Native,
             segment_buffers: Vec::new(),
         }
     }
+
+    // the auxiliary vector without AT_RANDOM, which points into the stack, and AT_NULL
+    pub fn auxv(&self) -> Vec<(usize, usize)> {
+        vec![
+            (AT_PHDR, self.phdr),
+            (AT_PHENT, PHENT_SIZE),
+            (AT_PHNUM, self.phnum),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_HWCAP, HWCAP),
+            (AT_BASE, self.interp_base),
+            (AT_ENTRY, self.program_entry),
+        ]
+    }
 }
 
-pub fn load_exe(path: &str, page_table: &mut Table) -> Result<ExecutableInfo, Error> {
-    let fs = unsafe { file_system() };
-    let fd = fs.lock().open_file(path)?;
-    let size = fs.lock().get_file_size(fd)?;
-
-    let layout = Layout::from_size_align(size, 0x1000).unwrap();
-    let bin_data = unsafe { alloc_zeroed(layout) };
-    let bin_slice = unsafe { slice::from_raw_parts_mut(bin_data, size) };
-    fs.lock().read(fd, bin_slice)?;
-
-    let obj = Object::parse(bin_slice).unwrap();
-    let elf = match obj {
-        Object::Elf(elf) => elf,
-        _ => return Err(Error::Msg(format!("{} is not an elf file", path))),
+// a PT_LOAD segment which passed the checks
+struct LoadSegment {
+    vm_range: Range<usize>, // the tail past the file contents is bss
+    file_range: Range<usize>,
+    flags: usize, // EntryBits
+}
+
+fn bad_exe(msg: String) -> Error {
+    Error::BadExecutable(msg)
+}
+
+// a writable page has to be readable as well
+fn entry_flags(p_flags: u32) -> usize {
+    let mut flags = EntryBits::U.val();
+    if p_flags & (PF_R | PF_W) != 0 {
+        flags |= EntryBits::R.val();
+    }
+    if p_flags & PF_W != 0 {
+        flags |= EntryBits::W.val();
+    }
+    if p_flags & PF_X != 0 {
+        flags |= EntryBits::X.val();
+    }
+    flags
+}
+
+fn check_header(elf: &Elf) -> Result<(), Error> {
+    if !elf.is_64 || !elf.little_endian {
+        return Err(bad_exe(String::from("not a 64 bit little endian program")));
+    }
+    if elf.header.e_machine != EM_RISCV {
+        return Err(bad_exe(format!(
+            "built for machine {}, not RISC-V",
+            elf.header.e_machine
+        )));
+    }
+    if elf.header.e_type != ET_EXEC && elf.header.e_type != ET_DYN {
+        return Err(bad_exe(format!(
+            "elf type {} is not an executable",
+            elf.header.e_type
+        )));
+    }
+    Ok(())
+}
+
+// the PT_LOAD segments of `elf` sorted by address, `size` is the length of the file
+fn check_segments(elf: &Elf, size: usize) -> Result<Vec<LoadSegment>, Error> {
+    let mut segments = Vec::new();
+    for ph in elf.program_headers.iter().filter(|ph| ph.p_type == PT_LOAD) {
+        if ph.p_memsz == 0 {
+            continue;
+        }
+        if ph.p_filesz > ph.p_memsz {
+            return Err(bad_exe(format!(
+                "segment at {:#x} has more file contents than memory",
+                ph.p_vaddr
+            )));
+        }
+        let file_end = ph
+            .p_offset
+            .checked_add(ph.p_filesz)
+            .filter(|end| *end <= size as u64)
+            .ok_or_else(|| {
+                bad_exe(format!(
+                    "segment at {:#x} ends past the end of the file",
+                    ph.p_vaddr
+                ))
+            })?;
+        if ph.p_align > 1
+            && (!ph.p_align.is_power_of_two()
+                || ph.p_vaddr % ph.p_align != ph.p_offset % ph.p_align)
+        {
+            return Err(bad_exe(format!(
+                "segment at {:#x} is misaligned",
+                ph.p_vaddr
+            )));
+        }
+        let vm_end = ph.p_vaddr.checked_add(ph.p_memsz).ok_or_else(|| {
+            bad_exe(format!(
+                "segment at {:#x} is outside of user memory",
+                ph.p_vaddr
+            ))
+        })?;
+        if ph.p_flags & (PF_R | PF_W | PF_X) == 0 {
+            return Err(bad_exe(format!(
+                "segment at {:#x} has no permissions",
+                ph.p_vaddr
+            )));
+        }
+
+        segments.push(LoadSegment {
+            vm_range: (ph.p_vaddr as usize)..(vm_end as usize),
+            file_range: (ph.p_offset as usize)..(file_end as usize),
+            flags: entry_flags(ph.p_flags),
+        });
+    }
+
+    if segments.is_empty() {
+        return Err(bad_exe(String::from("no loadable segment")));
+    }
+    segments.sort_by_key(|segment| segment.vm_range.start);
+    for pair in segments.windows(2) {
+        if pair[0].vm_range.end > pair[1].vm_range.start {
+            return Err(bad_exe(format!(
+                "segments at {:#x} and {:#x} overlap",
+                pair[0].vm_range.start, pair[1].vm_range.start
+            )));
+        }
+    }
+
+    Ok(segments)
+}
+
+// move `segments` by `bias` and check that they are in user memory
+fn place_segments(segments: &mut [LoadSegment], bias: usize) -> Result<(), Error> {
+    for segment in segments.iter_mut() {
+        let start = segment.vm_range.start.wrapping_add(bias);
+        let end = segment.vm_range.end.wrapping_add(bias);
+        if end < start || end > KERNEL_MAPPED_START {
+            return Err(bad_exe(format!(
+                "segment at {:#x} is outside of user memory",
+                segment.vm_range.start
+            )));
+        }
+        segment.vm_range = start..end;
+    }
+    Ok(())
+}
+
+// which relocations the kernel applies
+#[derive(Copy, Clone, PartialEq)]
+enum Relocation {
+    All,      // a program without interpreter, which can only have R_RISCV_RELATIVE relocations
+    Relative, // an interpreter, which does the rest itself
+    Skip,     // a program with an interpreter, which relocates it
+}
+
+// relative relocations have to be words inside the program
+fn check_relocations(
+    elf: &Elf,
+    segments: &[LoadSegment],
+    bias: usize,
+    relocation: Relocation,
+) -> Result<(), Error> {
+    let strict = relocation == Relocation::All;
+    if strict && (!elf.dynrels.is_empty() || !elf.pltrelocs.is_empty()) {
+        return Err(bad_exe(String::from("needs a dynamic linker")));
+    }
+    for reloc in elf.dynrelas.iter() {
+        if reloc.r_type != R_RISCV_RELATIVE {
+            if strict {
+                return Err(bad_exe(format!(
+                    "unsupported relocation type {}",
+                    reloc.r_type
+                )));
+            }
+            continue;
+        }
+        let vaddr = (reloc.r_offset as usize).wrapping_add(bias);
+        let inside = segments.iter().any(|segment| {
+            vaddr >= segment.vm_range.start && vaddr.saturating_add(8) <= segment.vm_range.end
+        });
+        if !inside {
+            return Err(bad_exe(format!(
+                "relocation at {:#x} is outside of the program",
+                reloc.r_offset
+            )));
+        }
+    }
+    Ok(())
+}
+
+// whether the notes in `notes` have one named `name` of type `n_type`
+fn has_note(notes: &[u8], name: &str, n_type: u32) -> bool {
+    let word = |offset: usize| {
+        notes
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
     };
+    let align = |size: usize| (size + 3) & !3;
+    let mut offset = 0;
+    // namesz, descsz and type, then the name and the desc padded to 4 bytes
+    while let (Some(namesz), Some(descsz), Some(note_type)) =
+        (word(offset), word(offset + 4), word(offset + 8))
+    {
+        let name_start = offset + 12;
+        let note_name = match notes.get(name_start..name_start + namesz) {
+            Some(note_name) => note_name,
+            None => return false,
+        };
+        if note_type == n_type as usize && note_name.strip_suffix(&[0]) == Some(name.as_bytes()) {
+            return true;
+        }
+        offset = name_start + align(namesz) + align(descsz);
+    }
+    false
+}
 
-    let mut segment_buffers = Vec::new();
-
-    for ph in elf.program_headers.iter() {
-        let vm_range = ph.vm_range();
-        let layout = Layout::from_size_align(vm_range.len(), 0x1000).unwrap();
-        let buffer = unsafe { alloc_zeroed(layout) };
-        let flags =
-            EntryBits::R.val() | EntryBits::W.val() | EntryBits::X.val() | EntryBits::U.val();
-        map_range(
-            page_table,
-            vm_range.start,
-            buffer as usize,
-            vm_range.len(),
-            flags,
-        );
-
-        let range = ph.file_range();
-        unsafe {
-            buffer
-                .copy_from_nonoverlapping(bin_slice[range.start..range.end].as_ptr(), range.len());
-        }
-
-        segment_buffers.push(Segment::new(buffer, layout, vm_range, flags));
-    }
-
-    // for section in elf.section_headers.iter() {
-    //     let vm_range = section.vm_range();
-    //     let layout = Layout::from_size_align(vm_range.len(), 0x1000).unwrap();
-    //     let mut buffer = unsafe { alloc_zeroed(layout) };
-    //     println!("{:#018x}", vm_range.start);
-    //     map_range(
-    //         page_table,
-    //         vm_range.start,
-    //         buffer as usize,
-    //         vm_range.len() as usize,
-    //         EntryBits::R.val() | EntryBits::W.val() | EntryBits::X.val() | EntryBits::U.val(),
-    //     );
-
-    //     if let Some(range) = section.file_range() {
-    //         unsafe {
-    //             println!("addr: {:#018x}", vm_range.start as usize);
-    //             println!("range.start: {:02x}", bin_slice[range.start]);
-    //             buffer
-    //                 .add(vm_range.start as usize - (vm_range.start as usize & !0x0fff_usize))
-    //                 .copy_from_nonoverlapping(
-    //                     bin_slice[range.start..range.end].as_ptr(),
-    //                     range.len(),
-    //                 );
-    //         }
-    //     }
-
-    //     section_buffers.push((buffer, layout));
-    // }
-
-    let exec_info = ExecutableInfo {
-        entry: elf.header.e_entry as usize,
-        segment_buffers,
+// a program or an interpreter which passed the checks, placed at its addresses
+struct Image<'a> {
+    elf: Elf<'a>,
+    data: &'a [u8],
+    bias: usize,
+    segments: Vec<LoadSegment>,
+    relocation: Relocation,
+}
+
+impl<'a> Image<'a> {
+    // a position independent image goes to a random page above `base`
+    fn parse(data: &'a [u8], base: usize, is_interp: bool) -> Result<Self, Error> {
+        let elf = Elf::parse(data).map_err(|err| bad_exe(format!("{}", err)))?;
+        check_header(&elf)?;
+        let relocation = match (is_interp, elf.interpreter) {
+            (true, Some(_)) => {
+                return Err(bad_exe(String::from(
+                    "the interpreter asks for an interpreter",
+                )))
+            }
+            (true, None) => Relocation::Relative,
+            (false, Some(_)) => Relocation::Skip,
+            (false, None) => Relocation::All,
+        };
+
+        let mut segments = check_segments(&elf, data.len())?;
+        let bias = if elf.header.e_type == ET_DYN {
+            let base = base + aslr::random_offset(PIE_RANDOM_PAGES);
+            base.wrapping_sub(segments[0].vm_range.start & !(PAGE_SIZE - 1))
+        } else {
+            0
+        };
+        place_segments(&mut segments, bias)?;
+        check_relocations(&elf, &segments, bias, relocation)?;
+
+        let image = Image {
+            elf,
+            data,
+            bias,
+            segments,
+            relocation,
+        };
+        let entry = image.entry();
+        let executable = image.segments.iter().any(|segment| {
+            segment.vm_range.contains(&entry) && segment.flags & EntryBits::X.val() != 0
+        });
+        if !executable {
+            return Err(bad_exe(format!(
+                "entry point {:#x} is not in an executable segment",
+                image.elf.header.e_entry
+            )));
+        }
+        Ok(image)
+    }
+
+    fn entry(&self) -> usize {
+        (self.elf.header.e_entry as usize).wrapping_add(self.bias)
+    }
+
+    // PT_PHDR, or else the segment loading the program headers
+    fn phdr(&self) -> usize {
+        if let Some(ph) = self
+            .elf
+            .program_headers
+            .iter()
+            .find(|ph| ph.p_type == PT_PHDR)
+        {
+            return (ph.p_vaddr as usize).wrapping_add(self.bias);
+        }
+        let start = self.elf.header.e_phoff as usize;
+        let end = start + self.elf.header.e_phnum as usize * PHENT_SIZE;
+        self.segments
+            .iter()
+            .find(|segment| segment.file_range.start <= start && end <= segment.file_range.end)
+            .map_or(0, |segment| {
+                segment.vm_range.start + (start - segment.file_range.start)
+            })
+    }
+
+    // Native if a PT_NOTE has the citron note
+    fn abi(&self) -> Abi {
+        let native = self
+            .elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_NOTE)
+            .filter_map(|ph| {
+                let start = ph.p_offset as usize;
+                self.data
+                    .get(start..start.checked_add(ph.p_filesz as usize)?)
+            })
+            .any(|notes| has_note(notes, NOTE_CITRON, NT_CITRON_ABI));
+        if native {
+            Abi::Native
+        } else {
+            Abi::Linux
+        }
+    }
+
+    // the file contents of the segments and the relative relocations
+    fn contents(&self) -> ImageContents {
+        let segments = self
+            .segments
+            .iter()
+            .filter(|segment| !segment.file_range.is_empty())
+            .map(|segment| {
+                let bytes = self.data[segment.file_range.clone()].to_vec();
+                (segment.vm_range.start, bytes)
+            })
+            .collect();
+        let mut relocations = BTreeMap::new();
+        if self.relocation != Relocation::Skip {
+            for reloc in self.elf.dynrelas.iter() {
+                if reloc.r_type != R_RISCV_RELATIVE {
+                    continue;
+                }
+                let vaddr = (reloc.r_offset as usize).wrapping_add(self.bias);
+                let value = (reloc.r_addend.unwrap_or(0) as usize).wrapping_add(self.bias);
+                relocations.insert(vaddr, value);
+            }
+        }
+        ImageContents {
+            segments,
+            relocations,
+        }
+    }
+
+    // the areas of the segments, a page shared by two segments gets an area of its own
+    fn areas(&self) -> Vec<VmArea> {
+        let backing = Backing::Image(Rc::new(self.contents()));
+        let mut areas: Vec<VmArea> = Vec::new();
+        for segment in self.segments.iter() {
+            let mut start = segment.vm_range.start & !(PAGE_SIZE - 1);
+            let end = (segment.vm_range.end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+            if let Some(last) = areas.last_mut() {
+                if last.range.end > start {
+                    if last.range.len() == PAGE_SIZE {
+                        last.flags |= segment.flags;
+                    } else {
+                        last.range.end = start;
+                        let flags = last.flags | segment.flags;
+                        areas.push(VmArea {
+                            range: start..(start + PAGE_SIZE),
+                            flags,
+                            backing: backing.clone(),
+                        });
+                    }
+                    start += PAGE_SIZE;
+                }
+            }
+            if start < end {
+                areas.push(VmArea {
+                    range: start..end,
+                    flags: segment.flags,
+                    backing: backing.clone(),
+                });
+            }
+        }
+        areas
+    }
+}
+
+// add the areas of the program in `data`, and of the interpreter in `interp` if the program asks
+// for one, to `areas`
+// nothing is added unless both are valid and apart, fails with Error::MemoryLimit if the
+// segments take more than `max_memory` bytes
+pub fn load_elf(
+    data: &[u8],
+    interp: Option<&[u8]>,
+    areas: &mut Vec<VmArea>,
+    max_memory: usize,
+) -> Result<ExecutableInfo, Error> {
+    let program = Image::parse(data, PIE_BASE, false)?;
+    let interp = match (program.elf.interpreter, interp) {
+        (Some(_), Some(interp)) => Some(Image::parse(interp, INTERP_BASE, true)?),
+        (Some(path), None) => return Err(bad_exe(format!("needs the interpreter {}", path))),
+        (None, _) => None,
+    };
+
+    let mut new_areas = program.areas();
+    if let Some(interp) = interp.as_ref() {
+        let interp_areas = interp.areas();
+        let overlap = new_areas.iter().any(|area| {
+            interp_areas.iter().any(|other| {
+                area.range.start < other.range.end && other.range.start < area.range.end
+            })
+        });
+        if overlap {
+            return Err(bad_exe(String::from(
+                "the interpreter overlaps the program",
+            )));
+        }
+        new_areas.extend(interp_areas);
+    }
+    let size = new_areas.iter().map(|area| area.range.len()).sum::<usize>();
+    if size > max_memory {
+        return Err(Error::MemoryLimit);
+    }
+    areas.extend(new_areas);
+
+    Ok(ExecutableInfo {
+        entry: interp
+            .as_ref()
+            .map_or(program.entry(), |interp| interp.entry()),
+        bias: program.bias,
+        program_entry: program.entry(),
+        phdr: program.phdr(),
+        phnum: program.elf.header.e_phnum as usize,
+        interp_base: interp.as_ref().map_or(0, |interp| interp.bias),
+        program_end: program.segments.last().unwrap().vm_range.end,
+        abi: program.abi(),
+        segment_buffers: Vec::new(),
+    })
+}
+
+pub fn load_exe(
+    path: &str,
+    areas: &mut Vec<VmArea>,
+    max_memory: usize,
+) -> Result<ExecutableInfo, Error> {
+    let fs = unsafe { file_system() };
+    let data = fs.lock().read_file(path)?;
+    // the interpreter named by PT_INTERP, errors in the program are reported by load_elf
+    let interp_path = Elf::parse(&data)
+        .ok()
+        .and_then(|elf| elf.interpreter.map(String::from));
+    let interp = match interp_path {
+        Some(interp_path) => Some(fs.lock().read_file(&interp_path)?),
+        None => None,
     };
 
-    Ok(exec_info)
+    load_elf(&data, interp.as_deref(), areas, max_memory)
 }
@@ -160,6 +160,11 @@ impl Entry {
     pub fn get_flags(&self) -> usize {
         self.entry & 0x3ff
     }
+
+    // physical address of the next level table or the page
+    pub fn get_addr(&self) -> usize {
+        (self.entry & !0x3ff) << 2
+    }
 }
 
 #[cfg(target_pointer_width = "32")]
@@ -313,6 +318,11 @@ pub fn unmap(root: &mut Table) {
 }
 
 pub fn virt_to_phys(root: &Table, vaddr: usize) -> Option<usize> {
+    translate(root, vaddr).map(|(paddr, _)| paddr)
+}
+
+// physical address of `vaddr` and the flags of the leaf entry mapping it
+pub fn translate(root: &Table, vaddr: usize) -> Option<(usize, usize)> {
     #[cfg(target_pointer_width = "32")]
     let vpn = [(vaddr >> 12) & 0x3ff, (vaddr >> 22) & 0x3ff];
     #[cfg(target_pointer_width = "64")]
@@ -337,7 +347,7 @@ pub fn virt_to_phys(root: &Table, vaddr: usize) -> Option<usize> {
             let off_mask: usize = (1 << (12 + i * 9)) - 1;
             let vaddr_pgoff = vaddr & off_mask;
             let addr = ((v.get_entry() << 2) as usize) & !off_mask;
-            return Some(addr | vaddr_pgoff);
+            return Some((addr | vaddr_pgoff, v.get_flags()));
         }
 
         let entry = ((v.get_entry() & !0x3ff) << 2) as *const Entry;
@@ -347,6 +357,66 @@ pub fn virt_to_phys(root: &Table, vaddr: usize) -> Option<usize> {
     None
 }
 
+// remove the mapping of a single page, the page itself isn't freed
+pub fn unmap_page(root: &mut Table, vaddr: usize) {
+    #[cfg(target_pointer_width = "32")]
+    let vpn = [(vaddr >> 12) & 0x3ff, (vaddr >> 22) & 0x3ff];
+    #[cfg(target_pointer_width = "64")]
+    let vpn = [
+        (vaddr >> 12) & 0x1ff,
+        (vaddr >> 21) & 0x1ff,
+        (vaddr >> 30) & 0x1ff,
+    ];
+
+    let mut v = &mut root.entries[vpn[LEVELS - 1]];
+    for i in (0..LEVELS).rev() {
+        if v.is_invalid() {
+            return;
+        } else if v.is_leaf() {
+            v.set_entry(0);
+            return;
+        } else if i == 0 {
+            return;
+        }
+
+        let entry = v.get_addr() as *mut Entry;
+        v = unsafe { entry.add(vpn[i - 1]).as_mut().unwrap() };
+    }
+}
+
+// call `f` with the virtual address and the entry of every leaf in `root`
+pub fn walk<F: FnMut(usize, &Entry)>(root: &Table, mut f: F) {
+    walk_table(root, LEVELS - 1, 0, &mut f);
+}
+
+fn walk_table<F: FnMut(usize, &Entry)>(table: &Table, level: usize, base: usize, f: &mut F) {
+    #[cfg(target_pointer_width = "32")]
+    let vpn_bits = 10;
+    #[cfg(target_pointer_width = "64")]
+    let vpn_bits = 9;
+
+    for (i, entry) in table.entries.iter().enumerate() {
+        if entry.is_invalid() {
+            continue;
+        }
+
+        let vaddr = base | (i << (12 + level * vpn_bits));
+        if entry.is_leaf() {
+            // Sv39 addresses are sign-extended from bit 38
+            #[cfg(target_pointer_width = "64")]
+            let vaddr = if vaddr & (1 << 38) != 0 {
+                vaddr | !((1 << 39) - 1)
+            } else {
+                vaddr
+            };
+            f(vaddr, entry);
+        } else if level > 0 {
+            let next = unsafe { (entry.get_addr() as *const Table).as_ref().unwrap() };
+            walk_table(next, level - 1, vaddr, f);
+        }
+    }
+}
+
 fn align_val(val: usize, align: usize) -> usize {
     let t = (1usize << align) - 1;
     (val + t) & !t
@@ -1,3 +1,4 @@
+use super::clint;
 use super::csr::Csr;
 use super::interrupt;
 use super::interrupt::*;
@@ -5,23 +6,44 @@ use super::loader::*;
 use super::paging;
 use super::paging::unmap;
 use super::plic;
+use super::signal;
 use super::syscall;
 use super::trampoline;
 use super::trap;
+use super::vdso::{self, VdsoData};
 use super::virtio;
-use crate::process::process_manager;
+use crate::process::{process_manager, ProcessError};
+use crate::rlimit::RLIMIT_AS;
+use crate::timer;
 use crate::*;
 use alloc::alloc::alloc;
 use alloc::alloc::alloc_zeroed;
 use alloc::alloc::dealloc;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 use core::mem;
+use core::ops::Range;
 use core::ptr::NonNull;
 use core::{alloc::Layout, default::Default};
 use core::arch::global_asm;
 
 pub const PROC_START: usize = 0x1000;
+// the highest top of the user stack, it is lowered by a random number of pages
 pub const USER_STACK_START: usize = 0xffff_ffff_ffff_f000;
+// the stack of a new program, it grows on faults below it up to USER_STACK_MAX
 pub const USER_STACK_SIZE: usize = 0x1000;
+pub const USER_STACK_MAX: usize = 0x80_0000;
+pub const STACK_RANDOM_PAGES: usize = 0x4000;
+// room for the arguments and the environment on the initial stack, a quarter of the most the
+// stack grows to as on Linux, init_stack grows the stack to fit them
+pub const ARGS_MAX: usize = USER_STACK_MAX / 4;
+// mappings placed by the kernel, like window frame buffers, start at a random page above this
+pub const MMAP_BASE: usize = 0x20_0000_0000;
+pub const MMAP_RANDOM_PAGES: usize = 0x1_0000;
+// the heap starts at a random page past the end of the program
+pub const BRK_RANDOM_PAGES: usize = 0x2000;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -155,18 +177,746 @@ impl Default for Context {
     }
 }
 
+// the page every zero filled page is mapped to until it is written
+#[repr(C, align(4096))]
+struct ZeroPage([u8; 0x1000]);
+
+static ZERO_PAGE: ZeroPage = ZeroPage([0; 0x1000]);
+
+pub fn zero_page() -> usize {
+    &ZERO_PAGE as *const ZeroPage as usize
+}
+
+// `vaddr` is in the user part of Sv39, below the pages mapped by the kernel or in the upper half,
+// where the stack is
+pub fn is_user_address(vaddr: usize) -> bool {
+    vaddr < trampoline::KERNEL_MAPPED_START || vaddr >= !((1 << 38) - 1)
+}
+
+// a window frame buffer mapped into user space
+#[derive(Copy, Clone)]
+pub struct MappedWindow {
+    pub id: usize, // ObjectId of the window
+    pub vaddr: usize,
+    pub size: usize,
+}
+
+// memory of a process, shared by all of its threads
+// user memory is a list of VM areas whose pages are mapped on first touch, see `fault`. only the
+// window frame buffers, which belong to the windows, are mapped as they are shared
+pub struct AddressSpace {
+    pub page_table: NonNull<paging::Table>,
+    pub stack_top: usize,    // the stack of the main thread ends there
+    pub stack_bottom: usize, // and has grown down to there
+    pub mmap_next: usize,    // where the next mapping placed by the kernel goes
+    pub brk_start: usize,    // the heap, grown and shrunk by brk
+    pub brk: usize,
+    pub exec_info: ExecutableInfo,
+    pub areas: Vec<VmArea>,
+    pub memory: usize, // bytes of the areas and the windows, checked against RLIMIT_AS
+    pub windows: Vec<MappedWindow>,
+    pub mappings: Vec<Range<usize>>, // anonymous mappings, page aligned
+    thread_slots: u64,               // trap frame slots in use, see trampoline::thread_trapframe
+    pub vdso_data: NonNull<VdsoData>, // mapped at VDSO_DATA
+}
+
+impl AddressSpace {
+    pub fn new() -> Self {
+        let layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
+        let page_table = unsafe { alloc_zeroed(layout) } as *mut paging::Table;
+        let vdso_data = unsafe { alloc_zeroed(layout) } as *mut VdsoData;
+        unsafe { (*vdso_data).init() };
+        let stack_top = USER_STACK_START - aslr::random_offset(STACK_RANDOM_PAGES);
+        AddressSpace {
+            page_table: NonNull::new(page_table).unwrap(),
+            stack_top,
+            stack_bottom: stack_top,
+            mmap_next: MMAP_BASE + aslr::random_offset(MMAP_RANDOM_PAGES),
+            brk_start: 0,
+            brk: 0,
+            exec_info: ExecutableInfo::new(),
+            areas: Vec::new(),
+            memory: 0,
+            windows: Vec::new(),
+            mappings: Vec::new(),
+            thread_slots: 0,
+            vdso_data: NonNull::new(vdso_data).unwrap(),
+        }
+    }
+
+    // copy every user page for fork, the kernel mapped pages are set up by the caller
+    // the pages still untouched stay so, and those still shared with the zero page stay shared
+    pub fn duplicate(&self) -> Self {
+        let mut new = AddressSpace::new();
+        new.areas = self.areas.clone();
+        new.memory = self.areas.iter().map(|area| area.range.len()).sum();
+        for (vaddr, paddr, flags) in self.user_pages().into_iter() {
+            if paddr == zero_page() {
+                unsafe {
+                    paging::map(
+                        new.page_table.as_mut(),
+                        vaddr,
+                        paddr,
+                        flags
+                            & (paging::EntryBits::R.val()
+                                | paging::EntryBits::X.val()
+                                | paging::EntryBits::U.val()),
+                        0,
+                    );
+                }
+                continue;
+            }
+            unsafe {
+                new.map_user_page(vaddr, paddr as *const u8, flags);
+            }
+        }
+        new.exec_info.entry = self.exec_info.entry;
+        new.exec_info.bias = self.exec_info.bias;
+        new.exec_info.program_entry = self.exec_info.program_entry;
+        new.exec_info.phdr = self.exec_info.phdr;
+        new.exec_info.phnum = self.exec_info.phnum;
+        new.exec_info.interp_base = self.exec_info.interp_base;
+        new.exec_info.program_end = self.exec_info.program_end;
+        new.exec_info.abi = self.exec_info.abi;
+        new.stack_top = self.stack_top;
+        new.stack_bottom = self.stack_bottom;
+        new.mmap_next = self.mmap_next;
+        new.brk_start = self.brk_start;
+        new.brk = self.brk;
+        new.mappings = self.mappings.clone();
+
+        new
+    }
+
+    // (virtual address, physical address, flags) of the pages user mode can access,
+    // without the pages of the kernel mapped area
+    // a page still shared with the zero page has the flags of its area, with W
+    pub fn user_pages(&self) -> Vec<(usize, usize, usize)> {
+        let mut pages = Vec::new();
+        paging::walk(unsafe { self.page_table.as_ref() }, |vaddr, entry| {
+            let mut flags = entry.get_flags();
+            if flags & paging::EntryBits::U.val() == 0
+                || (vaddr >= trampoline::KERNEL_MAPPED_START && vaddr <= trampoline::TRAMPOLINE)
+            {
+                return;
+            }
+            if entry.get_addr() == zero_page() {
+                flags |= self.area(vaddr).map_or(0, |area| area.flags);
+            }
+            pages.push((vaddr, entry.get_addr(), flags));
+        });
+        pages
+    }
+
+    // physical address of `vaddr` if it is mapped for user mode
+    pub fn user_to_phys(&self, vaddr: usize) -> Option<usize> {
+        if vaddr >= trampoline::KERNEL_MAPPED_START && vaddr <= trampoline::TRAMPOLINE {
+            return None;
+        }
+        let (paddr, flags) = paging::translate(unsafe { self.page_table.as_ref() }, vaddr)?;
+        if flags & paging::EntryBits::U.val() == 0 {
+            return None;
+        }
+        Some(paddr)
+    }
+
+    // physical address of `vaddr` if user mode can read it, or write it if `write`
+    // unlike user_to_phys, which lets a tracer patch code, the page permissions are checked
+    pub fn user_to_phys_checked(&self, vaddr: usize, write: bool) -> Option<usize> {
+        let paddr = self.user_to_phys(vaddr)?;
+        let (_, flags) = paging::translate(unsafe { self.page_table.as_ref() }, vaddr)?;
+        let needed = if write {
+            paging::EntryBits::W.val()
+        } else {
+            paging::EntryBits::R.val()
+        };
+        if flags & needed == 0 {
+            return None;
+        }
+        Some(paddr)
+    }
+
+    // physical address of `vaddr` like user_to_phys_checked, after handling the fault user mode
+    // would take there, see `fault`
+    pub fn fault_in(&mut self, vaddr: usize, write: bool, max_memory: usize) -> Option<usize> {
+        if let Some(paddr) = self.user_to_phys_checked(vaddr, write) {
+            return Some(paddr);
+        }
+        let access = if write {
+            paging::EntryBits::W.val()
+        } else {
+            paging::EntryBits::R.val()
+        };
+        if !self.fault(vaddr, access, max_memory) {
+            return None;
+        }
+        self.user_to_phys_checked(vaddr, write)
+    }
+
+    // physical address of `vaddr` for a tracer, which may write pages user mode can only read or
+    // run: an untouched page is mapped first, and one shared with the zero page is copied before
+    // a write
+    pub fn tracee_to_phys(&mut self, vaddr: usize, write: bool) -> Option<usize> {
+        let page = vaddr & !0xfff;
+        match self.user_to_phys(vaddr) {
+            Some(paddr) if !write || paddr & !0xfff != zero_page() => return Some(paddr),
+            _ => {}
+        }
+        let area = self.area(page)?.clone();
+        self.populate(page, &area, write);
+        self.user_to_phys(vaddr)
+    }
+
+    // the area `vaddr` is in
+    pub fn area(&self, vaddr: usize) -> Option<&VmArea> {
+        self.areas.iter().find(|area| area.range.contains(&vaddr))
+    }
+
+    // add `range`, page aligned and free, as an area; a zero filled one is merged with one next
+    // to it with the same flags
+    fn add_area(&mut self, range: Range<usize>, flags: usize, backing: Backing) {
+        self.memory += range.len();
+        if let Backing::Zero = backing {
+            let next_to = self.areas.iter_mut().find(|area| {
+                matches!(area.backing, Backing::Zero)
+                    && area.flags == flags
+                    && (area.range.end == range.start || area.range.start == range.end)
+            });
+            if let Some(area) = next_to {
+                area.range = area.range.start.min(range.start)..area.range.end.max(range.end);
+                return;
+            }
+        }
+        self.areas.push(VmArea {
+            range,
+            flags,
+            backing,
+        });
+    }
+
+    // remove the areas in `range`, page aligned, and free their pages
+    fn remove_areas(&mut self, range: Range<usize>) {
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for area in mem::take(&mut self.areas) {
+            if area.range.end <= range.start || range.end <= area.range.start {
+                kept.push(area);
+                continue;
+            }
+            let start = area.range.start.max(range.start);
+            let end = area.range.end.min(range.end);
+            if area.range.start < start {
+                let mut below = area.clone();
+                below.range.end = start;
+                kept.push(below);
+            }
+            if end < area.range.end {
+                let mut above = area.clone();
+                above.range.start = end;
+                kept.push(above);
+            }
+            removed.push(start..end);
+        }
+        self.areas = kept;
+        for range in removed {
+            self.memory -= range.len();
+            for page in range.step_by(0x1000) {
+                self.free_page(page);
+            }
+        }
+    }
+
+    // map the page at `vaddr` of `area`, or replace the zero page there: a page of a program is
+    // filled from its file contents, a zero filled one is mapped to the zero page, read only,
+    // unless `write`
+    fn populate(&mut self, vaddr: usize, area: &VmArea, write: bool) {
+        let contents = match &area.backing {
+            Backing::Image(contents) if contents.has_contents(vaddr) => Some(contents.clone()),
+            _ => None,
+        };
+        if contents.is_none() && !write {
+            unsafe {
+                paging::map(
+                    self.page_table.as_mut(),
+                    vaddr,
+                    zero_page(),
+                    area.flags & !paging::EntryBits::W.val(),
+                    0,
+                );
+            }
+            return;
+        }
+        unsafe {
+            self.map_user_page(vaddr, zero_page() as *const u8, area.flags);
+            if let Some(contents) = contents {
+                let page = self.user_to_phys(vaddr).unwrap();
+                contents.fill(vaddr, page as *mut u8);
+                self.release_contents(vaddr);
+            }
+        }
+    }
+
+    // once every page of the area at `vaddr` with file contents is mapped, the rest of it is zero
+    // filled, and the contents are freed with the last area of the image still needing them
+    fn release_contents(&mut self, vaddr: usize) {
+        let page_table = unsafe { self.page_table.as_ref() };
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|area| area.range.contains(&vaddr))
+        {
+            Some(area) => area,
+            None => return,
+        };
+        let populated = match &area.backing {
+            Backing::Image(contents) => area.range.clone().step_by(0x1000).all(|page| {
+                !contents.has_contents(page) || paging::translate(page_table, page).is_some()
+            }),
+            Backing::Zero => false,
+        };
+        if populated {
+            area.backing = Backing::Zero;
+        }
+    }
+
+    // handle a page fault at `vaddr` by an access which needs `access` (EntryBits R, W or X),
+    // returns false if the access isn't allowed
+    // the page is mapped if its area allows the access, and the zero page mapped there is
+    // replaced by a private page on a write. a page below the stack without an area is added to
+    // the stack, see grow_stack
+    pub fn fault(&mut self, vaddr: usize, access: usize, max_memory: usize) -> bool {
+        let page = vaddr & !0xfff;
+        if self.area(page).is_none() && !self.grow_stack(page, max_memory) {
+            return false;
+        }
+        let area = self.area(page).unwrap().clone();
+        if area.flags & access == 0 {
+            return false;
+        }
+        let write = access == paging::EntryBits::W.val();
+        let mapped = paging::translate(unsafe { self.page_table.as_ref() }, page);
+        match mapped {
+            None => self.populate(page, &area, write),
+            Some((paddr, _)) if paddr == zero_page() && write => self.populate(page, &area, true),
+            // mapped by another thread after the fault
+            Some((_, flags)) => return flags & access != 0,
+        }
+        true
+    }
+
+    // grow the stack of the main thread down to the page of `vaddr`, returns false if it would
+    // go past USER_STACK_MAX or user memory past `max_memory` bytes
+    pub fn grow_stack(&mut self, vaddr: usize, max_memory: usize) -> bool {
+        let page = vaddr & !0xfff;
+        if page >= self.stack_bottom || page < self.stack_top - USER_STACK_MAX {
+            return false;
+        }
+        let size = self.stack_bottom - page;
+        if self.memory + size > max_memory {
+            return false;
+        }
+        let flags =
+            paging::EntryBits::R.val() | paging::EntryBits::W.val() | paging::EntryBits::U.val();
+        self.add_area(page..self.stack_bottom, flags, Backing::Zero);
+        self.stack_bottom = page;
+        true
+    }
+
+    // map every page of the areas, for saving all of user memory
+    pub fn populate_all(&mut self) {
+        let page_table = unsafe { self.page_table.as_ref() };
+        let mut untouched = Vec::new();
+        for area in self.areas.iter() {
+            for page in area.range.clone().step_by(0x1000) {
+                if paging::translate(page_table, page).is_none() {
+                    untouched.push((page, area.clone()));
+                }
+            }
+        }
+        for (page, area) in untouched.iter() {
+            self.populate(*page, area, false);
+        }
+    }
+
+    // map a private copy of the page at `data`, in an area of its own unless one has `vaddr`
+    pub unsafe fn map_user_page(&mut self, vaddr: usize, data: *const u8, flags: usize) {
+        let page_layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
+        let bits = paging::EntryBits::R.val()
+            | paging::EntryBits::W.val()
+            | paging::EntryBits::X.val()
+            | paging::EntryBits::U.val();
+        if self.area(vaddr).is_none() {
+            self.add_area(vaddr..(vaddr + 0x1000), flags & bits, Backing::Zero);
+        }
+
+        let page = alloc(page_layout);
+        page.copy_from_nonoverlapping(data, 0x1000);
+        paging::map(
+            self.page_table.as_mut(),
+            vaddr,
+            page as usize,
+            flags & bits,
+            0,
+        );
+        self.exec_info.segment_buffers.push(Segment::new(
+            page,
+            page_layout,
+            vaddr..(vaddr + 0x1000),
+            flags & bits,
+        ));
+    }
+
+    // the page below the lowest the stack of the main thread can grow to, which is never mapped
+    pub fn in_stack_guard(&self, vaddr: usize) -> bool {
+        let bottom = self.stack_top - USER_STACK_MAX;
+        vaddr < bottom && vaddr >= bottom - 0x1000
+    }
+
+    // lay out the top of the main thread's stack for a new program as the RISC-V psABI does:
+    // 16 random bytes for AT_RANDOM and the strings of `argv` and `envp`, then from the stack
+    // pointer argc, the argv pointers, NULL, the envp pointers, NULL and the auxiliary vector
+    // `auxv` ended by AT_NULL
+    // the strings have to fit in ARGS_MAX bytes, see args_size, and may take several pages
+    pub fn init_stack(&mut self, argv: &[&str], envp: &[&str], auxv: &[(usize, usize)]) -> usize {
+        let random_addr = self.stack_top - 16;
+        self.write_stack(random_addr, &random::next_u64().to_le_bytes());
+        self.write_stack(random_addr + 8, &random::next_u64().to_le_bytes());
+
+        let mut top = random_addr;
+        let mut pointers = Vec::new();
+        for arg in argv.iter().chain(envp.iter()) {
+            top -= arg.len() + 1;
+            self.write_stack(top, arg.as_bytes());
+            self.write_stack(top + arg.len(), &[0]);
+            pointers.push(top);
+        }
+
+        let mut words = vec![argv.len()];
+        words.extend_from_slice(&pointers[..argv.len()]);
+        words.push(0);
+        words.extend_from_slice(&pointers[argv.len()..]);
+        words.push(0);
+        for (key, value) in auxv.iter() {
+            words.push(*key);
+            words.push(*value);
+        }
+        words.extend_from_slice(&[AT_RANDOM, random_addr, AT_NULL, 0]);
+
+        let sp = (top - words.len() * 8) & !0xf;
+        for (index, word) in words.iter().enumerate() {
+            self.write_stack(sp + index * 8, &word.to_le_bytes());
+        }
+        sp
+    }
+
+    // write `bytes` at `vaddr` of the stack, which grows as needed
+    fn write_stack(&mut self, vaddr: usize, bytes: &[u8]) {
+        let mut done = 0;
+        while done < bytes.len() {
+            let addr = vaddr + done;
+            let paddr = self
+                .fault_in(addr, true, usize::MAX)
+                .expect("the stack is out of reach");
+            let count = (bytes.len() - done).min(0x1000 - addr % 0x1000);
+            unsafe {
+                (paddr as *mut u8).copy_from_nonoverlapping(bytes[done..].as_ptr(), count);
+            }
+            done += count;
+        }
+    }
+
+    // the end of a window, an area or an anonymous mapping overlapping `size` bytes at `vaddr`
+    fn overlapping_end(&self, vaddr: usize, size: usize) -> Option<usize> {
+        let windows = self
+            .windows
+            .iter()
+            .map(|mapped| mapped.vaddr..(mapped.vaddr + mapped.size));
+        windows
+            .chain(self.areas.iter().map(|area| area.range.clone()))
+            .chain(self.mappings.iter().cloned())
+            .find(|range| range.start < vaddr + size && vaddr < range.end)
+            .map(|range| range.end)
+    }
+
+    // an address for `size` bytes from mmap_next, past the windows and mappings there
+    fn place_mapping(&mut self, size: usize) -> usize {
+        let mut vaddr = self.mmap_next;
+        while let Some(end) = self.overlapping_end(vaddr, size) {
+            vaddr = page_end(end);
+        }
+        self.mmap_next = page_end(vaddr + size);
+        vaddr
+    }
+
+    // `size` bytes at `vaddr` are in user memory and nothing is mapped or reserved there
+    pub fn range_free(&self, vaddr: usize, size: usize) -> bool {
+        match vaddr.checked_add(size) {
+            Some(end) if end <= trampoline::KERNEL_MAPPED_START => {
+                self.overlapping_end(vaddr, size).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    // unmap a page of the program, the heap or a mapping and free it unless it is the zero page
+    fn free_page(&mut self, vaddr: usize) {
+        let page_table = unsafe { self.page_table.as_mut() };
+        if paging::translate(page_table, vaddr).is_none() {
+            return;
+        }
+        paging::unmap_page(page_table, vaddr);
+        let buffers = &mut self.exec_info.segment_buffers;
+        if let Some(index) = buffers
+            .iter()
+            .position(|segment| segment.vm_range.start == vaddr)
+        {
+            let segment = buffers.swap_remove(index);
+            unsafe {
+                dealloc(segment.ptr, segment.layout);
+            }
+        }
+    }
+
+    // the heap starts at a random page past `program_end`
+    pub fn init_brk(&mut self, program_end: usize) {
+        self.brk_start = page_end(program_end) + aslr::random_offset(BRK_RANDOM_PAGES);
+        self.brk = self.brk_start;
+    }
+
+    // move the end of the heap to `brk`, returns the new end, or the old one if the heap can't
+    // end there: below its start, past `max_memory` bytes of user memory or over other pages
+    pub fn set_brk(&mut self, brk: usize, max_memory: usize) -> usize {
+        if brk < self.brk_start || brk > trampoline::KERNEL_MAPPED_START {
+            return self.brk;
+        }
+        let old_end = page_end(self.brk);
+        let new_end = page_end(brk);
+        if new_end > old_end {
+            let size = new_end - old_end;
+            if self.memory + size > max_memory || !self.range_free(old_end, size) {
+                return self.brk;
+            }
+            let flags = paging::EntryBits::R.val()
+                | paging::EntryBits::W.val()
+                | paging::EntryBits::U.val();
+            self.add_area(old_end..new_end, flags, Backing::Zero);
+        } else if new_end < old_end {
+            self.remove_areas(new_end..old_end);
+        }
+        self.brk = brk;
+        brk
+    }
+
+    // map `size` bytes of zero filled pages with `flags` at `vaddr`, or at an address chosen here
+    // if `vaddr` is 0, returns the address
+    // flags without R, W or X only reserve the range
+    // fails with ResourceLimit(RLIMIT_AS) past `max_memory` bytes of user memory and with
+    // InvalidArgument if the range isn't free
+    pub fn map_anonymous(
+        &mut self,
+        vaddr: usize,
+        size: usize,
+        flags: usize,
+        max_memory: usize,
+    ) -> Result<usize, ProcessError> {
+        if size > trampoline::KERNEL_MAPPED_START {
+            return Err(ProcessError::InvalidArgument);
+        }
+        let size = page_end(size);
+        let access =
+            paging::EntryBits::R.val() | paging::EntryBits::W.val() | paging::EntryBits::X.val();
+        let reserve = flags & access == 0;
+        if !reserve && self.memory + size > max_memory {
+            return Err(ProcessError::ResourceLimit(RLIMIT_AS));
+        }
+        let vaddr = if vaddr == 0 {
+            self.place_mapping(size)
+        } else {
+            vaddr
+        };
+        if !self.range_free(vaddr, size) {
+            return Err(ProcessError::InvalidArgument);
+        }
+
+        if !reserve {
+            self.add_area(vaddr..(vaddr + size), flags, Backing::Zero);
+        }
+        self.mappings.push(vaddr..(vaddr + size));
+        Ok(vaddr)
+    }
+
+    // map `size` bytes at `vaddr` like map_anonymous, in place of the anonymous mappings there
+    // the old mappings are only unmapped once the new one is known to fit, so they are kept if
+    // the range has other pages or the limit is hit
+    pub fn map_anonymous_fixed(
+        &mut self,
+        vaddr: usize,
+        size: usize,
+        flags: usize,
+        max_memory: usize,
+    ) -> Result<usize, ProcessError> {
+        if size > trampoline::KERNEL_MAPPED_START {
+            return Err(ProcessError::InvalidArgument);
+        }
+        let size = page_end(size);
+        let end = match vaddr.checked_add(size) {
+            Some(end) if end <= trampoline::KERNEL_MAPPED_START => end,
+            _ => return Err(ProcessError::InvalidArgument),
+        };
+
+        // outside of the anonymous mappings the range has to be free
+        let mut gaps = vec![vaddr..end];
+        for mapping in self.mappings.iter() {
+            let mut rest = Vec::new();
+            for gap in gaps {
+                if mapping.end <= gap.start || gap.end <= mapping.start {
+                    rest.push(gap);
+                    continue;
+                }
+                if gap.start < mapping.start {
+                    rest.push(gap.start..mapping.start);
+                }
+                if mapping.end < gap.end {
+                    rest.push(mapping.end..gap.end);
+                }
+            }
+            gaps = rest;
+        }
+        if gaps
+            .iter()
+            .any(|gap| self.overlapping_end(gap.start, gap.len()).is_some())
+        {
+            return Err(ProcessError::InvalidArgument);
+        }
+
+        let access =
+            paging::EntryBits::R.val() | paging::EntryBits::W.val() | paging::EntryBits::X.val();
+        let replaced: usize = self
+            .areas
+            .iter()
+            .map(|area| {
+                area.range
+                    .end
+                    .min(end)
+                    .saturating_sub(area.range.start.max(vaddr))
+            })
+            .sum();
+        if flags & access != 0 && self.memory - replaced + size > max_memory {
+            return Err(ProcessError::ResourceLimit(RLIMIT_AS));
+        }
+
+        self.unmap_anonymous(vaddr, size);
+        self.map_anonymous(vaddr, size, flags, max_memory)
+    }
+
+    // unmap the anonymous mappings in `size` bytes at `vaddr`, other pages are left alone
+    pub fn unmap_anonymous(&mut self, vaddr: usize, size: usize) {
+        let end = vaddr.saturating_add(size.saturating_add(0xfff) & !0xfff);
+        let mut kept = Vec::new();
+        for range in mem::take(&mut self.mappings) {
+            if range.end <= vaddr || end <= range.start {
+                kept.push(range);
+                continue;
+            }
+            let start = range.start.max(vaddr);
+            let stop = range.end.min(end);
+            self.remove_areas(start..stop);
+            if range.start < start {
+                kept.push(range.start..start);
+            }
+            if stop < range.end {
+                kept.push(stop..range.end);
+            }
+        }
+        self.mappings = kept;
+    }
+
+    // share the frame buffer of window `id` at `vaddr`, or at an address chosen here if `vaddr`
+    // is 0, returns the address
+    // a nonzero `vaddr` has to be page aligned with nothing mapped or reserved under the window
+    pub fn map_window(
+        &mut self,
+        id: usize,
+        vaddr: usize,
+        buffer: usize,
+        size: usize,
+    ) -> Result<usize, ProcessError> {
+        let vaddr = if vaddr == 0 {
+            self.place_mapping(size)
+        } else if vaddr % 0x1000 == 0 && self.range_free(vaddr, page_end(size)) {
+            vaddr
+        } else {
+            return Err(ProcessError::InvalidArgument);
+        };
+        unsafe {
+            paging::map_range(
+                self.page_table.as_mut(),
+                vaddr,
+                buffer,
+                size,
+                paging::EntryBits::R.val()
+                    | paging::EntryBits::W.val()
+                    | paging::EntryBits::U.val(),
+            );
+        }
+        self.windows.push(MappedWindow { id, vaddr, size });
+        self.memory += size;
+        Ok(vaddr)
+    }
+
+    fn alloc_thread_slot(&mut self) -> Option<usize> {
+        let slot = (!self.thread_slots).trailing_zeros() as usize;
+        if slot >= trampoline::MAX_THREADS {
+            return None;
+        }
+        self.thread_slots |= 1 << slot;
+        Some(slot)
+    }
+
+    fn free_thread_slot(&mut self, slot: usize) {
+        self.thread_slots &= !(1 << slot);
+    }
+}
+
+fn page_end(end: usize) -> usize {
+    (end + 0xfff) & !0xfff
+}
+
+// bytes of the initial stack taken by `argv` and `envp`: the strings and the pointers to them
+pub fn args_size(argv: &[&str], envp: &[&str]) -> usize {
+    let strings = argv.iter().chain(envp.iter());
+    strings.map(|arg| arg.len() + 1 + 8).sum::<usize>() + 2 * 8
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let page_layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
+
+        unsafe {
+            unmap(self.page_table.as_mut());
+            dealloc(self.page_table.as_ptr() as *mut u8, page_layout);
+            dealloc(self.vdso_data.as_ptr() as *mut u8, page_layout);
+
+            for segment in self.exec_info.segment_buffers.iter() {
+                dealloc(segment.ptr, segment.layout);
+            }
+        }
+    }
+}
+
+// a thread; the threads of a process share `addr_space`
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct ArchProcess {
-    pub page_table: NonNull<paging::Table>,
+    pub addr_space: Option<Rc<RefCell<AddressSpace>>>,
     pub trap_frame: *mut TrapFrame,
+    pub trap_frame_va: usize, // where `trap_frame` is mapped in `addr_space`
+    pub thread_slot: Option<usize>,
     pub context: Context,
     pub kernel_stack: usize,
     pub kernel_stack_size: usize,
-    pub user_stack: usize,
-    pub user_stack_size: usize,
-    pub exec_info: ExecutableInfo,
     pub pid: usize,
+    pub vdso_data: *mut VdsoData, // of `addr_space`, written without borrowing it
 }
 
 extern "C" {
@@ -216,46 +966,142 @@ global_asm!(
 impl ArchProcess {
     pub fn new(pid: usize) -> Self {
         let layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
-        // let page_table = unsafe { alloc_zeroed(layout) } as *mut paging::Table;
         let trap_frame = unsafe { alloc_zeroed(layout) } as *mut TrapFrame;
         ArchProcess {
-            page_table: NonNull::dangling(),
+            addr_space: Some(Rc::new(RefCell::new(AddressSpace::new()))),
             trap_frame,
+            trap_frame_va: trampoline::TRAPFRAME,
+            thread_slot: None,
             context: Default::default(),
             kernel_stack: 0,
             kernel_stack_size: 0,
-            user_stack: 0,
-            user_stack_size: 0,
-            exec_info: ExecutableInfo::new(),
             pid,
+            vdso_data: core::ptr::null_mut(),
+        }
+    }
+
+    pub fn addr_space(&self) -> &Rc<RefCell<AddressSpace>> {
+        self.addr_space
+            .as_ref()
+            .expect("address space is already freed")
+    }
+
+    pub fn page_table(&self) -> NonNull<paging::Table> {
+        self.addr_space().borrow().page_table
+    }
+
+    // user registers in the order of x0 to x31, with the pc in place of x0
+    pub fn user_registers(&self) -> [usize; 32] {
+        let tf = unsafe { &*self.trap_frame };
+        [
+            tf.epc, tf.ra, tf.sp, tf.gp, tf.tp, tf.t0, tf.t1, tf.t2, tf.s0, tf.s1, tf.a0, tf.a1,
+            tf.a2, tf.a3, tf.a4, tf.a5, tf.a6, tf.a7, tf.s2, tf.s3, tf.s4, tf.s5, tf.s6, tf.s7,
+            tf.s8, tf.s9, tf.s10, tf.s11, tf.t3, tf.t4, tf.t5, tf.t6,
+        ]
+    }
+
+    pub fn set_user_registers(&mut self, regs: &[usize; 32]) {
+        let tf = unsafe { &mut *self.trap_frame };
+        tf.epc = regs[0];
+        tf.ra = regs[1];
+        tf.sp = regs[2];
+        tf.gp = regs[3];
+        tf.tp = regs[4];
+        tf.t0 = regs[5];
+        tf.t1 = regs[6];
+        tf.t2 = regs[7];
+        tf.s0 = regs[8];
+        tf.s1 = regs[9];
+        tf.a0 = regs[10];
+        tf.a1 = regs[11];
+        tf.a2 = regs[12];
+        tf.a3 = regs[13];
+        tf.a4 = regs[14];
+        tf.a5 = regs[15];
+        tf.a6 = regs[16];
+        tf.a7 = regs[17];
+        tf.s2 = regs[18];
+        tf.s3 = regs[19];
+        tf.s4 = regs[20];
+        tf.s5 = regs[21];
+        tf.s6 = regs[22];
+        tf.s7 = regs[23];
+        tf.s8 = regs[24];
+        tf.s9 = regs[25];
+        tf.s10 = regs[26];
+        tf.s11 = regs[27];
+        tf.t3 = regs[28];
+        tf.t4 = regs[29];
+        tf.t5 = regs[30];
+        tf.t6 = regs[31];
+    }
+
+    // the per-process values of the vDSO data page
+    pub fn update_vdso(&self, pid: usize, cpu_time: usize) {
+        if self.vdso_data.is_null() {
+            return;
+        }
+        unsafe {
+            vdso::update(self.vdso_data, |data| {
+                data.pid = pid as u64;
+                data.cpu_time = cpu_time as u64;
+            });
         }
     }
 
+    // the address space itself is freed by the last thread
     pub fn free(&mut self) {
-        let user_stack_layout = Layout::from_size_align(self.user_stack_size, 0x1000).unwrap();
-        let page_table_layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
         let trap_frame_layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
+        self.vdso_data = core::ptr::null_mut();
 
-        unsafe {
-            dealloc(self.user_stack as *mut u8, user_stack_layout);
-            unmap(self.page_table.as_mut());
-            dealloc(self.page_table.as_ptr() as *mut u8, page_table_layout);
-            dealloc(self.trap_frame as *mut u8, trap_frame_layout);
+        if let Some(addr_space) = self.addr_space.take() {
+            if let Some(slot) = self.thread_slot.take() {
+                let mut addr_space = addr_space.borrow_mut();
+                unsafe {
+                    paging::unmap_page(addr_space.page_table.as_mut(), self.trap_frame_va);
+                }
+                addr_space.free_thread_slot(slot);
+            }
+        }
 
-            for segment in self.exec_info.segment_buffers.iter() {
-                dealloc(segment.ptr, segment.layout);
+        if !self.trap_frame.is_null() {
+            unsafe {
+                dealloc(self.trap_frame as *mut u8, trap_frame_layout);
             }
+            self.trap_frame = core::ptr::null_mut();
         }
     }
 
-    pub fn init_program(&mut self, path: &str) {
-        let exec_info = unsafe { load_exe(path, self.page_table.as_mut()).unwrap() };
-
-        self.exec_info = exec_info;
+    // fails with fs::Error::MemoryLimit if the program doesn't fit in `max_memory`
+    pub fn init_program(
+        &mut self,
+        path: &str,
+        argv: &[&str],
+        envp: &[&str],
+        max_memory: usize,
+    ) -> Result<(), fs::Error> {
+        let mut addr_space = self.addr_space().borrow_mut();
+        let available = max_memory.saturating_sub(addr_space.memory);
+        let first = addr_space.areas.len();
+        let exec_info = load_exe(path, &mut addr_space.areas, available)?;
+        let entry = exec_info.entry;
+        addr_space.memory += addr_space.areas[first..]
+            .iter()
+            .map(|area| area.range.len())
+            .sum::<usize>();
+        let sp = addr_space.init_stack(argv, envp, &exec_info.auxv());
+        addr_space.init_brk(exec_info.program_end);
+        let segment_buffers = mem::take(&mut addr_space.exec_info.segment_buffers);
+        addr_space.exec_info = exec_info;
+        addr_space.exec_info.segment_buffers = segment_buffers;
+        drop(addr_space);
 
         unsafe {
-            (*self.trap_frame).epc = self.exec_info.entry;
+            (*self.trap_frame).epc = entry;
+            (*self.trap_frame).sp = sp;
         }
+
+        Ok(())
     }
 
     pub fn init(&mut self, start: usize, kernel_stack: usize, kernel_stack_size: usize) {
@@ -266,22 +1112,72 @@ impl ArchProcess {
 
         unsafe {
             // (*self.trap_frame).epc = PROC_START;
-            (*self.trap_frame).sp = USER_STACK_START;
+            (*self.trap_frame).sp = self.addr_space().borrow().stack_top;
             (*self.trap_frame).ra = trampoline::KILLME;
         }
     }
 
+    // a new thread in `addr_space`, returns false if there are too many threads
+    // the caller fills the trap frame
+    pub fn init_thread(
+        &mut self,
+        addr_space: Rc<RefCell<AddressSpace>>,
+        kernel_stack: usize,
+        kernel_stack_size: usize,
+    ) -> bool {
+        let slot = match addr_space.borrow_mut().alloc_thread_slot() {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        self.vdso_data = addr_space.borrow().vdso_data.as_ptr();
+        self.addr_space = Some(addr_space);
+        self.thread_slot = Some(slot);
+        self.trap_frame_va = trampoline::thread_trapframe(slot);
+        self.map_trap_frame();
+
+        self.kernel_stack = kernel_stack;
+        self.kernel_stack_size = kernel_stack_size;
+        self.init_context(
+            ArchProcess::user_trap_return as usize,
+            kernel_stack + kernel_stack_size,
+        );
+
+        true
+    }
+
+    // the main thread of a forked process, `addr_space` is a copy made by `AddressSpace::duplicate`
+    pub fn init_fork(
+        &mut self,
+        addr_space: AddressSpace,
+        kernel_stack: usize,
+        kernel_stack_size: usize,
+    ) {
+        self.addr_space = Some(Rc::new(RefCell::new(addr_space)));
+        self.thread_slot = None;
+        self.trap_frame_va = trampoline::TRAPFRAME;
+        self.map_kernel_pages();
+
+        self.kernel_stack = kernel_stack;
+        self.kernel_stack_size = kernel_stack_size;
+        self.init_context(
+            ArchProcess::user_trap_return as usize,
+            kernel_stack + kernel_stack_size,
+        );
+    }
+
     pub fn interrupt(code: usize) {
         match code {
             1 => {
                 // supervisor software interrupt
                 let pm = unsafe { process_manager() };
                 Csr::Sip.write(Csr::Sip.read() & !(1 << 1)); // clear SSIP
-                pm.wakeup().expect("process");
+                timer::tick().expect("process");
                 pm.schedule().expect("process");
             }
             9 => {
                 let irq = plic::claim();
+                random::add_entropy(clint::now() as u64 ^ irq as u64);
                 if (irq as usize) >= plic::Irq::VirtioFirstIrq.val()
                     && (irq as usize) <= plic::Irq::VirtioEndIrq.val()
                 {
@@ -302,21 +1198,58 @@ impl ArchProcess {
 
     pub fn exception(&mut self, code: usize) {
         if code == 8 {
+            // skip ecall before dispatching, sigreturn and execve overwrite epc
+            unsafe {
+                (*self.trap_frame).epc += 4;
+            }
             interrupt_on();
             // system call
             let ret_val = unsafe { syscall::execute_syscall() };
             unsafe {
                 (*self.trap_frame).a0 = ret_val;
-                (*self.trap_frame).epc += 4;
             }
         } else {
+            let pm = unsafe { process_manager() };
+            let (traced, max_memory) = match get_process!(pm.ptable_lock(), self.pid) {
+                Ok(proc) => (proc.trace.tracer.is_some(), proc.rlimits.cur(RLIMIT_AS)),
+                Err(_) => (false, 0),
+            };
+            // a page fault maps the page if it is in an area of the process
+            let access = match code {
+                12 => paging::EntryBits::X.val(),
+                13 => paging::EntryBits::R.val(),
+                15 => paging::EntryBits::W.val(),
+                _ => 0,
+            };
+            if access != 0
+                && self
+                    .addr_space()
+                    .borrow_mut()
+                    .fault(Csr::Stval.read(), access, max_memory)
+            {
+                return;
+            }
+            if code == 3 && traced {
+                // ebreak: a breakpoint of the tracer, the process stops with SIGTRAP
+                pm.force_signal(self.pid, signal::exception_signal(code))
+                    .expect("process");
+                return;
+            }
+
             let sepc = Csr::Sepc.read();
             let stval = Csr::Stval.read();
             println!("==exception occurred==");
+            println!("pid    : {}", self.pid);
             println!("scause : {:#018x}", code);
             println!("sepc   : {:#018x}", sepc);
             println!("stval  : {:#018x}", stval);
-            loop {}
+            let page_fault = code == 12 || code == 13 || code == 15;
+            if page_fault && self.addr_space().borrow().in_stack_guard(stval) {
+                println!("user stack overflow");
+            }
+
+            pm.force_signal(self.pid, signal::exception_signal(code))
+                .expect("process");
         }
     }
 
@@ -338,6 +1271,9 @@ impl ArchProcess {
             self.exception(scause);
         }
 
+        let pm = process_manager();
+        pm.handle_signals().expect("process");
+
         self.user_trap_return();
     }
 
@@ -364,62 +1300,92 @@ impl ArchProcess {
 
         (*self.trap_frame).arch_proc = self as *mut ArchProcess as usize;
 
+        // threads of a process share the root table, so switching between them keeps satp
+        let page_table = self.page_table().as_ptr() as usize;
         #[cfg(target_pointer_width = "32")]
-        let mut satp = 1_usize << 31 | (self.page_table as usize >> 12);
+        let satp = 1_usize << 31 | (page_table >> 12);
 
         #[cfg(target_pointer_width = "64")]
-        let satp = 8_usize << 60 | (self.page_table.as_ptr() as usize >> 12);
+        let satp = 8_usize << 60 | (page_table >> 12);
         let func_usize: usize = trampoline::TRAMPOLINE
             + (trampoline::userret as usize - trampoline::trampoline as usize);
 
         let func = mem::transmute::<usize, fn(usize, usize)>(func_usize);
         // jump to userret
-        func(trampoline::TRAPFRAME, satp);
+        func(self.trap_frame_va, satp);
     }
 
-    pub fn setup_pagetable(&mut self) {
-        let layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
-        let page_table = unsafe { alloc_zeroed(layout) } as *mut paging::Table;
-        self.page_table = NonNull::new(page_table).unwrap();
+    fn map_trap_frame(&mut self) {
+        let page_table = self.page_table();
         unsafe {
             paging::map(
-                self.page_table.as_mut(),
+                &mut *page_table.as_ptr(),
+                self.trap_frame_va,
+                self.trap_frame as usize,
+                paging::EntryBits::R.val() | paging::EntryBits::W.val(),
+                0,
+            );
+        }
+    }
+
+    // pages every address space has: trampolines, the vDSO and the trap frame of the main thread
+    fn map_kernel_pages(&mut self) {
+        let mut page_table = self.page_table();
+        self.vdso_data = self.addr_space().borrow().vdso_data.as_ptr();
+        unsafe {
+            paging::map(
+                page_table.as_mut(),
                 trampoline::TRAMPOLINE,
                 trampoline::trampoline as usize,
                 paging::EntryBits::R.val() | paging::EntryBits::X.val(),
                 0,
             );
             paging::map(
-                self.page_table.as_mut(),
-                trampoline::TRAPFRAME,
-                self.trap_frame as usize,
-                paging::EntryBits::R.val() | paging::EntryBits::W.val(),
+                page_table.as_mut(),
+                trampoline::KILLME,
+                trampoline::killme as usize,
+                paging::EntryBits::R.val()
+                    | paging::EntryBits::X.val()
+                    | paging::EntryBits::U.val(),
                 0,
             );
             paging::map(
-                self.page_table.as_mut(),
-                trampoline::KILLME,
-                trampoline::killme as usize,
+                page_table.as_mut(),
+                trampoline::SIGRETURN,
+                trampoline::sigreturn_trampoline as usize,
                 paging::EntryBits::R.val()
                     | paging::EntryBits::X.val()
                     | paging::EntryBits::U.val(),
                 0,
             );
-
-            let stack_layout = Layout::from_size_align(USER_STACK_SIZE, 0x1000).unwrap();
-            let user_stack = alloc(stack_layout) as usize;
-            self.user_stack = user_stack;
-            self.user_stack_size = USER_STACK_SIZE;
             paging::map(
-                self.page_table.as_mut(),
-                USER_STACK_START - USER_STACK_SIZE,
-                user_stack,
+                page_table.as_mut(),
+                vdso::VDSO_DATA,
+                self.vdso_data as usize,
+                paging::EntryBits::R.val() | paging::EntryBits::U.val(),
+                0,
+            );
+            paging::map(
+                page_table.as_mut(),
+                vdso::VDSO_CODE,
+                vdso::vdso as usize,
                 paging::EntryBits::R.val()
-                    | paging::EntryBits::W.val()
+                    | paging::EntryBits::X.val()
                     | paging::EntryBits::U.val(),
                 0,
             );
         }
+        self.update_vdso(self.pid, 0);
+        self.map_trap_frame();
+    }
+
+    // the kernel mapped pages and the area of the user stack, whose pages are mapped as it is used
+    pub fn setup_pagetable(&mut self) {
+        self.map_kernel_pages();
+
+        let mut addr_space = self.addr_space().borrow_mut();
+        let bottom = addr_space.stack_top - USER_STACK_SIZE;
+        addr_space.grow_stack(bottom, usize::MAX);
     }
 
     pub fn init_context(&mut self, start: usize, stack: usize) {
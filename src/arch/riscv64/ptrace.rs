@@ -0,0 +1,119 @@
+use alloc::vec::Vec;
+use core::arch::asm;
+
+// software single step
+// there is no single step trap for user mode, so a step puts a temporary ebreak on every
+// instruction that can run after the current one and removes them at the next stop.
+
+pub const EBREAK: u32 = 0x0010_0073;
+pub const C_EBREAK: u16 = 0x9002;
+
+// length of the instruction whose first halfword is `low`
+pub fn instruction_len(low: u16) -> usize {
+    if low & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+
+// the breakpoint that fits in place of an instruction starting with `low`
+pub fn breakpoint_for(low: u16) -> ([u8; 4], usize) {
+    let len = instruction_len(low);
+    if len == 4 {
+        (EBREAK.to_le_bytes(), len)
+    } else {
+        let bytes = C_EBREAK.to_le_bytes();
+        ([bytes[0], bytes[1], 0, 0], len)
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> usize {
+    ((((value as u64) << (64 - bits)) as i64) >> (64 - bits)) as usize
+}
+
+// x0 isn't in `regs`, whose first entry is the pc
+fn reg(regs: &[usize; 32], index: u32) -> usize {
+    if index == 0 {
+        0
+    } else {
+        regs[index as usize]
+    }
+}
+
+// where execution can continue after the instruction `inst` at `pc`
+// `regs` is laid out as ArchProcess::user_registers, the upper half of a compressed
+// instruction is ignored
+pub fn next_pcs(pc: usize, inst: u32, regs: &[usize; 32]) -> Vec<usize> {
+    let mut pcs = Vec::new();
+    if instruction_len(inst as u16) == 4 {
+        let rs1 = (inst >> 15) & 0x1f;
+        match inst & 0x7f {
+            // jal
+            0x6f => {
+                let imm = ((inst >> 31) & 1) << 20
+                    | ((inst >> 21) & 0x3ff) << 1
+                    | ((inst >> 20) & 1) << 11
+                    | ((inst >> 12) & 0xff) << 12;
+                pcs.push(pc.wrapping_add(sign_extend(imm, 21)));
+            }
+            // jalr
+            0x67 => {
+                let imm = sign_extend(inst >> 20, 12);
+                pcs.push(reg(regs, rs1).wrapping_add(imm) & !1);
+            }
+            // conditional branches
+            0x63 => {
+                let imm = ((inst >> 31) & 1) << 12
+                    | ((inst >> 25) & 0x3f) << 5
+                    | ((inst >> 8) & 0xf) << 1
+                    | ((inst >> 7) & 1) << 11;
+                pcs.push(pc + 4);
+                pcs.push(pc.wrapping_add(sign_extend(imm, 13)));
+            }
+            _ => pcs.push(pc + 4),
+        }
+    } else {
+        let inst = inst & 0xffff;
+        let funct3 = inst >> 13;
+        match (inst & 0b11, funct3) {
+            // c.j
+            (0b01, 0b101) => {
+                let imm = ((inst >> 12) & 1) << 11
+                    | ((inst >> 11) & 1) << 4
+                    | ((inst >> 9) & 3) << 8
+                    | ((inst >> 8) & 1) << 10
+                    | ((inst >> 7) & 1) << 6
+                    | ((inst >> 6) & 1) << 7
+                    | ((inst >> 3) & 7) << 1
+                    | ((inst >> 2) & 1) << 5;
+                pcs.push(pc.wrapping_add(sign_extend(imm, 12)));
+            }
+            // c.beqz, c.bnez
+            (0b01, 0b110) | (0b01, 0b111) => {
+                let imm = ((inst >> 12) & 1) << 8
+                    | ((inst >> 10) & 3) << 3
+                    | ((inst >> 5) & 3) << 6
+                    | ((inst >> 3) & 3) << 1
+                    | ((inst >> 2) & 1) << 5;
+                pcs.push(pc + 2);
+                pcs.push(pc.wrapping_add(sign_extend(imm, 9)));
+            }
+            // c.jr, c.jalr
+            (0b10, 0b100) if (inst >> 2) & 0x1f == 0 && (inst >> 7) & 0x1f != 0 => {
+                pcs.push(reg(regs, (inst >> 7) & 0x1f) & !1);
+            }
+            _ => pcs.push(pc + 2),
+        }
+    }
+
+    pcs.dedup();
+    pcs
+}
+
+// make instructions written to memory visible to the instruction fetch
+pub fn flush_icache() {
+    unsafe {
+        asm!("fence.i");
+    }
+}
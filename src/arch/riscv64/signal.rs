@@ -0,0 +1,82 @@
+use super::process::{ArchProcess, TrapFrame};
+use super::trampoline;
+use crate::signal::*;
+use core::mem::size_of;
+
+// pushed on the user stack before a handler is called and popped by sigreturn, both through
+// crate::uaccess
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    pub trap_frame: TrapFrame,
+    pub blocked: SigSet,
+    pub signo: usize,
+}
+
+impl ArchProcess {
+    // the frame to push on the user stack before `sig` is handled, and the address it goes to
+    pub fn signal_frame(&self, sig: Signal, blocked: SigSet) -> (usize, SignalFrame) {
+        let trap_frame = unsafe { &*self.trap_frame };
+        let mut frame = SignalFrame {
+            trap_frame: *trap_frame,
+            blocked,
+            signo: sig.val(),
+        };
+        // user space doesn't see the kernel half, restore_signal_frame doesn't take it back
+        frame.trap_frame.kernel_satp = 0;
+        frame.trap_frame.kernel_sp = 0;
+        frame.trap_frame.kernel_trap = 0;
+        frame.trap_frame.kernel_hartid = 0;
+        frame.trap_frame.arch_proc = 0;
+        let sp = (trap_frame.sp.wrapping_sub(size_of::<SignalFrame>())) & !0xf;
+        (sp, frame)
+    }
+
+    // redirect the user context to `action.handler` once the frame is pushed at `sp`
+    pub fn enter_signal_handler(&mut self, sp: usize, sig: Signal, action: &SigAction) {
+        let trap_frame = unsafe { &mut *self.trap_frame };
+        trap_frame.sp = sp;
+        trap_frame.epc = action.handler;
+        trap_frame.a0 = sig.val();
+        // the handler returns into the sigreturn trampoline
+        trap_frame.ra = trampoline::SIGRETURN;
+    }
+
+    // where sigreturn finds the frame pushed for the handler
+    pub fn signal_frame_addr(&self) -> usize {
+        unsafe { (*self.trap_frame).sp }
+    }
+
+    // restore the user context saved in `frame`, returns the saved signal mask
+    pub fn restore_signal_frame(&mut self, frame: &SignalFrame) -> SigSet {
+        let trap_frame = unsafe { &mut *self.trap_frame };
+
+        // the kernel half of the trap frame must not be taken from user memory
+        let saved = frame.trap_frame;
+        let kernel_satp = trap_frame.kernel_satp;
+        let kernel_sp = trap_frame.kernel_sp;
+        let kernel_trap = trap_frame.kernel_trap;
+        let kernel_hartid = trap_frame.kernel_hartid;
+        let arch_proc = trap_frame.arch_proc;
+        *trap_frame = saved;
+        trap_frame.kernel_satp = kernel_satp;
+        trap_frame.kernel_sp = kernel_sp;
+        trap_frame.kernel_trap = kernel_trap;
+        trap_frame.kernel_hartid = kernel_hartid;
+        trap_frame.arch_proc = arch_proc;
+
+        frame.blocked
+    }
+}
+
+// signal raised by a synchronous exception (scause)
+pub fn exception_signal(code: usize) -> Signal {
+    match code {
+        0 | 4 | 6 => Signal::SIGBUS,  // misaligned access
+        1 | 5 | 7 => Signal::SIGSEGV, // access fault
+        2 => Signal::SIGILL,
+        3 => Signal::SIGTRAP,
+        12 | 13 | 15 => Signal::SIGSEGV, // page fault
+        _ => Signal::SIGILL,
+    }
+}
@@ -79,6 +79,10 @@ pub unsafe extern "C" fn start(main_func: usize) {
 
     clint::init();
 
+    // rdtime in user mode, for the vDSO
+    Csr::Mcounteren.write(Csr::Mcounteren.read() | 1 << 1);
+    Csr::Scounteren.write(Csr::Scounteren.read() | 1 << 1);
+
     let mut mie_val = Csr::Mie.read();
     mie_val |= 1 << 7;
     Csr::Mie.write(mie_val);
@@ -1,13 +1,28 @@
-use super::paging::*;
-use super::process::TrapFrame;
+use super::clint;
+use super::linux;
+use super::loader::Abi;
+use super::process::{TrapFrame, ARGS_MAX};
 use crate::arch::syscall::SysCallInfo;
+use crate::checkpoint;
+use crate::coredump;
+use crate::deadline::DeadlineParams;
+use crate::errno::Errno;
 use crate::fs::file_system;
+use crate::fs::FileTable;
+use crate::futex::*;
 use crate::graphics::*;
 use crate::process::*;
+use crate::ptrace;
+use crate::rlimit::*;
+use crate::signal::*;
+use crate::strace;
+use crate::uaccess::*;
 use crate::*;
+use alloc::rc::Rc;
 use alloc::string::*;
-use core::slice;
-use core::slice::from_raw_parts_mut;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 
 pub struct RiscvSysCallInfo {
     trap_frame: *mut TrapFrame,
@@ -30,21 +45,6 @@ impl SysCallInfo for RiscvSysCallInfo {
             arg
         }
     }
-
-    fn get_arg_ptr<T>(&self, idx: usize) -> *mut T {
-        let arg = self.get_arg_raw(idx);
-        let pm = unsafe { process_manager() };
-        let running = pm.running;
-        let page_table = unsafe {
-            get_process_mut!(pm.ptable_lock_mut(), running)
-                .unwrap()
-                .arch_proc
-                .page_table
-                .as_mut()
-        };
-        let ptr = virt_to_phys(page_table, arg).unwrap();
-        ptr as *mut T
-    }
 }
 
 pub unsafe fn syscall_info() -> RiscvSysCallInfo {
@@ -58,116 +58,511 @@ pub unsafe fn syscall_info() -> RiscvSysCallInfo {
     }
 }
 
-pub unsafe fn sys_read(_pm: &mut ProcessManager, fd: usize, buf: *mut u8, count: usize) -> usize {
+// descriptor table of the running process
+fn files(pm: &mut ProcessManager) -> Rc<RefCell<FileTable>> {
+    let running = pm.running;
+    get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .files
+        .clone()
+}
+
+// reads longer than MAX_RW_COUNT are cut short
+pub unsafe fn sys_read(pm: &mut ProcessManager, fd: usize, buf: usize, count: usize) -> usize {
     if fd == 0 || fd == 1 || fd == 2 {
         return 0;
     }
 
+    let files = files(pm);
     let fs = file_system();
-    let res = fs.lock().read(fd, from_raw_parts_mut(buf, count));
-    if let Err(_) = res {
-        return -1_isize as usize;
-    } else {
-        return res.unwrap();
+    let mut data = vec![0; count.min(MAX_RW_COUNT)];
+    let res = fs.lock().read_in(&mut files.borrow_mut(), fd, &mut data);
+    let read = match res {
+        Ok(read) => read,
+        Err(err) => return syscall_error(err),
+    };
+    match copy_to_user(pm, buf, &data[..read]) {
+        Ok(_) => read,
+        Err(err) => syscall_error(err),
     }
 }
 
-pub unsafe fn sys_write(_pm: &mut ProcessManager, _fd: usize, buf: *mut u8, count: usize) -> usize {
-    // let pagetable = pm.ptable[pm.running].arch_proc.page_table.as_mut();
-    // let buf_phys = paging::virt_to_phys(pagetable, buf as usize).unwrap() as *mut u8;
-    for i in 0..count {
-        print!("{}", buf.add(i).read() as char);
+// returns the bytes written, writes longer than MAX_RW_COUNT are cut short
+pub unsafe fn sys_write(pm: &mut ProcessManager, _fd: usize, buf: usize, count: usize) -> usize {
+    let mut data = vec![0; count.min(MAX_RW_COUNT)];
+    if let Err(err) = copy_from_user(pm, buf, &mut data) {
+        return syscall_error(err);
+    }
+    for ch in data.iter() {
+        print!("{}", *ch as char);
     }
 
-    0
+    data.len()
 }
 
-pub unsafe fn sys_seek(_pm: &mut ProcessManager, fd: usize, offset: usize, whence: u32) -> usize {
-    let fs = file_system();
-    let res = fs.lock().seek(fd, offset as isize, whence);
-    if let Err(_) = res {
-        return -1_isize as usize;
-    } else {
-        return res.unwrap();
+pub unsafe fn sys_seek(pm: &mut ProcessManager, fd: usize, offset: usize, whence: u32) -> usize {
+    match files(pm).borrow_mut().seek(fd, offset as isize, whence) {
+        Ok(offset) => offset,
+        Err(err) => syscall_error(err),
     }
 }
 
-pub unsafe fn sys_open(_pm: &mut ProcessManager, path: *mut u8) -> usize {
+pub unsafe fn sys_open(pm: &mut ProcessManager, path: usize) -> usize {
     let fs = file_system();
-    let mut index = 0;
-    let mut path_str = String::new();
-    loop {
-        let ch = path.add(index).read();
-        if ch == 0 {
-            break;
-        }
+    let path_str = match user_path(pm, path) {
+        Ok(path) => path,
+        Err(err) => return syscall_error(err),
+    };
+    let files = files(pm);
+    let fd = fs.lock().open_file_in(&mut files.borrow_mut(), &path_str);
+    match fd {
+        Ok(fd) => fd,
+        Err(err) => syscall_error(err),
+    }
+}
 
-        path_str.push(ch as char);
-        index += 1;
+pub unsafe fn sys_sleep(pm: &mut ProcessManager, delay: usize) -> usize {
+    let running = pm.running;
+    let pid = get_process!(pm.ptable_lock(), running).unwrap().pid;
+    match pm.sleep(pid, delay) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
     }
-    let fd = fs.lock().open_file(&path_str);
-    if let Err(_) = fd {
-        return -1_isize as usize;
-    } else {
-        return fd.unwrap();
+}
+
+pub unsafe fn sys_wait_exit(pm: &mut ProcessManager) -> usize {
+    match pm.wait_exit() {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
     }
 }
 
-pub unsafe fn sys_sleep(pm: &mut ProcessManager, delay: usize) -> usize {
+pub unsafe fn sys_fork(pm: &mut ProcessManager) -> usize {
+    match pm.fork().and_then(|pid| pm.ready(pid).map(|_| pid)) {
+        Ok(pid) => pid,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_clone(pm: &mut ProcessManager, flags: usize, stack: usize, tls: usize) -> usize {
+    // a thread shares the address space and the signal handlers of its process
+    if flags & CLONE_THREAD != 0 && flags & (CLONE_VM | CLONE_SIGHAND) != CLONE_VM | CLONE_SIGHAND {
+        return Errno::EINVAL.ret();
+    }
+
+    let res = pm
+        .clone_process(flags, stack, tls)
+        .and_then(|pid| pm.ready(pid).map(|_| pid));
+    match res {
+        Ok(pid) => pid,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_thread_join(pm: &mut ProcessManager, tid: usize) -> usize {
+    match pm.join_thread(tid) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_getpid(pm: &mut ProcessManager) -> usize {
+    let running = pm.running;
+    get_process!(pm.ptable_lock(), running).unwrap().tgid
+}
+
+pub unsafe fn sys_gettid(pm: &mut ProcessManager) -> usize {
+    pm.running
+}
+
+pub unsafe fn sys_exit(pm: &mut ProcessManager) -> usize {
     let running = pm.running;
     let pid = get_process!(pm.ptable_lock(), running).unwrap().pid;
-    pm.sleep(pid, delay).expect("process");
+    match pm.kill(pid) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
 
-    0
+pub unsafe fn sys_kill(pm: &mut ProcessManager, pid: usize, signo: usize) -> usize {
+    let target = match get_process!(pm.ptable_lock(), pid) {
+        Ok(proc) if proc.state != State::Free => Some(proc.is_user()),
+        _ => None,
+    };
+    match target {
+        None => return Errno::ESRCH.ret(),
+        Some(false) => return Errno::EPERM.ret(),
+        Some(true) => {}
+    }
+
+    // signal 0 only checks that the process exists
+    if signo == 0 {
+        return 0;
+    }
+
+    let sig = match Signal::from(signo) {
+        Some(sig) => sig,
+        None => return Errno::EINVAL.ret(),
+    };
+    match pm.send_signal(pid, sig) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
 }
 
-pub unsafe fn sys_wait_exit(pm: &mut ProcessManager) -> usize {
-    pm.wait_exit().expect("process");
+// `act` and `oldact` may be 0
+pub unsafe fn sys_sigaction(
+    pm: &mut ProcessManager,
+    signo: usize,
+    act: usize,
+    oldact: usize,
+) -> usize {
+    let sig = match Signal::from(signo) {
+        Some(sig) => sig,
+        None => return Errno::EINVAL.ret(),
+    };
+
+    let running = pm.running;
+    let old = match pm.get_signal_action(running, sig) {
+        Ok(old) => old,
+        Err(err) => return syscall_error(err),
+    };
+    if act != 0 {
+        let act = match get_user(pm, act) {
+            Ok(act) => act,
+            Err(err) => return syscall_error(err),
+        };
+        match pm.set_signal_action(running, sig, act) {
+            Ok(Some(_)) => {}
+            // SIGKILL and SIGSTOP
+            Ok(None) => return Errno::EINVAL.ret(),
+            Err(err) => return syscall_error(err),
+        }
+    }
+    if oldact != 0 {
+        if let Err(err) = put_user(pm, oldact, &old) {
+            return syscall_error(err);
+        }
+    }
 
     0
 }
 
-pub unsafe fn sys_fork(pm: &mut ProcessManager) -> usize {
-    let pid = pm.fork(pm.running);
-    let trapframe = get_process!(pm.ptable_lock(), pid)
-        .unwrap()
-        .arch_proc
-        .trap_frame;
-    // println!("[hobo0xcc] epc: {:#018x}", (*trapframe).epc);
-    (*trapframe).a0 = pid;
-    (*trapframe).epc += 4;
-    pm.ready(pid).expect("process");
+// `set` and `oldset` may be 0
+pub unsafe fn sys_sigprocmask(
+    pm: &mut ProcessManager,
+    how: usize,
+    set: usize,
+    oldset: usize,
+) -> usize {
+    let running = pm.running;
+    let old = match pm.get_signal_mask(running) {
+        Ok(old) => old,
+        Err(err) => return syscall_error(err),
+    };
+    if set != 0 {
+        let set: SigSet = match get_user(pm, set) {
+            Ok(set) => set,
+            Err(err) => return syscall_error(err),
+        };
+        match pm.set_signal_mask(running, how, set) {
+            Ok(Some(_)) => {}
+            // an unknown `how`
+            Ok(None) => return Errno::EINVAL.ret(),
+            Err(err) => return syscall_error(err),
+        }
+    }
+    if oldset != 0 {
+        if let Err(err) = put_user(pm, oldset, &old) {
+            return syscall_error(err);
+        }
+    }
 
     0
 }
 
-pub unsafe fn sys_kill(pm: &mut ProcessManager) -> usize {
+pub unsafe fn sys_sigreturn(pm: &mut ProcessManager) -> usize {
+    match pm.signal_return() {
+        Ok(ret) => ret,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// arg3 is the timeout in ticks for FUTEX_WAIT (0 waits forever)
+// and the maximum number of waiters to requeue for FUTEX_REQUEUE
+pub unsafe fn sys_futex(
+    pm: &mut ProcessManager,
+    uaddr: usize,
+    op: usize,
+    val: usize,
+    arg3: usize,
+    uaddr2: usize,
+) -> usize {
     let running = pm.running;
-    let pid = get_process!(pm.ptable_lock(), running).unwrap().pid;
-    pm.kill(pid).expect("process");
+    let (addr_space, max_memory) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, running).unwrap();
+        (
+            proc.arch_proc.addr_space().clone(),
+            proc.rlimits.cur(RLIMIT_AS),
+        )
+    };
+    // an aligned word doesn't cross a page, so its page is all there is to check
+    // the word gets a page of its own if it can, every word still on the zero page has one key
+    let resolve = |vaddr: usize| {
+        if vaddr % 4 != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let mut addr_space = addr_space.borrow_mut();
+        addr_space
+            .fault_in(vaddr, true, max_memory)
+            .or_else(|| addr_space.fault_in(vaddr, false, max_memory))
+            .ok_or(Errno::EFAULT)
+    };
+    let key = match resolve(uaddr) {
+        Ok(key) => key,
+        Err(err) => return err.ret(),
+    };
 
-    0
+    let res = match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let timeout = if arg3 == 0 { None } else { Some(arg3) };
+            futex_wait(key, val as u32, timeout).map(|_| 0)
+        }
+        FUTEX_WAKE => futex_wake(key, val),
+        FUTEX_REQUEUE => {
+            let key2 = match resolve(uaddr2) {
+                Ok(key) => key,
+                Err(err) => return err.ret(),
+            };
+            futex_requeue(key, val, key2, arg3)
+        }
+        _ => return Errno::EINVAL.ret(),
+    };
+
+    match res {
+        Ok(ret) => ret,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// the return value of a system call failed with `err`
+pub fn syscall_error<E: Into<Errno>>(err: E) -> usize {
+    err.into().ret()
+}
+
+// `name` is null for an anonymous semaphore, which is shared only with forked children
+// a count past isize::MAX is negative and fails with EINVAL
+pub unsafe fn sys_sem_open(pm: &mut ProcessManager, name: usize, count: usize) -> usize {
+    let name_str = if name == 0 {
+        None
+    } else {
+        match user_path(pm, name) {
+            Ok(name) => Some(name),
+            Err(err) => return syscall_error(err),
+        }
+    };
+
+    match pm.open_semaphore(name_str.as_deref(), count as isize) {
+        Ok(sid) => sid,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// `timeout` is in ticks, 0 waits forever
+pub unsafe fn sys_sem_wait(pm: &mut ProcessManager, sid: usize, timeout: usize) -> usize {
+    if let Err(err) = pm.check_semaphore_access(sid) {
+        return syscall_error(err);
+    }
+
+    let timeout = if timeout == 0 { None } else { Some(timeout) };
+    match pm.wait_semaphore_timeout(sid, timeout) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_sem_trywait(pm: &mut ProcessManager, sid: usize) -> usize {
+    let res = pm
+        .check_semaphore_access(sid)
+        .and_then(|_| pm.try_wait_semaphore(sid));
+    match res {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_sem_post(pm: &mut ProcessManager, sid: usize) -> usize {
+    let res = pm
+        .check_semaphore_access(sid)
+        .and_then(|_| pm.signal_semaphore(sid));
+    match res {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_sem_close(pm: &mut ProcessManager, sid: usize) -> usize {
+    let running = pm.running;
+    let tgid = get_process!(pm.ptable_lock(), running).unwrap().tgid;
+    let res = pm
+        .check_semaphore_access(sid)
+        .and_then(|_| pm.close_semaphore(sid, tgid));
+    match res {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// delete the semaphore for every process, the waiters fail with EIDRM
+pub unsafe fn sys_sem_delete(pm: &mut ProcessManager, sid: usize) -> usize {
+    let res = pm
+        .check_semaphore_access(sid)
+        .and_then(|_| pm.delete_semaphore(sid));
+    match res {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// reserve CPU time for the calling thread, a null `params` drops the reservation
+// fails with EBUSY when the reservation isn't admitted
+pub unsafe fn sys_sched_deadline(pm: &mut ProcessManager, params: usize) -> usize {
+    let params = if params == 0 {
+        None
+    } else {
+        match get_user::<DeadlineParams>(pm, params) {
+            Ok(params) => Some(params),
+            Err(err) => return syscall_error(err),
+        }
+    };
+
+    let running = pm.running;
+    match pm.set_deadline(running, params) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// save `pid`, a stopped process or the caller, to `path`
+// returns 0, or CHECKPOINT_RESTORED in a process restored from the checkpoint of itself
+pub unsafe fn sys_checkpoint(pm: &mut ProcessManager, pid: usize, path: usize) -> usize {
+    let path = match user_path(pm, path) {
+        Ok(path) => path,
+        Err(err) => return syscall_error(err),
+    };
+    match checkpoint::checkpoint(pid, &path) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// start a copy of the process saved in `path`, returns its pid
+pub unsafe fn sys_restore(pm: &mut ProcessManager, path: usize) -> usize {
+    let path = match user_path(pm, path) {
+        Ok(path) => path,
+        Err(err) => return syscall_error(err),
+    };
+    match checkpoint::restore(&path) {
+        Ok(pid) => pid,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// switch core dumps on or off, returns the previous setting
+pub fn sys_coredump(_pm: &mut ProcessManager, enable: usize) -> usize {
+    coredump::set_enabled(enable != 0) as usize
+}
+
+pub fn sys_ptrace(
+    pm: &mut ProcessManager,
+    request: usize,
+    pid: usize,
+    addr: usize,
+    data: usize,
+) -> usize {
+    match ptrace::request(pm, request, pid, addr, data) {
+        Ok(ret) => ret,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// wait for the next stop or the exit of a traced process, `status` is encoded as by waitpid
+pub fn sys_ptrace_wait(pm: &mut ProcessManager, pid: usize, status: usize) -> usize {
+    let result = ptrace::wait(pm, pid).and_then(|value| match status {
+        0 => Ok(()),
+        _ => put_user(pm, status, &(value as u32)),
+    });
+    match result {
+        Ok(_) => pid,
+        Err(err) => syscall_error(err),
+    }
 }
 
-pub unsafe fn sys_execve(pm: &mut ProcessManager, path: *mut u8) -> usize {
-    // pm.ptable[pm.running].arch_proc.free();
+pub const PATH_MAX: usize = 4096;
 
-    pm.setup_process(pm.running).expect("process");
+// a path or a name at `vaddr` of the running process, longer than PATH_MAX is EINVAL
+pub fn user_path(pm: &mut ProcessManager, vaddr: usize) -> Result<String, ProcessError> {
+    strncpy_from_user(pm, vaddr, PATH_MAX)?.ok_or(ProcessError::InvalidArgument)
+}
 
-    let mut path_str = String::new();
-    let mut index = 0;
+// the NULL terminated array of strings at `vaddr` of the running process, 0 is an empty array
+// `size` counts the bytes they take on the initial stack, which fails with E2BIG past ARGS_MAX
+fn user_string_array(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    size: &mut usize,
+) -> Result<Vec<String>, ProcessError> {
+    let mut strings = Vec::new();
+    if vaddr == 0 {
+        return Ok(strings);
+    }
     loop {
-        let ch = path.add(index).read();
-        if ch == 0 {
-            break;
+        let addr = vaddr
+            .checked_add(strings.len() * 8)
+            .ok_or(ProcessError::BadAddress)?;
+        let ptr: usize = get_user(pm, addr)?;
+        *size += 8;
+        if ptr == 0 {
+            return Ok(strings);
         }
 
-        path_str.push(ch as char);
-        index += 1;
+        let max = ARGS_MAX.saturating_sub(*size);
+        let string = strncpy_from_user(pm, ptr, max)?.ok_or(ProcessError::ArgumentListTooLong)?;
+        *size += string.len() + 1;
+        strings.push(string);
     }
-    pm.load_program(pm.running, &path_str).expect("process");
+}
+
+// path, argv and envp of execve, copied before the address space they are in is replaced
+fn execve_args(
+    pm: &mut ProcessManager,
+    path: usize,
+    argv: usize,
+    envp: usize,
+) -> Result<(String, Vec<String>, Vec<String>), ProcessError> {
+    let path = user_path(pm, path)?;
+    let mut size = 0;
+    let argv = user_string_array(pm, argv, &mut size)?;
+    let envp = user_string_array(pm, envp, &mut size)?;
+    Ok((path, argv, envp))
+}
+
+// the new program replaces the caller only once it is loaded, errors return to the caller
+pub unsafe fn sys_execve(pm: &mut ProcessManager, path: usize, argv: usize, envp: usize) -> usize {
+    let (path, argv, envp) = match execve_args(pm, path, argv, envp) {
+        Ok(args) => args,
+        Err(err) => return syscall_error(err),
+    };
+    let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+    let envp: Vec<&str> = envp.iter().map(|env| env.as_str()).collect();
 
     let running = pm.running;
+    if let Err(err) = pm.load_program(running, &path, &argv, &envp) {
+        return syscall_error(err);
+    }
+    pm.kill_other_threads(running).expect("process");
+
     get_process_mut!(pm.ptable_lock_mut(), running)
         .unwrap()
         .arch_proc
@@ -176,9 +571,33 @@ pub unsafe fn sys_execve(pm: &mut ProcessManager, path: *mut u8) -> usize {
     0
 }
 
+pub unsafe fn sys_getrlimit(pm: &mut ProcessManager, resource: usize, rlim: usize) -> usize {
+    let running = pm.running;
+    let result = pm
+        .get_rlimit(running, resource)
+        .and_then(|limit| put_user(pm, rlim, &limit));
+    match result {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+pub unsafe fn sys_setrlimit(pm: &mut ProcessManager, resource: usize, rlim: usize) -> usize {
+    let limit: RLimit = match get_user(pm, rlim) {
+        Ok(limit) => limit,
+        Err(err) => return syscall_error(err),
+    };
+    let running = pm.running;
+    match pm.set_rlimit(running, resource, limit) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
+}
+
+// titles longer than PATH_MAX are EINVAL
 pub unsafe fn sys_create_window(
-    _pm: &mut ProcessManager,
-    title: *mut u8,
+    pm: &mut ProcessManager,
+    title: usize,
     title_len: usize,
     x: usize,
     y: usize,
@@ -186,9 +605,15 @@ pub unsafe fn sys_create_window(
     height: usize,
 ) -> usize {
     let wm = window_manager();
+    if title_len > PATH_MAX {
+        return syscall_error(ProcessError::InvalidArgument);
+    }
+    let mut title_bytes = vec![0; title_len];
+    if let Err(err) = copy_from_user(pm, title, &mut title_bytes) {
+        return syscall_error(err);
+    }
     let mut title_str = String::new();
-    let title_slice = slice::from_raw_parts(title, title_len);
-    for ch in title_slice.iter() {
+    for ch in title_bytes.iter() {
         title_str.push(*ch as char);
     }
 
@@ -198,58 +623,133 @@ pub unsafe fn sys_create_window(
     id
 }
 
+// `vaddr` 0 lets the kernel choose the address, returns the address of the frame buffer
 pub unsafe fn sys_map_window(pm: &mut ProcessManager, window_id: usize, vaddr: usize) -> usize {
     let pid = pm.running;
-    let page_table = get_process_mut!(pm.ptable_lock_mut(), pid)
-        .unwrap()
-        .arch_proc
-        .page_table
-        .as_mut();
-
     let arena = object_arena();
 
-    let window = arena.get(window_id);
+    // the id may name the mouse or the desktop as well
+    let window = arena
+        .get(window_id)
+        .and_then(|object| (&**object).as_any().downcast_ref::<Window>());
     let window = if let Some(window) = window {
-        (&**window).as_any().downcast_ref::<Window>().unwrap()
+        window
     } else {
-        return 1;
+        return syscall_error(ProcessError::InvalidArgument);
     };
 
     let window_frame = window.get_frame();
     let size = window_frame.width * 4 * window_frame.height;
-    map_range(
-        page_table,
+
+    let (addr_space, max_memory) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, pid).unwrap();
+        (
+            proc.arch_proc.addr_space().clone(),
+            proc.rlimits.cur(RLIMIT_AS),
+        )
+    };
+    let memory = addr_space.borrow().memory;
+    if memory.saturating_add(size as usize) > max_memory {
+        return syscall_error(ProcessError::ResourceLimit(RLIMIT_AS));
+    }
+
+    match addr_space.borrow_mut().map_window(
+        window_id,
         vaddr,
         window_frame.buffer as usize,
         size as usize,
-        EntryBits::R.val() | EntryBits::W.val() | EntryBits::U.val(),
-    );
-
-    0
+    ) {
+        Ok(vaddr) => vaddr,
+        Err(err) => syscall_error(err),
+    }
 }
 
 pub unsafe fn sys_sync_window(_pm: &mut ProcessManager, window_id: usize) -> usize {
     let wm = window_manager();
-    wm.update_window_frame(window_id);
+    match wm.update_window_frame(window_id) {
+        Some(_) => 0,
+        None => syscall_error(ProcessError::InvalidArgument),
+    }
+}
 
-    0
+// switch system call tracing of `pid` (0 for the caller) on or off
+pub fn sys_strace(pm: &mut ProcessManager, pid: usize, flags: usize) -> usize {
+    let pid = if pid == 0 { pm.running } else { pid };
+    match pm.set_strace(pid, flags) {
+        Ok(_) => 0,
+        Err(err) => syscall_error(err),
+    }
 }
 
 pub unsafe fn execute_syscall() -> usize {
     let info = syscall_info();
     let pm = process_manager();
-    let syscall_number = info.get_arg_raw(0);
+
+    let running = pm.running;
+    let (flags, abi) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, running).unwrap();
+        let abi = proc.arch_proc.addr_space().borrow().exec_info.abi;
+        (proc.strace, abi)
+    };
+    let dispatch = |pm: &mut ProcessManager| match abi {
+        Abi::Native => dispatch_syscall(pm, &info, info.get_arg_raw(0)),
+        Abi::Linux => linux::dispatch(pm, &info),
+    };
+    if flags & strace::STRACE_SYSCALLS == 0 {
+        return dispatch(pm);
+    }
+    let name = get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .name
+        .clone();
+
+    // the arguments are decoded before the call, which may change or free them
+    let (call, desc) = match abi {
+        Abi::Native => {
+            let number = info.get_arg_raw(0);
+            let args: Vec<usize> = (1..=6).map(|idx| info.get_arg_raw(idx)).collect();
+            let call = strace::format_call(pm, running, number, &args);
+            (call, strace::describe(number))
+        }
+        Abi::Linux => {
+            let number = info.get_arg_raw(7);
+            let args: Vec<usize> = (0..=5).map(|idx| info.get_arg_raw(idx)).collect();
+            let call = strace::format_linux_call(pm, running, number, &args);
+            (call, strace::describe_linux(number))
+        }
+    };
+    let returns = desc.map_or(true, |desc| desc.returns);
+    if !returns {
+        strace::log_call(&name, running, &call, None, 0);
+    }
+    let start = clint::now();
+    let ret_val = dispatch(pm);
+    let elapsed = (clint::now() - start) / (clint::TIMEBASE_FREQ / 1_000_000);
+    if returns {
+        strace::log_call(&name, running, &call, Some(ret_val), elapsed);
+    }
+
+    ret_val
+}
+
+unsafe fn dispatch_syscall(
+    pm: &mut ProcessManager,
+    info: &RiscvSysCallInfo,
+    syscall_number: usize,
+) -> usize {
     let ret_val = match syscall_number {
         0 => sys_read(
             pm,
             info.get_arg_raw(1),
-            info.get_arg_ptr(2),
+            info.get_arg_raw(2),
             info.get_arg_raw(3),
         ),
         1 => sys_write(
             pm,
             info.get_arg_raw(1),
-            info.get_arg_ptr::<u8>(2),
+            info.get_arg_raw(2),
             info.get_arg_raw(3),
         ),
         2 => sys_seek(
@@ -258,15 +758,59 @@ pub unsafe fn execute_syscall() -> usize {
             info.get_arg_raw(2),
             info.get_arg_raw(3) as u32,
         ),
-        3 => sys_open(pm, info.get_arg_ptr(1)),
+        3 => sys_open(pm, info.get_arg_raw(1)),
         35 => sys_sleep(pm, info.get_arg_raw(1)),
         56 => sys_wait_exit(pm),
         57 => sys_fork(pm),
-        62 => sys_kill(pm),
-        63 => sys_execve(pm, info.get_arg_ptr(1)),
+        62 => sys_exit(pm),
+        63 => sys_execve(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+        ),
+        98 => sys_futex(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+            info.get_arg_raw(4),
+            info.get_arg_raw(5),
+        ),
+        117 => sys_ptrace(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+            info.get_arg_raw(4),
+        ),
+        129 => sys_kill(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        134 => sys_sigaction(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+        ),
+        135 => sys_sigprocmask(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+        ),
+        139 => sys_sigreturn(pm),
+        172 => sys_getpid(pm),
+        178 => sys_gettid(pm),
+        163 => sys_getrlimit(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        164 => sys_setrlimit(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        220 => sys_clone(
+            pm,
+            info.get_arg_raw(1),
+            info.get_arg_raw(2),
+            info.get_arg_raw(3),
+        ),
         1000 => sys_create_window(
             pm,
-            info.get_arg_ptr(1),
+            info.get_arg_raw(1),
             info.get_arg_raw(2),
             info.get_arg_raw(3),
             info.get_arg_raw(4),
@@ -275,7 +819,20 @@ pub unsafe fn execute_syscall() -> usize {
         ),
         1001 => sys_map_window(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
         1002 => sys_sync_window(pm, info.get_arg_raw(1)),
-        _ => panic!("not implemented: {}", syscall_number),
+        1100 => sys_thread_join(pm, info.get_arg_raw(1)),
+        1200 => sys_sem_open(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        1201 => sys_sem_wait(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        1202 => sys_sem_trywait(pm, info.get_arg_raw(1)),
+        1203 => sys_sem_post(pm, info.get_arg_raw(1)),
+        1204 => sys_sem_close(pm, info.get_arg_raw(1)),
+        1205 => sys_sem_delete(pm, info.get_arg_raw(1)),
+        1300 => sys_sched_deadline(pm, info.get_arg_raw(1)),
+        1400 => sys_checkpoint(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        1401 => sys_restore(pm, info.get_arg_raw(1)),
+        1500 => sys_coredump(pm, info.get_arg_raw(1)),
+        1600 => sys_ptrace_wait(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        1700 => sys_strace(pm, info.get_arg_raw(1), info.get_arg_raw(2)),
+        _ => syscall_error(ProcessError::NotImplemented),
     };
 
     ret_val
@@ -5,11 +5,25 @@ extern "C" {
     pub fn uservec();
     pub fn userret(trapframe: usize, satp: usize);
     pub fn killme();
+    pub fn sigreturn_trampoline();
 }
 
 pub const TRAMPOLINE: usize = (1_usize << (9 + 9 + 9 + 12 - 1)) - 0x1000;
 pub const TRAPFRAME: usize = TRAMPOLINE - 0x1000;
 pub const KILLME: usize = TRAPFRAME - 0x1000;
+pub const SIGRETURN: usize = KILLME - 0x1000;
+// the vDSO, the code page finds the data page right above it
+pub const VDSO_DATA: usize = SIGRETURN - 0x1000;
+pub const VDSO_CODE: usize = VDSO_DATA - 0x1000;
+// trap frames of the threads other than the main one (which uses TRAPFRAME)
+pub const MAX_THREADS: usize = 64;
+pub const THREAD_TRAPFRAME: usize = VDSO_CODE - 0x1000;
+// the lowest address of the pages mapped by the kernel
+pub const KERNEL_MAPPED_START: usize = THREAD_TRAPFRAME - (MAX_THREADS - 1) * 0x1000;
+
+pub fn thread_trapframe(slot: usize) -> usize {
+    THREAD_TRAPFRAME - slot * 0x1000
+}
 
 // from xv6
 global_asm!(
@@ -110,5 +124,11 @@ global_asm!(
     "killme:",
     "  li a0, 62",
     "  ecall",
+    // signal handlers return here
+    ".section sigretsec",
+    ".globl sigreturn_trampoline",
+    "sigreturn_trampoline:",
+    "  li a0, 139",
+    "  ecall",
     ".section .text"
 );
@@ -0,0 +1,130 @@
+use super::clint;
+use super::interrupt::{interrupt_disable, interrupt_restore};
+use super::trampoline;
+use core::arch::global_asm;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{fence, Ordering};
+
+// the vDSO
+// two pages mapped into every process next to the trampolines let it read the time and its pid
+// without a trap. the data page belongs to the address space and is read only for user mode, the
+// kernel writes it. the code page is shared by every process and finds the data page right above
+// itself. user mode reads mtime with rdtime, which the counteren registers allow (see start).
+//
+// a reader takes `seq`, which is odd while the kernel writes the page, reads the fields and reads
+// `seq` again; if it was odd or has changed the read is retried.
+//
+// entry points, called as C functions:
+// - VDSO_CLOCK_GETTIME: int clock_gettime(int clock, struct timespec *tp), the clocks of the
+//   Linux system call but CLOCK_THREAD_CPUTIME_ID, -EINVAL for the others
+// - VDSO_GETPID: int getpid(void)
+// processes which share an address space without CLONE_THREAD see the pid of the first one.
+
+pub const VDSO_DATA: usize = trampoline::VDSO_DATA;
+pub const VDSO_CODE: usize = trampoline::VDSO_CODE;
+pub const VDSO_CLOCK_GETTIME: usize = VDSO_CODE;
+pub const VDSO_GETPID: usize = VDSO_CODE + 4;
+
+// the data page, the offsets are used by the code page
+#[repr(C)]
+pub struct VdsoData {
+    pub seq: u64,           // 0
+    pub timebase_freq: u64, // 8, of mtime
+    pub boot_time: u64,     // 16, mtime when the kernel started, the clocks count from there
+    pub ticks_per_sec: u64, // 24, the unit of cpu_time
+    pub pid: u64,           // 32, the thread group id
+    pub cpu_time: u64,      // 40, ticks spent running by the whole process
+}
+
+impl VdsoData {
+    // the values shared by every process, the page is zeroed before
+    pub fn init(&mut self) {
+        self.timebase_freq = clint::TIMEBASE_FREQ as u64;
+        self.boot_time = clint::boot_time() as u64;
+        self.ticks_per_sec = clint::TICKS_PER_SEC as u64;
+    }
+}
+
+// change the data page at `data` while readers may retry but never see half of it
+pub unsafe fn update<F: FnOnce(&mut VdsoData)>(data: *mut VdsoData, f: F) {
+    let mask = interrupt_disable();
+    let seq = addr_of_mut!((*data).seq);
+    seq.write_volatile(seq.read_volatile() + 1);
+    fence(Ordering::SeqCst);
+    f(&mut *data);
+    fence(Ordering::SeqCst);
+    seq.write_volatile(seq.read_volatile() + 1);
+    interrupt_restore(mask);
+}
+
+extern "C" {
+    pub fn vdso();
+}
+
+global_asm!(
+    ".section vdsosec",
+    ".globl vdso",
+    "vdso:",
+    // the entry points are at fixed offsets
+    ".option push",
+    ".option norvc",
+    "  j vdso_clock_gettime",
+    "  j vdso_getpid",
+    ".option pop",
+    "vdso_clock_gettime:",
+    // the data page is the next one
+    "  auipc t0, 1",
+    "  srli t0, t0, 12",
+    "  slli t0, t0, 12",
+    "  li t1, 2", // CLOCK_PROCESS_CPUTIME_ID
+    "  beq a0, t1, 3f",
+    "  li t1, 3", // CLOCK_THREAD_CPUTIME_ID
+    "  beq a0, t1, 5f",
+    "  li t1, 7", // CLOCK_BOOTTIME
+    "  bgtu a0, t1, 5f",
+    // the other clocks count mtime from boot
+    "1:",
+    "  ld t1, 0(t0)",
+    "  andi t2, t1, 1",
+    "  bnez t2, 1b",
+    "  fence r, r",
+    "  ld t3, 8(t0)",
+    "  ld t4, 16(t0)",
+    "  fence r, r",
+    "  ld t2, 0(t0)",
+    "  bne t1, t2, 1b",
+    "  rdtime t5",
+    "  sub t5, t5, t4",
+    "  j 4f",
+    "3:",
+    "  ld t1, 0(t0)",
+    "  andi t2, t1, 1",
+    "  bnez t2, 3b",
+    "  fence r, r",
+    "  ld t3, 24(t0)",
+    "  ld t5, 40(t0)",
+    "  fence r, r",
+    "  ld t2, 0(t0)",
+    "  bne t1, t2, 3b",
+    // t5 counts at t3 per second, struct timespec { time_t tv_sec; long tv_nsec; }
+    "4:",
+    "  divu t1, t5, t3",
+    "  remu t2, t5, t3",
+    "  li t4, 1000000000",
+    "  divu t4, t4, t3",
+    "  mul t2, t2, t4",
+    "  sd t1, 0(a1)",
+    "  sd t2, 8(a1)",
+    "  li a0, 0",
+    "  ret",
+    "5:",
+    "  li a0, -22", // EINVAL
+    "  ret",
+    "vdso_getpid:",
+    "  auipc t0, 1",
+    "  srli t0, t0, 12",
+    "  slli t0, t0, 12",
+    "  ld a0, 32(t0)",
+    "  ret",
+    ".section .text"
+);
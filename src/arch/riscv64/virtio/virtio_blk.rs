@@ -2,6 +2,7 @@ use super::super::virtio;
 use super::super::virtio::*;
 use crate::fs;
 use crate::process::process_manager;
+use crate::sync::WaitQueue;
 use alloc::alloc::dealloc;
 use alloc::alloc::{alloc, alloc_zeroed};
 use alloc::collections::BTreeSet;
@@ -46,6 +47,11 @@ impl RequestType {
     }
 }
 
+// the status byte written by the device
+pub const VIRTIO_BLK_S_OK: u8 = 0;
+pub const VIRTIO_BLK_S_IOERR: u8 = 1;
+pub const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
 #[repr(C, packed)]
 #[allow(dead_code)]
 #[derive(Copy, Clone)]
@@ -65,7 +71,6 @@ impl VirtioBlkRequest {
     }
 }
 
-#[derive(Clone)]
 #[allow(dead_code)]
 pub struct VirtioBlk {
     base: usize,
@@ -75,7 +80,9 @@ pub struct VirtioBlk {
     desc_indexes: Option<Vec<u16>>,
     ack_used_index: u16,
     sid: usize, // Semaphore id
-    pid: usize,
+    io_queue: WaitQueue,
+    done: bool,                        // the submitted request has completed
+    result: Result<(), fs::DiskError>, // of the completed request
 }
 
 impl VirtioBlk {
@@ -88,8 +95,10 @@ impl VirtioBlk {
             status: [0; VIRTIO_RING_SIZE],
             desc_indexes: None,
             ack_used_index: 0,
-            sid: pm.create_semaphore(1),
-            pid: 0,
+            sid: pm.create_mutex(),
+            io_queue: WaitQueue::named("virtio-blk"),
+            done: false,
+            result: Ok(()),
         }
     }
 
@@ -227,14 +236,18 @@ impl VirtioBlk {
         self.read_reg64(0x100) as usize
     }
 
-    pub fn block_op(&mut self, buffer: *mut u8, size: usize, sector: usize, write: bool) {
+    pub fn block_op(
+        &mut self,
+        buffer: *mut u8,
+        size: usize,
+        sector: usize,
+        write: bool,
+    ) -> Result<(), fs::DiskError> {
         let pm = unsafe { process_manager() };
 
         // until disk operation end
         pm.wait_semaphore(self.sid).expect("process");
 
-        self.pid = pm.running;
-
         let req_layout = Layout::from_size_align(size_of::<VirtioBlkRequest>(), 1).unwrap();
         let req = unsafe { alloc(req_layout) } as *mut VirtioBlkRequest;
 
@@ -279,8 +292,7 @@ impl VirtioBlk {
         );
         self.write_desc(desc_indexes[2] as usize, desc);
 
-        pm.signal_semaphore(self.sid).expect("process");
-
+        // the lock is held until the request completes, `pending` releases it
         unsafe {
             let mut avail = self.virtqueue.as_mut().avail.as_mut().unwrap();
             let index = avail.idx as usize;
@@ -289,10 +301,15 @@ impl VirtioBlk {
             avail.idx = avail.idx.wrapping_add(1);
             asm!("fence iorw, iorw");
             self.desc_indexes = Some(desc_indexes);
-            pm.io_wait(self.pid).expect("process");
+            self.done = false;
             self.write_reg32(VirtioReg::QueueNotify.val(), 0);
-            pm.schedule().expect("process");
         }
+
+        let done = &self.done as *const bool;
+        self.io_queue
+            .wait_until(|| unsafe { done.read_volatile() })
+            .expect("process");
+        self.result
     }
 
     #[allow(unaligned_references)]
@@ -303,12 +320,17 @@ impl VirtioBlk {
         let used = unsafe { self.virtqueue.as_mut().used.as_mut().unwrap() };
         let mut freed_desc = BTreeSet::new();
 
+        self.result = Ok(());
         while self.ack_used_index != used.idx {
             let index = self.ack_used_index % VIRTIO_RING_SIZE as u16;
             let elem = used.ring[index as usize];
-            if self.status[elem.id as usize] != 0 {
-                println!("{}", self.status[elem.id as usize]);
-                panic!("virtio_blk operation failed");
+            match self.status[elem.id as usize] {
+                VIRTIO_BLK_S_OK => (),
+                VIRTIO_BLK_S_UNSUPP => self.result = Err(fs::DiskError::Unsupported),
+                status => {
+                    println!("virtio_blk: request failed with status {}", status);
+                    self.result = Err(fs::DiskError::Io);
+                }
             }
 
             self.ack_used_index = self.ack_used_index.wrapping_add(1);
@@ -332,18 +354,19 @@ impl VirtioBlk {
         self.deallocate_desc(&desc_indexes);
 
         let pm = unsafe { process_manager() };
-        pm.io_signal(self.pid).expect("process");
+        self.done = true;
+        self.io_queue.wake_one().expect("process");
         pm.signal_semaphore(self.sid).expect("process");
     }
 }
 
 impl fs::Disk for VirtioBlk {
-    fn read_sector(&mut self, sector: usize, buffer: &mut [u8]) {
-        self.block_op(buffer.as_mut_ptr(), 512, sector, false);
+    fn read_sector(&mut self, sector: usize, buffer: &mut [u8]) -> Result<(), fs::DiskError> {
+        self.block_op(buffer.as_mut_ptr(), 512, sector, false)
     }
 
-    fn write_sector(&mut self, sector: usize, buffer: &mut [u8]) {
-        self.block_op(buffer.as_mut_ptr(), 512, sector, true);
+    fn write_sector(&mut self, sector: usize, buffer: &mut [u8]) -> Result<(), fs::DiskError> {
+        self.block_op(buffer.as_mut_ptr(), 512, sector, true)
     }
 
     fn sector_size(&self) -> usize {
@@ -459,7 +459,7 @@ impl VirtioGpu {
             avail.idx = avail.idx.wrapping_add(1);
             asm!("fence iorw, iorw");
             self.desc_indexes = Some(desc_indexes);
-            pm.io_wait(self.pid).expect("process");
+            pm.io_wait(self.pid, "virtio-gpu").expect("process");
             self.write_reg32(VirtioReg::QueueNotify.val(), queue as u32);
             pm.schedule().expect("process");
         }
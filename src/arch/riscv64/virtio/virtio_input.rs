@@ -907,7 +907,7 @@ impl VirtioInput {
 
         let config = (self.base + VirtioReg::Config as usize) as *mut VirtioInputConfig;
         unsafe {
-            pm.io_wait(self.pid).expect("process");
+            pm.io_wait(self.pid, "virtio-input").expect("process");
             (*config)
                 .select
                 .write(VirtioInputConfigSelect::InputCfgIdName.val());
@@ -927,7 +927,7 @@ impl VirtioInput {
                 DeviceType::Keyboard => EventType::EV_KEY,
             };
 
-            pm.io_wait(self.pid).expect("process");
+            pm.io_wait(self.pid, "virtio-input").expect("process");
             (*config).subsel.write(event_type as u8);
             (*config)
                 .select
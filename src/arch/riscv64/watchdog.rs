@@ -0,0 +1,100 @@
+use super::clint::{MachineFrame, TICKS_PER_SEC};
+use super::csr::Csr;
+use crate::process::*;
+use crate::*;
+use core::arch::asm;
+
+// soft lockup detector
+// the machine timer keeps running while a hart spins with interrupts disabled,
+// so it notices a hart that hasn't called the scheduler for SOFT_LOCKUP_SECS
+
+pub const MAX_HARTS: usize = 8;
+pub const SOFT_LOCKUP_SECS: usize = 10;
+
+#[derive(Copy, Clone)]
+struct HartWatch {
+    armed: bool,      // the hart runs the scheduler
+    heartbeat: usize, // bumped by `touch`
+    last: usize,      // heartbeat seen by the previous check
+    stalled: usize,   // machine timer ticks without a heartbeat
+    reported: bool,
+}
+
+impl HartWatch {
+    const fn new() -> Self {
+        HartWatch {
+            armed: false,
+            heartbeat: 0,
+            last: 0,
+            stalled: 0,
+            reported: false,
+        }
+    }
+}
+
+static mut HARTS: [HartWatch; MAX_HARTS] = [HartWatch::new(); MAX_HARTS];
+
+// only valid in supervisor mode, where tp holds the hart id
+pub fn hart_id() -> usize {
+    let hart_id: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg)hart_id);
+    }
+    hart_id
+}
+
+// called by the scheduler
+pub fn touch() {
+    let watch = unsafe { &mut HARTS[hart_id()] };
+    watch.heartbeat = watch.heartbeat.wrapping_add(1);
+    watch.armed = true;
+}
+
+// called by the machine timer on every interval
+pub unsafe fn check(hart: usize, frame: &MachineFrame) {
+    let watch = &mut HARTS[hart];
+    if !watch.armed {
+        return;
+    }
+
+    let heartbeat = (&watch.heartbeat as *const usize).read_volatile();
+    if heartbeat != watch.last {
+        watch.last = heartbeat;
+        watch.stalled = 0;
+        watch.reported = false;
+        return;
+    }
+
+    watch.stalled += 1;
+    if !watch.reported && watch.stalled >= SOFT_LOCKUP_SECS * TICKS_PER_SEC {
+        watch.reported = true;
+        dump(hart, frame);
+    }
+}
+
+// the kernel may be stuck in the middle of anything, so nothing here takes a lock
+unsafe fn dump(hart: usize, frame: &MachineFrame) {
+    let pm = process_manager();
+    let running = pm.running;
+
+    println!(
+        "watchdog: soft lockup on hart {}, stuck for {}s",
+        hart, SOFT_LOCKUP_SECS
+    );
+    match pm.ptable.get_inner_spinlock_mut().get_mut().get(&running) {
+        Some(proc) => println!("pid      : {} ({}, {:?})", running, proc.name, proc.state),
+        None => println!("pid      : {}", running),
+    }
+    println!("pc       : {:#018x}", Csr::Mepc.read());
+    println!("ra       : {:#018x}", frame.ra);
+    println!("sp       : {:#018x}", Csr::Mscratch.read());
+    println!("mstatus  : {:#018x}", Csr::Mstatus.read());
+    println!(
+        "a0-a3    : {:#018x} {:#018x} {:#018x} {:#018x}",
+        frame.a0, frame.a1, frame.a2, frame.a3
+    );
+    println!(
+        "a4-a7    : {:#018x} {:#018x} {:#018x} {:#018x}",
+        frame.a4, frame.a5, frame.a6, frame.a7
+    );
+}
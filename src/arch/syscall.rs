@@ -1,4 +1,4 @@
+// pointer arguments are user addresses, they are accessed through crate::uaccess
 pub trait SysCallInfo {
     fn get_arg_raw(&self, idx: usize) -> usize;
-    fn get_arg_ptr<T>(&self, idx: usize) -> *mut T;
 }
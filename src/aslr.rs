@@ -0,0 +1,40 @@
+use crate::arch::target::fw_cfg::fw;
+use crate::random;
+use crate::*;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// address space layout randomization
+// a position independent program, the user stack and the mappings placed by the kernel start at
+// a random number of pages from their base address, see arch::target::loader and
+// arch::target::process for the bases and ranges.
+// the boot option "norandmaps" turns it off for reproducible debugging:
+//   make qemu-riscv64 CMDLINE=norandmaps
+
+const PAGE_SIZE: usize = 0x1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// returns the previous setting
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// a random offset below `pages` pages, 0 while randomization is off
+pub fn random_offset(pages: usize) -> usize {
+    if !enabled() || pages == 0 {
+        return 0;
+    }
+    random::below(pages) * PAGE_SIZE
+}
+
+pub fn init() {
+    let options = fw::boot_options();
+    if options.split_whitespace().any(|opt| opt == "norandmaps") {
+        set_enabled(false);
+        println!("address space randomization is off");
+    }
+}
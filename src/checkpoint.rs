@@ -0,0 +1,601 @@
+use crate::arch::target::loader::Abi;
+use crate::arch::target::paging::EntryBits;
+use crate::arch::target::process::{
+    is_user_address, AddressSpace, STACK_RANDOM_PAGES, USER_STACK_MAX, USER_STACK_START,
+};
+use crate::arch::target::trampoline::KERNEL_MAPPED_START;
+use crate::fs;
+use crate::fs::file_system;
+use crate::graphics::*;
+use crate::process::*;
+use crate::rlimit::*;
+use crate::signal::*;
+use crate::writer::Writer;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::slice;
+
+// process checkpoints
+// the image of a stopped process (or of the caller itself) is written to a file, from which a
+// copy of the process is started later, possibly after a reboot, with a new pid.
+// an image holds the user pages, the user registers, signal handlers, resource limits, open
+// files with their offsets and the windows mapped by the process. only single threaded
+// processes can be checkpointed. the saved resource limits are lowered to the hard limits of the
+// process restoring them.
+//
+// layout, every number is a little endian u64 and a string is its length followed by its bytes:
+//   magic, version, name, priority
+//   32 registers (see ArchProcess::user_registers)
+//   blocked signals, NSIG x (handler, flags, mask)
+//   RLIM_NLIMITS x (cur, max)
+//   file count, then fd, offset and path of each file
+//   window count, then vaddr, x, y, width, height, title and the pixels of each window
+//   entry point, ABI (0 native, 1 Linux), start and end of the heap, top and bottom of the stack
+//   mapping count, then start and end of each anonymous mapping
+//   page count, then vaddr, flags and the 4096 bytes of each page
+
+pub const CHECKPOINT_MAGIC: u64 = 0x4b43_4e4f_5254_4943; // "CITRONCK"
+pub const CHECKPOINT_VERSION: u64 = 3;
+
+// what a restored process gets from the checkpoint call that saved it
+pub const CHECKPOINT_RESTORED: usize = 1;
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Process(ProcessError),
+    Fs(fs::Error),
+    NotStopped, // the process may change while it is saved
+    MultiThreaded,
+    BadImage,
+}
+
+impl From<ProcessError> for CheckpointError {
+    fn from(err: ProcessError) -> Self {
+        CheckpointError::Process(err)
+    }
+}
+
+impl From<fs::Error> for CheckpointError {
+    fn from(err: fs::Error) -> Self {
+        CheckpointError::Fs(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenFile {
+    pub fd: usize,
+    pub offset: usize,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowImage {
+    pub vaddr: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub pixels: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageImage {
+    pub vaddr: usize,
+    pub flags: usize,
+    pub data: Vec<u8>, // PAGE_SIZE bytes
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointImage {
+    pub name: String,
+    pub priority: usize,
+    pub registers: [usize; 32],
+    pub blocked: SigSet,
+    pub actions: Vec<SigAction>,
+    pub limits: Vec<RLimit>,
+    pub files: Vec<OpenFile>,
+    pub windows: Vec<WindowImage>,
+    pub entry: usize,
+    pub abi: Abi,
+    pub brk_start: usize,
+    pub brk: usize,
+    pub stack_top: usize,
+    pub stack_bottom: usize,
+    pub mappings: Vec<Range<usize>>,
+    pub pages: Vec<PageImage>,
+}
+
+// its length, then its bytes
+fn put_str(w: &mut Writer, s: &str) {
+    w.u64(s.len());
+    w.bytes(s.as_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CheckpointError> {
+        let end = self.pos.checked_add(len).ok_or(CheckpointError::BadImage)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(CheckpointError::BadImage)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn get(&mut self) -> Result<usize, CheckpointError> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(value) as usize)
+    }
+
+    fn get_u32(&mut self) -> Result<u32, CheckpointError> {
+        let value = self.get()?;
+        if value > u32::MAX as usize {
+            return Err(CheckpointError::BadImage);
+        }
+        Ok(value as u32)
+    }
+
+    fn get_str(&mut self) -> Result<String, CheckpointError> {
+        let len = self.get()?;
+        let bytes = self.bytes(len)?;
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| CheckpointError::BadImage)
+    }
+
+    // a count of items taking at least `item_size` bytes each, checked against what is left
+    fn get_count(&mut self, item_size: usize) -> Result<usize, CheckpointError> {
+        let count = self.get()?;
+        if count.saturating_mul(item_size) > self.data.len() - self.pos {
+            return Err(CheckpointError::BadImage);
+        }
+        Ok(count)
+    }
+}
+
+impl CheckpointImage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.u64(CHECKPOINT_MAGIC as usize);
+        w.u64(CHECKPOINT_VERSION as usize);
+        put_str(&mut w, &self.name);
+        w.u64(self.priority);
+        for reg in self.registers.iter() {
+            w.u64(*reg);
+        }
+
+        w.u64(self.blocked as usize);
+        for action in self.actions.iter() {
+            w.u64(action.handler);
+            w.u64(action.flags);
+            w.u64(action.mask as usize);
+        }
+        for limit in self.limits.iter() {
+            w.u64(limit.cur);
+            w.u64(limit.max);
+        }
+
+        w.u64(self.files.len());
+        for file in self.files.iter() {
+            w.u64(file.fd);
+            w.u64(file.offset);
+            put_str(&mut w, &file.path);
+        }
+
+        w.u64(self.windows.len());
+        for window in self.windows.iter() {
+            w.u64(window.vaddr);
+            w.u64(window.x as usize);
+            w.u64(window.y as usize);
+            w.u64(window.width as usize);
+            w.u64(window.height as usize);
+            put_str(&mut w, &window.title);
+            for pixel in window.pixels.iter() {
+                w.bytes(&pixel.to_le_bytes());
+            }
+        }
+
+        w.u64(self.entry);
+        w.u64(match self.abi {
+            Abi::Native => 0,
+            Abi::Linux => 1,
+        });
+        w.u64(self.brk_start);
+        w.u64(self.brk);
+        w.u64(self.stack_top);
+        w.u64(self.stack_bottom);
+        w.u64(self.mappings.len());
+        for mapping in self.mappings.iter() {
+            w.u64(mapping.start);
+            w.u64(mapping.end);
+        }
+        w.u64(self.pages.len());
+        for page in self.pages.iter() {
+            w.u64(page.vaddr);
+            w.u64(page.flags);
+            w.bytes(&page.data);
+        }
+
+        w.data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, CheckpointError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.get()? as u64 != CHECKPOINT_MAGIC || r.get()? as u64 != CHECKPOINT_VERSION {
+            return Err(CheckpointError::BadImage);
+        }
+        let name = r.get_str()?;
+        let priority = r.get()?;
+        let mut registers = [0; 32];
+        for reg in registers.iter_mut() {
+            *reg = r.get()?;
+        }
+
+        let blocked = r.get()? as SigSet;
+        let mut actions = Vec::new();
+        for _ in 0..NSIG {
+            actions.push(SigAction {
+                handler: r.get()?,
+                flags: r.get()?,
+                mask: r.get()? as SigSet,
+            });
+        }
+        let mut limits = Vec::new();
+        for _ in 0..RLIM_NLIMITS {
+            limits.push(RLimit {
+                cur: r.get()?,
+                max: r.get()?,
+            });
+        }
+
+        let mut files = Vec::new();
+        for _ in 0..r.get_count(24)? {
+            files.push(OpenFile {
+                fd: r.get()?,
+                offset: r.get()?,
+                path: r.get_str()?,
+            });
+        }
+
+        let mut windows = Vec::new();
+        for _ in 0..r.get_count(48)? {
+            let vaddr = r.get()?;
+            if !is_user_address(vaddr) {
+                return Err(CheckpointError::BadImage);
+            }
+            let x = r.get_u32()?;
+            let y = r.get_u32()?;
+            let width = r.get_u32()?;
+            let height = r.get_u32()?;
+            let title = r.get_str()?;
+            let count = (width as usize)
+                .checked_mul(height as usize)
+                .ok_or(CheckpointError::BadImage)?;
+            let pixels = r
+                .bytes(count.checked_mul(4).ok_or(CheckpointError::BadImage)?)?
+                .chunks(4)
+                .map(|pixel| u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]))
+                .collect();
+            windows.push(WindowImage {
+                vaddr,
+                x,
+                y,
+                width,
+                height,
+                title,
+                pixels,
+            });
+        }
+
+        let entry = r.get()?;
+        let abi = match r.get()? {
+            0 => Abi::Native,
+            1 => Abi::Linux,
+            _ => return Err(CheckpointError::BadImage),
+        };
+        let brk_start = r.get()?;
+        let brk = r.get()?;
+        let stack_top = r.get()?;
+        let stack_bottom = r.get()?;
+        // where AddressSpace::new could have put the stack, grown to USER_STACK_MAX at most
+        if stack_top % PAGE_SIZE != 0
+            || stack_bottom % PAGE_SIZE != 0
+            || stack_top > USER_STACK_START
+            || stack_top < USER_STACK_START - STACK_RANDOM_PAGES * PAGE_SIZE
+            || stack_bottom > stack_top
+            || stack_top - stack_bottom > USER_STACK_MAX
+        {
+            return Err(CheckpointError::BadImage);
+        }
+        let mut mappings = Vec::new();
+        for _ in 0..r.get_count(16)? {
+            let mapping = r.get()?..r.get()?;
+            if mapping.start % PAGE_SIZE != 0
+                || mapping.end % PAGE_SIZE != 0
+                || mapping.start >= mapping.end
+                || mapping.end > KERNEL_MAPPED_START
+            {
+                return Err(CheckpointError::BadImage);
+            }
+            mappings.push(mapping);
+        }
+        let mut pages = Vec::new();
+        for _ in 0..r.get_count(16 + PAGE_SIZE)? {
+            let vaddr = r.get()?;
+            let flags = r.get()?;
+            // a user page which can be accessed somehow
+            let access = EntryBits::R.val() | EntryBits::W.val() | EntryBits::X.val();
+            if vaddr % PAGE_SIZE != 0
+                || !is_user_address(vaddr)
+                || flags & EntryBits::U.val() == 0
+                || flags & access == 0
+            {
+                return Err(CheckpointError::BadImage);
+            }
+            pages.push(PageImage {
+                vaddr,
+                flags,
+                data: r.bytes(PAGE_SIZE)?.to_vec(),
+            });
+        }
+
+        Ok(CheckpointImage {
+            name,
+            priority,
+            registers,
+            blocked,
+            actions,
+            limits,
+            files,
+            windows,
+            entry,
+            abi,
+            brk_start,
+            brk,
+            stack_top,
+            stack_bottom,
+            mappings,
+            pages,
+        })
+    }
+
+    // the user memory of the image without the windows, which takes up to `max_memory` bytes
+    // (RLIMIT_AS) with the windows
+    pub fn address_space(&self, max_memory: usize) -> Result<AddressSpace, CheckpointError> {
+        let windows: usize = self
+            .windows
+            .iter()
+            .map(|window| window.pixels.len() * 4)
+            .sum();
+        if self.pages.len() * PAGE_SIZE + windows > max_memory {
+            return Err(ProcessError::ResourceLimit(RLIMIT_AS).into());
+        }
+
+        let mut addr_space = AddressSpace::new();
+        for page in self.pages.iter() {
+            unsafe {
+                addr_space.map_user_page(page.vaddr, page.data.as_ptr(), page.flags);
+            }
+        }
+        addr_space.exec_info.entry = self.entry;
+        addr_space.exec_info.abi = self.abi;
+        addr_space.brk_start = self.brk_start;
+        addr_space.brk = self.brk;
+        addr_space.stack_top = self.stack_top;
+        addr_space.stack_bottom = self.stack_bottom;
+        addr_space.mappings = self.mappings.clone();
+
+        Ok(addr_space)
+    }
+}
+
+// the image of `pid`, which is either stopped or the running process
+pub fn capture(pid: Pid) -> Result<CheckpointImage, CheckpointError> {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let (state, tgid) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, pid)?;
+        (proc.state, proc.tgid)
+    };
+    if pid != running && state != State::Stopped {
+        return Err(CheckpointError::NotStopped);
+    }
+    if pm.threads_of(tgid).len() > 1 {
+        return Err(CheckpointError::MultiThreaded);
+    }
+
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, pid)?;
+    let mut registers = proc.arch_proc.user_registers();
+    if pid == running {
+        // a0 of the system call
+        registers[10] = CHECKPOINT_RESTORED;
+    }
+
+    let files = proc
+        .files
+        .borrow()
+        .iter()
+        .map(|file| OpenFile {
+            fd: file.fd,
+            offset: file.offset,
+            path: file.path.clone(),
+        })
+        .collect();
+
+    let mut addr_space = proc.arch_proc.addr_space().borrow_mut();
+    // the pages not touched yet are saved as they would read
+    addr_space.populate_all();
+    let arena = unsafe { object_arena() };
+    let wm = unsafe { window_manager() };
+    let mut windows = Vec::new();
+    for mapped in addr_space.windows.iter() {
+        let window = match arena.get(mapped.id) {
+            Some(window) => (&**window).as_any().downcast_ref::<Window>().unwrap(),
+            None => continue,
+        };
+        let frame = window.get_frame();
+        let (x, y) = wm.get_window_position(mapped.id);
+        let count = (frame.width * frame.height) as usize;
+        windows.push(WindowImage {
+            vaddr: mapped.vaddr,
+            x,
+            y,
+            width: frame.width,
+            height: frame.height,
+            title: String::from(window.get_title()),
+            pixels: unsafe { slice::from_raw_parts(frame.buffer, count) }.to_vec(),
+        });
+    }
+
+    // the window buffers are saved with their windows
+    let in_window = |vaddr: usize| {
+        addr_space
+            .windows
+            .iter()
+            .any(|mapped| vaddr >= mapped.vaddr && vaddr < mapped.vaddr + mapped.size)
+    };
+    let pages = addr_space
+        .user_pages()
+        .into_iter()
+        .filter(|(vaddr, _, _)| !in_window(*vaddr))
+        .map(|(vaddr, paddr, flags)| PageImage {
+            vaddr,
+            flags,
+            data: unsafe { slice::from_raw_parts(paddr as *const u8, PAGE_SIZE) }.to_vec(),
+        })
+        .collect();
+
+    Ok(CheckpointImage {
+        name: proc.name.clone(),
+        // a reservation isn't saved
+        priority: match proc.deadline {
+            Some(dl) => dl.normal_priority,
+            None => proc.base_priority,
+        },
+        registers,
+        blocked: proc.signals.blocked,
+        actions: proc.signals.actions.to_vec(),
+        limits: (0..RLIM_NLIMITS)
+            .map(|resource| proc.rlimits.get(resource).unwrap())
+            .collect(),
+        files,
+        windows,
+        entry: addr_space.exec_info.entry,
+        abi: addr_space.exec_info.abi,
+        brk_start: addr_space.brk_start,
+        brk: addr_space.brk,
+        stack_top: addr_space.stack_top,
+        stack_bottom: addr_space.stack_bottom,
+        mappings: addr_space.mappings.clone(),
+        pages,
+    })
+}
+
+pub fn checkpoint(pid: Pid, path: &str) -> Result<(), CheckpointError> {
+    let image = capture(pid)?;
+    let data = image.encode();
+    let fs = unsafe { file_system() };
+    fs.lock().write_file(path, &data)?;
+
+    Ok(())
+}
+
+// start a copy of the process saved in `path`, it is ready to run
+pub fn restore(path: &str) -> Result<Pid, CheckpointError> {
+    let fs = unsafe { file_system() };
+    let data = fs.lock().read_file(path)?;
+    let image = CheckpointImage::decode(&data)?;
+
+    let pm = unsafe { process_manager() };
+    let pid = pm.create_process(&image.name, image.priority, false)?;
+    if let Err(err) = restore_into(pid, &image) {
+        pm.kill(pid)?;
+        return Err(err);
+    }
+    pm.ready(pid)?;
+
+    Ok(pid)
+}
+
+fn restore_into(pid: Pid, image: &CheckpointImage) -> Result<(), CheckpointError> {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let caller = get_process!(pm.ptable_lock(), running)?.rlimits;
+    let addr_space = image.address_space(caller.cur(RLIMIT_AS))?;
+
+    let mut ptable = pm.ptable_lock_mut();
+    let proc = get_process_mut!(ptable, pid)?;
+    let kernel_stack = proc.kernel_stack;
+    proc.arch_proc
+        .init_fork(addr_space, kernel_stack, KERNEL_STACK_SIZE);
+    proc.arch_proc.set_user_registers(&image.registers);
+
+    proc.signals.blocked = image.blocked;
+    for (action, saved) in proc.signals.actions.iter_mut().zip(image.actions.iter()) {
+        *action = *saved;
+    }
+    // the saved limits can't go past the hard limits of the process restoring them
+    proc.rlimits = caller;
+    for (resource, saved) in image.limits.iter().enumerate() {
+        let max = saved.max.min(caller.get(resource)?.max);
+        proc.rlimits.set(
+            resource,
+            RLimit {
+                cur: saved.cur.min(max),
+                max,
+            },
+        )?;
+    }
+    proc.files
+        .borrow_mut()
+        .set_max_files(proc.rlimits.cur(RLIMIT_NOFILE));
+
+    let files = proc.files.clone();
+    let addr_space = proc.arch_proc.addr_space().clone();
+    drop(ptable);
+
+    let fs = unsafe { file_system() };
+    for file in image.files.iter() {
+        fs.lock()
+            .reopen_file_in(&mut files.borrow_mut(), &file.path, file.fd, file.offset)?;
+    }
+
+    let wm = unsafe { window_manager() };
+    for saved in image.windows.iter() {
+        // not over the pages or a window restored before
+        if saved.vaddr % PAGE_SIZE != 0
+            || !addr_space
+                .borrow()
+                .range_free(saved.vaddr, saved.pixels.len() * 4)
+        {
+            return Err(CheckpointError::BadImage);
+        }
+        let id = wm.create_window(&saved.title, saved.x, saved.y, saved.width, saved.height);
+        wm.show_window(id);
+        let frame = wm.get_window_frame(id);
+        let size = (frame.width * 4 * frame.height) as usize;
+        unsafe {
+            frame
+                .buffer
+                .copy_from_nonoverlapping(saved.pixels.as_ptr(), saved.pixels.len());
+        }
+        addr_space
+            .borrow_mut()
+            .map_window(id, saved.vaddr, frame.buffer as usize, size)?;
+        wm.update_window_frame(id);
+    }
+
+    Ok(())
+}
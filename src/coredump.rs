@@ -0,0 +1,295 @@
+use crate::arch::target::clint::TICKS_PER_SEC;
+use crate::arch::target::paging::EntryBits;
+use crate::fs;
+use crate::fs::file_system;
+use crate::process::*;
+use crate::signal::*;
+use crate::writer::Writer;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// core dumps
+// a process killed by a signal whose default action dumps core (a fault, SIGABRT, ...) is saved
+// to /core.<name>.<pid> as an ELF core file, which gdb loads with the program:
+//   riscv64-unknown-elf-gdb <program> core.<name>.<pid>
+// the file has a PT_NOTE segment with NT_PRSTATUS (signal, pid and the user registers) and
+// NT_PRPSINFO (program name), then a PT_LOAD segment for every range of user pages mapped with
+// the same permissions.
+// dumps are on by default and switched with the coredump system call.
+
+const PAGE_SIZE: usize = 0x1000;
+
+pub const EM_RISCV: u16 = 243;
+pub const ET_CORE: u16 = 4;
+pub const PT_LOAD: u32 = 1;
+pub const PT_NOTE: u32 = 4;
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_PRPSINFO: u32 = 3;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PRSTATUS_SIZE: usize = 376; // struct elf_prstatus of riscv64
+const PRPSINFO_SIZE: usize = 136; // struct elf_prpsinfo of a 64 bit target
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// returns the previous setting
+pub fn set_enabled(enabled: bool) -> bool {
+    ENABLED.swap(enabled, Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+pub enum CoreDumpError {
+    Process(ProcessError),
+    Fs(fs::Error),
+}
+
+impl From<ProcessError> for CoreDumpError {
+    fn from(err: ProcessError) -> Self {
+        CoreDumpError::Process(err)
+    }
+}
+
+impl From<fs::Error> for CoreDumpError {
+    fn from(err: fs::Error) -> Self {
+        CoreDumpError::Fs(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoreSegment {
+    pub vaddr: usize,
+    pub flags: u32, // PF_*
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoreImage {
+    pub name: String,
+    pub pid: Pid,
+    pub tgid: Pid,
+    pub signal: Signal,
+    pub pending: SigSet,
+    pub blocked: SigSet,
+    pub cpu_time: usize,        // in ticks
+    pub registers: [usize; 32], // see ArchProcess::user_registers
+    pub segments: Vec<CoreSegment>,
+}
+
+// name and description are padded to 4 bytes
+fn note(w: &mut Writer, note_type: u32, desc: &[u8]) {
+    w.u32(5); // "CORE" and its null
+    w.u32(desc.len() as u32);
+    w.u32(note_type);
+    w.bytes(b"CORE\0");
+    w.pad_to(4);
+    w.bytes(desc);
+    w.pad_to(4);
+}
+
+// flags of a page table entry as PF_*
+fn segment_flags(entry_flags: usize) -> u32 {
+    let mut flags = 0;
+    if entry_flags & EntryBits::R.val() != 0 {
+        flags |= PF_R;
+    }
+    if entry_flags & EntryBits::W.val() != 0 {
+        flags |= PF_W;
+    }
+    if entry_flags & EntryBits::X.val() != 0 {
+        flags |= PF_X;
+    }
+    flags
+}
+
+fn timeval(w: &mut Writer, ticks: usize) {
+    w.u64(ticks / TICKS_PER_SEC);
+    w.u64(ticks % TICKS_PER_SEC * (1_000_000 / TICKS_PER_SEC));
+}
+
+impl CoreImage {
+    fn prstatus(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        // siginfo: signo, code, errno
+        w.u32(self.signal.val() as u32);
+        w.u32(0);
+        w.u32(0);
+        w.u16(self.signal.val() as u16); // pr_cursig
+        w.pad_to(8);
+        w.u64(self.pending as usize);
+        w.u64(self.blocked as usize);
+        // pid, ppid, pgrp, sid
+        w.u32(self.pid as u32);
+        w.u32(0);
+        w.u32(self.tgid as u32);
+        w.u32(0);
+        // user, system, and the children's user and system time
+        timeval(&mut w, self.cpu_time);
+        for _ in 0..3 {
+            timeval(&mut w, 0);
+        }
+        for reg in self.registers.iter() {
+            w.u64(*reg);
+        }
+        w.u32(0); // pr_fpvalid
+        w.pad_to(8);
+        debug_assert_eq!(w.data.len(), PRSTATUS_SIZE);
+        w.data
+    }
+
+    fn prpsinfo(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        // state, sname, zomb, nice
+        w.bytes(&[0, b'R', 0, 0]);
+        w.pad_to(8);
+        w.u64(0); // flag
+        w.u32(0); // uid
+        w.u32(0); // gid
+        w.u32(self.pid as u32);
+        w.u32(0);
+        w.u32(self.tgid as u32);
+        w.u32(0);
+        // fname and psargs
+        let mut fname = [0; 16];
+        let mut psargs = [0; 80];
+        let name = self.name.as_bytes();
+        let len = name.len().min(fname.len() - 1);
+        fname[..len].copy_from_slice(&name[..len]);
+        let len = name.len().min(psargs.len() - 1);
+        psargs[..len].copy_from_slice(&name[..len]);
+        w.bytes(&fname);
+        w.bytes(&psargs);
+        debug_assert_eq!(w.data.len(), PRPSINFO_SIZE);
+        w.data
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut notes = Writer::new();
+        note(&mut notes, NT_PRSTATUS, &self.prstatus());
+        note(&mut notes, NT_PRPSINFO, &self.prpsinfo());
+
+        let phnum = 1 + self.segments.len();
+        let notes_offset = EHDR_SIZE + PHDR_SIZE * phnum;
+        // the contents of the segments start at a page boundary
+        let mut offsets = Vec::new();
+        let mut offset = (notes_offset + notes.data.len() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        for segment in self.segments.iter() {
+            offsets.push(offset);
+            offset += segment.data.len();
+        }
+
+        let mut w = Writer::new();
+        // e_ident: magic, 64 bit, little endian, version 1, System V ABI
+        w.bytes(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        w.pad_to(16);
+        w.u16(ET_CORE);
+        w.u16(EM_RISCV);
+        w.u32(1); // e_version
+        w.u64(0); // e_entry
+        w.u64(EHDR_SIZE); // e_phoff
+        w.u64(0); // e_shoff
+        w.u32(0); // e_flags
+        w.u16(EHDR_SIZE as u16);
+        w.u16(PHDR_SIZE as u16);
+        w.u16(phnum as u16);
+        w.u16(0); // e_shentsize
+        w.u16(0); // e_shnum
+        w.u16(0); // e_shstrndx
+
+        // p_type, p_flags, p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+        w.u32(PT_NOTE);
+        w.u32(0);
+        w.u64(notes_offset);
+        w.u64(0);
+        w.u64(0);
+        w.u64(notes.data.len());
+        w.u64(0);
+        w.u64(4);
+        for (segment, offset) in self.segments.iter().zip(offsets.iter()) {
+            w.u32(PT_LOAD);
+            w.u32(segment.flags);
+            w.u64(*offset);
+            w.u64(segment.vaddr);
+            w.u64(0);
+            w.u64(segment.data.len());
+            w.u64(segment.data.len());
+            w.u64(PAGE_SIZE);
+        }
+
+        w.bytes(&notes.data);
+        for segment in self.segments.iter() {
+            w.pad_to(PAGE_SIZE);
+            w.bytes(&segment.data);
+        }
+        w.data
+    }
+}
+
+// the registers are those the process trapped with
+pub fn capture(pid: Pid, sig: Signal) -> Result<CoreImage, ProcessError> {
+    let pm = unsafe { process_manager() };
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, pid)?;
+
+    // the pages not touched yet are dumped as they would read
+    let addr_space = proc.arch_proc.addr_space();
+    addr_space.borrow_mut().populate_all();
+    let mut pages = addr_space.borrow().user_pages();
+    pages.sort_by_key(|(vaddr, _, _)| *vaddr);
+
+    // adjacent pages with the same permissions make one segment
+    let mut segments: Vec<CoreSegment> = Vec::new();
+    for (vaddr, paddr, entry_flags) in pages {
+        let flags = segment_flags(entry_flags);
+        let data = unsafe { slice::from_raw_parts(paddr as *const u8, PAGE_SIZE) };
+        match segments.last_mut() {
+            Some(last) if last.vaddr + last.data.len() == vaddr && last.flags == flags => {
+                last.data.extend_from_slice(data);
+            }
+            _ => segments.push(CoreSegment {
+                vaddr,
+                flags,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    Ok(CoreImage {
+        name: proc.name.clone(),
+        pid,
+        tgid: proc.tgid,
+        signal: sig,
+        pending: proc.signals.pending,
+        blocked: proc.signals.blocked,
+        cpu_time: proc.cpu_time,
+        registers: proc.arch_proc.user_registers(),
+        segments,
+    })
+}
+
+pub fn core_path(name: &str, pid: Pid) -> String {
+    // the name of the program without its directories
+    let name = name.rsplit('/').next().unwrap_or(name);
+    format!("/core.{}.{}", name, pid)
+}
+
+// write the core file of `pid`, killed by `sig`, returns its path
+pub fn dump(pid: Pid, sig: Signal) -> Result<String, CoreDumpError> {
+    let image = capture(pid, sig)?;
+    let path = core_path(&image.name, pid);
+    let data = image.encode();
+    let fs = unsafe { file_system() };
+    fs.lock().write_file(&path, &data)?;
+
+    Ok(path)
+}
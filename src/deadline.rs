@@ -0,0 +1,89 @@
+use crate::process::*;
+use crate::timer::*;
+
+// deadline scheduling class
+// a process with a reservation gets `runtime` ticks of CPU in every `period` ticks, to be used
+// within `deadline` ticks of the start of the period. ready reservations run earliest deadline
+// first, ahead of every normal priority. once the runtime of a period is used up the process is
+// throttled until the next period, so it can't starve the others.
+// a reservation is admitted only while the total bandwidth (sum of runtime / period) stays
+// within DL_MAX_BANDWIDTH, which leaves room for the normal processes.
+
+// bandwidth is a fixed point fraction of the CPU
+pub const DL_BW_SHIFT: usize = 20;
+pub const DL_BW_UNIT: usize = 1 << DL_BW_SHIFT;
+pub const DL_MAX_BANDWIDTH: usize = DL_BW_UNIT * 95 / 100;
+// the longest period, so the bandwidth and the absolute deadlines can't overflow
+pub const DL_PERIOD_MAX: Tick = 1 << 40;
+
+// layout shared with user space (struct sched_deadline in bin/syscall.h), in ticks
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeadlineParams {
+    pub runtime: Tick,
+    pub deadline: Tick, // relative to the start of the period
+    pub period: Tick,
+}
+
+impl DeadlineParams {
+    pub fn new(runtime: Tick, deadline: Tick, period: Tick) -> Self {
+        DeadlineParams {
+            runtime,
+            deadline,
+            period,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ProcessError> {
+        if self.runtime == 0
+            || self.runtime > self.deadline
+            || self.deadline > self.period
+            || self.period > DL_PERIOD_MAX
+        {
+            return Err(ProcessError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    pub fn bandwidth(&self) -> usize {
+        (self.runtime << DL_BW_SHIFT) / self.period
+    }
+}
+
+// the reservation of a process
+#[derive(Copy, Clone, Debug)]
+pub struct DeadlineEntity {
+    pub params: DeadlineParams,
+    pub abs_deadline: Tick,     // deadline of the current period
+    pub remaining: Tick,        // runtime left in the current period
+    pub timer: TimerId,         // replenishes the runtime at every period
+    pub normal_priority: usize, // base priority to return to when the reservation is dropped
+}
+
+impl DeadlineEntity {
+    pub fn new(params: DeadlineParams, timer: TimerId, normal_priority: usize) -> Self {
+        DeadlineEntity {
+            params,
+            abs_deadline: jiffies() + params.deadline,
+            remaining: params.runtime,
+            timer,
+            normal_priority,
+        }
+    }
+
+    // start a new period
+    pub fn replenish(&mut self) {
+        self.abs_deadline = jiffies() + self.params.deadline;
+        self.remaining = self.params.runtime;
+    }
+
+    pub fn priority(&self) -> usize {
+        deadline_priority(self.abs_deadline)
+    }
+}
+
+// an earlier deadline maps to a higher priority, so the ready queue orders reservations EDF
+// normal priorities are small numbers far below any of these
+pub fn deadline_priority(abs_deadline: Tick) -> usize {
+    usize::MAX - abs_deadline
+}
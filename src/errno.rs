@@ -0,0 +1,187 @@
+use crate::checkpoint::CheckpointError;
+use crate::fs;
+use crate::futex::FutexError;
+use crate::process::ProcessError;
+use crate::rlimit::*;
+use alloc::format;
+use alloc::string::String;
+
+// error numbers
+// every system call fails with a negated Errno, numbered as in Linux so that programs of either
+// ABI understand them. kernel errors are converted here and nowhere else.
+// the codes are published to user programs as bin/errno.h and bin/errno.rs, generated from the
+// table below by c_header and rust_module (tests/errno.rs checks that they are up to date).
+
+macro_rules! errnos {
+    ($($name: ident = $val: expr, $desc: expr;)*) => {
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        pub enum Errno {
+            $($name = $val,)*
+        }
+
+        pub static ERRNOS: &[Errno] = &[$(Errno::$name,)*];
+
+        impl Errno {
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(Errno::$name => stringify!($name),)*
+                }
+            }
+
+            pub fn description(&self) -> &'static str {
+                match *self {
+                    $(Errno::$name => $desc,)*
+                }
+            }
+        }
+    };
+}
+
+errnos! {
+    EPERM = 1, "operation not permitted";
+    ENOENT = 2, "no such file or directory";
+    ESRCH = 3, "no such process";
+    EINTR = 4, "interrupted system call";
+    EIO = 5, "input/output error";
+    E2BIG = 7, "argument list too long";
+    ENOEXEC = 8, "exec format error";
+    EBADF = 9, "bad file descriptor";
+    EAGAIN = 11, "resource temporarily unavailable";
+    ENOMEM = 12, "cannot allocate memory";
+    EFAULT = 14, "bad address";
+    EBUSY = 16, "device or resource busy";
+    ENODEV = 19, "no such device";
+    ENOTDIR = 20, "not a directory";
+    EISDIR = 21, "is a directory";
+    EINVAL = 22, "invalid argument";
+    EMFILE = 24, "too many open files";
+    ENOSPC = 28, "no space left on device";
+    ENAMETOOLONG = 36, "file name too long";
+    ENOSYS = 38, "function not implemented";
+    EIDRM = 43, "identifier removed";
+    ETIMEDOUT = 110, "connection timed out";
+}
+
+impl Errno {
+    pub fn val(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn from_val(val: usize) -> Option<Errno> {
+        ERRNOS.iter().copied().find(|errno| errno.val() == val)
+    }
+
+    // the return value of a failed system call
+    pub fn ret(&self) -> usize {
+        (-(self.val() as isize)) as usize
+    }
+}
+
+impl From<ProcessError> for Errno {
+    fn from(err: ProcessError) -> Self {
+        match err {
+            ProcessError::ProcessNotFound(_) => Errno::ESRCH,
+            ProcessError::SemaphoreNotFound(_) => Errno::EINVAL,
+            ProcessError::SemaphoreDeleted(_) => Errno::EIDRM,
+            ProcessError::TooManyThreads(_) => Errno::EAGAIN,
+            ProcessError::TimedOut => Errno::ETIMEDOUT,
+            ProcessError::WouldBlock => Errno::EAGAIN,
+            ProcessError::InvalidArgument => Errno::EINVAL,
+            ProcessError::PermissionDenied => Errno::EPERM,
+            ProcessError::ResourceLimit(resource) => match resource {
+                RLIMIT_NOFILE => Errno::EMFILE,
+                RLIMIT_AS => Errno::ENOMEM,
+                _ => Errno::EAGAIN,
+            },
+            ProcessError::Busy => Errno::EBUSY,
+            ProcessError::BadAddress => Errno::EFAULT,
+            ProcessError::NotImplemented => Errno::ENOSYS,
+            ProcessError::BadExecutable => Errno::ENOEXEC,
+            ProcessError::ArgumentListTooLong => Errno::E2BIG,
+            ProcessError::FileNotFound => Errno::ENOENT,
+            ProcessError::BadFileDescriptor => Errno::EBADF,
+            ProcessError::IoError => Errno::EIO,
+            ProcessError::NoDevice => Errno::ENODEV,
+        }
+    }
+}
+
+impl From<fs::DiskError> for Errno {
+    fn from(err: fs::DiskError) -> Self {
+        match err {
+            fs::DiskError::Io => Errno::EIO,
+            fs::DiskError::Unsupported => Errno::ENODEV,
+        }
+    }
+}
+
+impl From<fs::Error> for Errno {
+    fn from(err: fs::Error) -> Self {
+        match err {
+            fs::Error::FileNotOpen => Errno::EBADF,
+            fs::Error::FileNotExist => Errno::ENOENT,
+            fs::Error::NotDirectory => Errno::ENOTDIR,
+            fs::Error::IsDirectory => Errno::EISDIR,
+            fs::Error::BadName => Errno::EINVAL,
+            fs::Error::NameTooLong => Errno::ENAMETOOLONG,
+            fs::Error::NoSpace => Errno::ENOSPC,
+            fs::Error::UnknownOption => Errno::EINVAL,
+            fs::Error::TooManyFiles => Errno::EMFILE,
+            fs::Error::MemoryLimit => Errno::ENOMEM,
+            fs::Error::BadExecutable(_) => Errno::ENOEXEC,
+            fs::Error::Disk(err) => err.into(),
+        }
+    }
+}
+
+impl From<FutexError> for Errno {
+    fn from(err: FutexError) -> Self {
+        match err {
+            FutexError::WouldBlock => Errno::EAGAIN,
+            FutexError::TimedOut => Errno::ETIMEDOUT,
+            FutexError::Interrupted => Errno::EINTR,
+            FutexError::Process(err) => err.into(),
+        }
+    }
+}
+
+impl From<CheckpointError> for Errno {
+    fn from(err: CheckpointError) -> Self {
+        match err {
+            CheckpointError::Process(err) => err.into(),
+            CheckpointError::Fs(err) => err.into(),
+            CheckpointError::NotStopped => Errno::EBUSY,
+            CheckpointError::MultiThreaded => Errno::EINVAL,
+            CheckpointError::BadImage => Errno::ENOEXEC,
+        }
+    }
+}
+
+// the contents of bin/errno.h
+pub fn c_header() -> String {
+    let mut s = String::from("// generated from src/errno.rs, don't edit\n#pragma once\n\n");
+    for errno in ERRNOS.iter() {
+        s.push_str(&format!(
+            "#define {} {} // {}\n",
+            errno.name(),
+            errno.val(),
+            errno.description()
+        ));
+    }
+    s
+}
+
+// the contents of bin/errno.rs
+pub fn rust_module() -> String {
+    let mut s = String::from("// generated from src/errno.rs, don't edit\n\n");
+    for errno in ERRNOS.iter() {
+        s.push_str(&format!(
+            "pub const {}: isize = {}; // {}\n",
+            errno.name(),
+            errno.val(),
+            errno.description()
+        ));
+    }
+    s
+}
@@ -0,0 +1,84 @@
+use crate::fs;
+use crate::fs::file_system;
+use crate::process::*;
+use crate::rlimit::RLIMIT_AS;
+use crate::*;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// scripts
+// a file starting with "#!" runs through the interpreter named on that line, with at most one
+// argument after it: "#!/bin/sh -e" runs `path` as
+//   /bin/sh -e path argv[1] ...
+// the interpreter can be a script itself, up to MAX_SCRIPT_DEPTH levels.
+
+const MAX_LINE: usize = 256;
+const MAX_SCRIPT_DEPTH: usize = 4;
+
+// the interpreter and its argument on the "#!" line at the start of `data`, None if it isn't a
+// script
+pub fn parse_shebang(data: &[u8]) -> Result<Option<(String, Option<String>)>, ProcessError> {
+    if !data.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let line = &data[2..];
+    let end = line
+        .iter()
+        .position(|ch| *ch == b'\n')
+        .unwrap_or(line.len());
+    if end > MAX_LINE {
+        return Err(ProcessError::BadExecutable);
+    }
+    let line = core::str::from_utf8(&line[..end]).map_err(|_| ProcessError::BadExecutable)?;
+
+    let blank = |ch: char| ch == ' ' || ch == '\t' || ch == '\r';
+    let line = line.trim_matches(blank);
+    let (interp, arg) = match line.find(blank) {
+        Some(space) => (&line[..space], Some(line[space..].trim_matches(blank))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(ProcessError::BadExecutable);
+    }
+    Ok(Some((String::from(interp), arg.map(String::from))))
+}
+
+// the program which runs for `path` and its arguments, following "#!" lines
+pub fn resolve(path: &str, argv: &[&str]) -> Result<(String, Vec<String>), ProcessError> {
+    let mut path = String::from(path);
+    let mut argv: Vec<String> = argv.iter().map(|arg| String::from(*arg)).collect();
+    for _ in 0..=MAX_SCRIPT_DEPTH {
+        let head = unsafe { file_system() }
+            .lock()
+            .read_head(&path, MAX_LINE + 3)
+            .map_err(|err| load_error(&path, err))?;
+        let (interp, arg) = match parse_shebang(&head)? {
+            Some(shebang) => shebang,
+            None => return Ok((path, argv)),
+        };
+
+        // argv[0] of the script gives way to its path
+        let mut script_argv = vec![interp.clone()];
+        script_argv.extend(arg);
+        script_argv.push(path);
+        script_argv.extend(argv.into_iter().skip(1));
+        path = interp;
+        argv = script_argv;
+    }
+
+    println!("too many levels of interpreters for {}", path);
+    Err(ProcessError::BadExecutable)
+}
+
+// the error user space sees for a program at `path` which failed to load
+pub fn load_error(path: &str, err: fs::Error) -> ProcessError {
+    match err {
+        fs::Error::FileNotExist => ProcessError::FileNotFound,
+        fs::Error::MemoryLimit => ProcessError::ResourceLimit(RLIMIT_AS),
+        err => {
+            println!("failed to load {}: {:?}", path, err);
+            ProcessError::BadExecutable
+        }
+    }
+}
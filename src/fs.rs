@@ -3,31 +3,57 @@ pub mod fat;
 use core::mem::MaybeUninit;
 
 use crate::arch::riscv64::virtio::virtio_blk::*;
+use crate::rlimit::RLIM_INFINITY;
 use crate::*;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
 use spin::Mutex;
 
 pub static mut FS: MaybeUninit<Mutex<FileSystem<fat::Fat32<VirtioBlk>>>> = MaybeUninit::uninit();
 
+// a request the disk driver couldn't carry out
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DiskError {
+    Io,          // the device failed the request
+    Unsupported, // the device doesn't do this kind of request
+}
+
 pub trait Disk {
-    fn read_sector(&mut self, sector: usize, buffer: &mut [u8]);
-    fn write_sector(&mut self, sector: usize, buffer: &mut [u8]);
+    fn read_sector(&mut self, sector: usize, buffer: &mut [u8]) -> Result<(), DiskError>;
+    fn write_sector(&mut self, sector: usize, buffer: &mut [u8]) -> Result<(), DiskError>;
     fn sector_size(&self) -> usize;
 }
 
 #[derive(Debug)]
 pub enum Error {
-    Msg(String),
     FileNotOpen,
     FileNotExist,
+    NotDirectory,
+    IsDirectory,
+    BadName,     // a file name the file system can't store
+    NameTooLong, // longer than 255 bytes
+    NoSpace,     // the volume is full
     UnknownOption,
+    TooManyFiles,          // RLIMIT_NOFILE reached
+    MemoryLimit,           // RLIMIT_AS reached while loading a program
+    BadExecutable(String), // a malformed program or one for another machine
+    Disk(DiskError),
+}
+
+impl From<DiskError> for Error {
+    fn from(err: DiskError) -> Self {
+        Error::Disk(err)
+    }
 }
 
 pub trait BackingFileSystem {
     fn read_at(&mut self, buffer: &mut [u8], path: &str, offset: usize) -> Result<usize, Error>;
     fn file_size(&mut self, path: &str) -> Result<usize, Error>;
+    // create `path` or replace its contents
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error>;
 }
 
 #[allow(non_camel_case_types)]
@@ -38,8 +64,9 @@ pub enum SeekWhence {
     SEEK_END = 2,
 }
 
-type FileDesc = usize;
+pub type FileDesc = usize;
 
+#[derive(Clone)]
 pub struct File {
     pub path: String,
     pub fd: FileDesc,
@@ -58,49 +85,100 @@ impl File {
     }
 }
 
-pub struct FileSystem<'a, T: BackingFileSystem> {
-    backing: &'a mut T,
-    desc_table: BTreeMap<FileDesc, File>,
+// descriptor table of a process, shared by its threads
+#[derive(Clone)]
+pub struct FileTable {
+    files: BTreeMap<FileDesc, File>,
     curr_fd: FileDesc,
+    max_files: usize, // RLIMIT_NOFILE of the owner
 }
 
-impl<'a, T: BackingFileSystem> FileSystem<'a, T> {
-    pub fn new(backing: &'a mut T) -> Self {
-        FileSystem {
-            backing,
-            desc_table: BTreeMap::new(),
+impl FileTable {
+    pub fn new() -> Self {
+        FileTable {
+            files: BTreeMap::new(),
             curr_fd: 3,
+            max_files: RLIM_INFINITY,
         }
     }
 
-    pub fn open_file(&mut self, path: &str) -> Result<FileDesc, Error> {
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn set_max_files(&mut self, max_files: usize) {
+        self.max_files = max_files;
+    }
+
+    pub fn open_file<B: BackingFileSystem>(
+        &mut self,
+        backing: &mut B,
+        path: &str,
+    ) -> Result<FileDesc, Error> {
+        if self.files.len() >= self.max_files {
+            return Err(Error::TooManyFiles);
+        }
+
+        let size = backing.file_size(path)?;
         let fd = self.curr_fd;
         self.curr_fd += 1;
-        let size = self.backing.file_size(path)?;
         let file = File::new(path, fd, size);
-        self.desc_table.insert(fd, file);
+        self.files.insert(fd, file);
         Ok(fd)
     }
 
-    pub fn get_file_size(&mut self, fd: FileDesc) -> Result<usize, Error> {
-        Ok(self.desc_table.get(&fd).ok_or(Error::FileNotOpen)?.size)
+    // open `path` again as `fd`, for a restored process
+    pub fn reopen_file<B: BackingFileSystem>(
+        &mut self,
+        backing: &mut B,
+        path: &str,
+        fd: FileDesc,
+        offset: usize,
+    ) -> Result<(), Error> {
+        if self.files.len() >= self.max_files {
+            return Err(Error::TooManyFiles);
+        }
+
+        let size = backing.file_size(path)?;
+        let mut file = File::new(path, fd, size);
+        file.offset = offset;
+        self.files.insert(fd, file);
+        self.curr_fd = self.curr_fd.max(fd + 1);
+        Ok(())
+    }
+
+    pub fn close(&mut self, fd: FileDesc) -> Result<(), Error> {
+        self.files.remove(&fd).ok_or(Error::FileNotOpen)?;
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &File> {
+        self.files.values()
+    }
+
+    pub fn get_file(&self, fd: FileDesc) -> Result<&File, Error> {
+        self.files.get(&fd).ok_or(Error::FileNotOpen)
+    }
+
+    pub fn get_file_size(&self, fd: FileDesc) -> Result<usize, Error> {
+        Ok(self.get_file(fd)?.size)
     }
 
     pub fn seek(&mut self, fd: FileDesc, offset: isize, whence: u32) -> Result<usize, Error> {
         if whence == SeekWhence::SEEK_SET as u32 {
-            let file = self.desc_table.get_mut(&fd).ok_or(Error::FileNotOpen)?;
+            let file = self.files.get_mut(&fd).ok_or(Error::FileNotOpen)?;
 
             file.offset = offset as usize;
             return Ok(file.offset);
         } else if whence == SeekWhence::SEEK_CUR as u32 {
-            let file = self.desc_table.get_mut(&fd).ok_or(Error::FileNotOpen)?;
+            let file = self.files.get_mut(&fd).ok_or(Error::FileNotOpen)?;
 
             let mut offset_isize = file.offset as isize;
             offset_isize += offset;
             file.offset = offset_isize as usize;
             return Ok(file.offset);
         } else if whence == SeekWhence::SEEK_END as u32 {
-            let file = self.desc_table.get_mut(&fd).ok_or(Error::FileNotOpen)?;
+            let file = self.files.get_mut(&fd).ok_or(Error::FileNotOpen)?;
 
             let mut offset_isize = file.size as isize;
             offset_isize += offset;
@@ -111,16 +189,99 @@ impl<'a, T: BackingFileSystem> FileSystem<'a, T> {
         }
     }
 
-    pub fn read(&mut self, fd: FileDesc, buffer: &mut [u8]) -> Result<usize, Error> {
-        let file = self.desc_table.get_mut(&fd).ok_or(Error::FileNotOpen)?;
-        let size = self
-            .backing
-            .read_at(buffer, file.path.as_str(), file.offset)?;
+    pub fn read<B: BackingFileSystem>(
+        &mut self,
+        backing: &mut B,
+        fd: FileDesc,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        let file = self.files.get_mut(&fd).ok_or(Error::FileNotOpen)?;
+        let size = backing.read_at(buffer, file.path.as_str(), file.offset)?;
         file.offset += size;
         Ok(size)
     }
 }
 
+pub struct FileSystem<'a, T: BackingFileSystem> {
+    backing: &'a mut T,
+    desc_table: FileTable, // files opened by the kernel itself
+}
+
+impl<'a, T: BackingFileSystem> FileSystem<'a, T> {
+    pub fn new(backing: &'a mut T) -> Self {
+        FileSystem {
+            backing,
+            desc_table: FileTable::new(),
+        }
+    }
+
+    pub fn open_file(&mut self, path: &str) -> Result<FileDesc, Error> {
+        self.desc_table.open_file(self.backing, path)
+    }
+
+    pub fn close(&mut self, fd: FileDesc) -> Result<(), Error> {
+        self.desc_table.close(fd)
+    }
+
+    pub fn get_file_size(&mut self, fd: FileDesc) -> Result<usize, Error> {
+        self.desc_table.get_file_size(fd)
+    }
+
+    pub fn seek(&mut self, fd: FileDesc, offset: isize, whence: u32) -> Result<usize, Error> {
+        self.desc_table.seek(fd, offset, whence)
+    }
+
+    pub fn read(&mut self, fd: FileDesc, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.desc_table.read(self.backing, fd, buffer)
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let size = self.backing.file_size(path)?;
+        let mut data = vec![0; size];
+        let read = self.backing.read_at(&mut data, path, 0)?;
+        data.truncate(read);
+        Ok(data)
+    }
+
+    // the first `size` bytes of the file at `path`, or all of a shorter file
+    pub fn read_head(&mut self, path: &str, size: usize) -> Result<Vec<u8>, Error> {
+        let size = size.min(self.backing.file_size(path)?);
+        let mut data = vec![0; size];
+        let read = self.backing.read_at(&mut data, path, 0)?;
+        data.truncate(read);
+        Ok(data)
+    }
+
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.backing.write_file(path, data)
+    }
+
+    // the same operations on the descriptor table of a process
+
+    pub fn open_file_in(&mut self, table: &mut FileTable, path: &str) -> Result<FileDesc, Error> {
+        table.open_file(self.backing, path)
+    }
+
+    pub fn read_in(
+        &mut self,
+        table: &mut FileTable,
+        fd: FileDesc,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        table.read(self.backing, fd, buffer)
+    }
+
+    pub fn reopen_file_in(
+        &mut self,
+        table: &mut FileTable,
+        path: &str,
+        fd: FileDesc,
+        offset: usize,
+    ) -> Result<(), Error> {
+        table.reopen_file(self.backing, path, fd, offset)
+    }
+}
+
 pub unsafe fn file_system(
 ) -> &'static mut Mutex<FileSystem<'static, fat::Fat32<'static, VirtioBlk>>> {
     FS.assume_init_mut()
@@ -5,10 +5,13 @@ use alloc::alloc::alloc_zeroed;
 use alloc::alloc::dealloc;
 use alloc::format;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::cmp::min;
 use core::mem::MaybeUninit;
+use core::ops::Range;
+use core::slice;
 use core::slice::from_raw_parts_mut;
 use spin::Mutex;
 
@@ -21,6 +24,11 @@ pub static mut FAT32_FS: MaybeUninit<Mutex<Fat32<VirtioBlk>>> = MaybeUninit::uni
 
 // https://wiki.osdev.org/FAT
 
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LFN: u8 = 0x0F;
+const FAT_EOC: u32 = 0x0FFFFFFF; // end of a cluster chain
+
 #[derive(Copy, Clone)]
 #[repr(C, packed)]
 pub struct DirEntry {
@@ -92,6 +100,10 @@ pub struct Fat32<'a, T: Disk> {
     sectors_per_cluster: u8,
     root_dir_first_cluster: u32,
     sector_size: u32,
+    fat_size: u32,      // sectors per FAT
+    num_fats: u32,      // copies of the FAT, all of them are written
+    cluster_count: u32, // data clusters, numbered from 2
+    free_hint: u32,     // where the search for a free cluster starts
     sid: usize,
 }
 
@@ -106,7 +118,11 @@ impl<'a, T: Disk> Fat32<'a, T> {
             sectors_per_cluster: 0,
             root_dir_first_cluster: 0,
             sector_size: 512,
-            sid: pm.create_semaphore(1),
+            fat_size: 0,
+            num_fats: 0,
+            cluster_count: 0,
+            free_hint: 2,
+            sid: pm.create_mutex(),
         }
     }
 
@@ -114,7 +130,7 @@ impl<'a, T: Disk> Fat32<'a, T> {
         let pm = process_manager();
         pm.wait_semaphore(self.sid).expect("process");
 
-        self.read_bootsector();
+        self.read_bootsector().expect("fat32: boot sector");
         let bs = *(self.buffer.as_mut_ptr() as *mut Fat32BootSector);
 
         let fat_start_sector = bs.BPB_RsvdSecCnt as u32;
@@ -155,6 +171,9 @@ impl<'a, T: Disk> Fat32<'a, T> {
         self.sectors_per_cluster = bs.BPB_SecPerClus;
         self.root_dir_first_cluster = root_dir_first_cluster;
         self.sector_size = bs.BPB_BytsPerSec as u32;
+        self.fat_size = fatsz;
+        self.num_fats = bs.BPB_NumFATs as u32;
+        self.cluster_count = count_of_clusters;
 
         // self.list_all_files_in_dir(self.cluster_begin);
 
@@ -231,77 +250,16 @@ impl<'a, T: Disk> Fat32<'a, T> {
         }
     }
 
-    pub unsafe fn find_file(&mut self, mut dir_cluster: u32, file_name: &str) -> Option<DirEntry> {
-        let mut curr_idx = 0;
-        let layout = Layout::from_size_align(512, 8).unwrap();
-        let buffer = alloc_zeroed(layout);
-        let buf_slice = from_raw_parts_mut(buffer, 512);
-
-        let mut found = false;
-
-        self.read_cluster(dir_cluster, buf_slice);
-
-        loop {
-            let mut entry = buffer.add(curr_idx * 32);
-            let is_end = entry.read() == 0x00;
-            let _is_unused = entry.read() == 0xE5;
-            if is_end {
-                break;
-            }
-
-            let is_lfn = entry.add(11).read() == 0x0F;
-            if is_lfn {
-                let lfn_entry = entry as *mut LFNEntry;
-                let name = self.lfn_to_string(*lfn_entry);
-                if name == file_name {
-                    found = true;
-                }
-                curr_idx += 1;
-
-                if curr_idx >= 16 {
-                    let next_cluster = self.next_cluster(dir_cluster);
-                    if let Some(cluster) = next_cluster {
-                        self.read_cluster(cluster, buf_slice);
-                        dir_cluster = cluster;
-                    } else {
-                        break;
-                    }
-                    curr_idx %= 16;
-                }
-
-                entry = buffer.add(curr_idx * 32);
-            }
-
-            let dir_entry = entry as *mut DirEntry;
-            if found {
-                dealloc(buffer, layout);
-                return Some(*dir_entry);
-            } else {
-                let name = self.dir_entry_name(*dir_entry);
-                if name == file_name.to_uppercase() {
-                    dealloc(buffer, layout);
-                    return Some(*dir_entry);
-                }
-            }
-            curr_idx += 1;
-
-            if curr_idx >= 16 {
-                let next_cluster = self.next_cluster(dir_cluster);
-                if let Some(cluster) = next_cluster {
-                    self.read_cluster(cluster, buf_slice);
-                    dir_cluster = cluster;
-                } else {
-                    break;
-                }
-                curr_idx %= 16;
-            }
-        }
-
-        dealloc(buffer, layout);
-        None
+    pub fn find_file(
+        &mut self,
+        dir_cluster: u32,
+        file_name: &str,
+    ) -> Result<Option<DirEntry>, Error> {
+        let dir = self.read_dir(dir_cluster)?;
+        Ok(self.find_in_dir(&dir, file_name).map(|(_, entry)| entry))
     }
 
-    pub fn get_entry_from_path(&mut self, path: &str) -> Option<DirEntry> {
+    pub fn get_entry_from_path(&mut self, path: &str) -> Result<Option<DirEntry>, Error> {
         let mut cluster = self.root_dir_first_cluster;
         let mut curr_entry = None;
         let split_path: Vec<&str> = path.split('/').collect();
@@ -309,25 +267,25 @@ impl<'a, T: Disk> Fat32<'a, T> {
             if name.len() == 0 {
                 continue;
             }
-            if let Some(entry) = unsafe { self.find_file(cluster, name) } {
+            if let Some(entry) = self.find_file(cluster, name)? {
                 cluster =
                     (entry.first_cluster_high as u32) << 16 | (entry.first_cluster_low as u32);
                 curr_entry = Some(entry);
             } else {
-                return None;
+                return Ok(None);
             }
         }
 
-        curr_entry
+        Ok(curr_entry)
     }
 
-    pub unsafe fn list_all_files_in_dir(&mut self, mut cluster_num: u32) {
+    pub unsafe fn list_all_files_in_dir(&mut self, mut cluster_num: u32) -> Result<(), Error> {
         let mut curr_idx = 0;
         let layout = Layout::from_size_align(512, 8).unwrap();
         let buffer = alloc_zeroed(layout);
         let buf_slice = from_raw_parts_mut(buffer, 512);
 
-        self.read_cluster(cluster_num, buf_slice);
+        self.read_cluster(cluster_num, buf_slice)?;
         loop {
             let mut entry = buffer.add(curr_idx * 32);
             let is_end = entry.read() == 0x00;
@@ -344,9 +302,9 @@ impl<'a, T: Disk> Fat32<'a, T> {
                 curr_idx += 1;
 
                 if curr_idx >= 128 {
-                    let next_cluster = self.next_cluster(cluster_num);
+                    let next_cluster = self.next_cluster(cluster_num)?;
                     if let Some(cluster) = next_cluster {
-                        self.read_cluster(cluster, buf_slice);
+                        self.read_cluster(cluster, buf_slice)?;
                         cluster_num = cluster;
                     } else {
                         break;
@@ -365,9 +323,9 @@ impl<'a, T: Disk> Fat32<'a, T> {
             curr_idx += 1;
 
             if curr_idx >= 128 {
-                let next_cluster = self.next_cluster(cluster_num);
+                let next_cluster = self.next_cluster(cluster_num)?;
                 if let Some(cluster) = next_cluster {
-                    self.read_cluster(cluster, buf_slice);
+                    self.read_cluster(cluster, buf_slice)?;
                     cluster_num = cluster;
                 } else {
                     break;
@@ -377,59 +335,391 @@ impl<'a, T: Disk> Fat32<'a, T> {
         }
 
         dealloc(buffer, layout);
+        Ok(())
     }
 
-    fn read_sector(&mut self, sector_num: u32, buffer: &mut [u8]) {
+    fn read_sector(&mut self, sector_num: u32, buffer: &mut [u8]) -> Result<(), Error> {
         for i in 0..(self.sector_size as usize / self.disk.sector_size()) {
-            self.disk.read_sector(sector_num as usize + i, buffer);
+            self.disk.read_sector(sector_num as usize + i, buffer)?;
         }
+        Ok(())
     }
 
-    pub fn read_cluster(&mut self, cluster_num: u32, buffer: &mut [u8]) {
+    pub fn read_cluster(&mut self, cluster_num: u32, buffer: &mut [u8]) -> Result<(), Error> {
         let first_sector = self.sector_of_cluster(cluster_num);
         for i in 0..self.sectors_per_cluster as u32 {
             self.read_sector(
                 first_sector + i,
                 &mut buffer[(i * self.sector_size) as usize..],
-            );
+            )?;
         }
+        Ok(())
+    }
+
+    pub fn write_cluster(&mut self, cluster_num: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        let first_sector = self.sector_of_cluster(cluster_num);
+        for i in 0..self.sectors_per_cluster as u32 {
+            self.disk.write_sector(
+                (first_sector + i) as usize,
+                &mut buffer[(i * self.sector_size) as usize..],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sector_size as usize * self.sectors_per_cluster as usize
     }
 
     fn sector_of_cluster(&self, cluster_num: u32) -> u32 {
         ((cluster_num - 2) * self.sectors_per_cluster as u32) + self.cluster_begin
     }
 
-    fn next_cluster(&mut self, cluster_num: u32) -> Option<u32> {
+    fn fat_entry(&mut self, cluster_num: u32) -> Result<u32, Error> {
         let fat_offset = cluster_num * 4;
         let fat_sector = self.fat_begin + (fat_offset / self.sector_size);
         let ent_offset = fat_offset % self.sector_size;
         self.disk
-            .read_sector(fat_sector as usize, &mut self.buffer[0..]);
-        let table_value = unsafe {
-            (self.buffer.as_ptr().add(ent_offset as usize) as *const u32).read() & 0x0FFFFFFF
+            .read_sector(fat_sector as usize, &mut self.buffer[0..])?;
+        let entry = unsafe { (self.buffer.as_ptr().add(ent_offset as usize) as *const u32).read() };
+        Ok(entry & 0x0FFFFFFF)
+    }
+
+    // every copy of the FAT is updated, the reserved top bits are kept
+    fn set_fat_entry(&mut self, cluster_num: u32, value: u32) -> Result<(), Error> {
+        let fat_offset = cluster_num * 4;
+        let ent_offset = (fat_offset % self.sector_size) as usize;
+        for fat in 0..self.num_fats {
+            let fat_sector = self.fat_begin + fat * self.fat_size + fat_offset / self.sector_size;
+            self.disk
+                .read_sector(fat_sector as usize, &mut self.buffer[0..])?;
+            unsafe {
+                let entry = self.buffer.as_mut_ptr().add(ent_offset) as *mut u32;
+                entry.write((entry.read() & 0xF0000000) | (value & 0x0FFFFFFF));
+            }
+            self.disk
+                .write_sector(fat_sector as usize, &mut self.buffer[0..])?;
+        }
+        Ok(())
+    }
+
+    // a free cluster, marked as the end of a chain
+    fn alloc_cluster(&mut self) -> Result<u32, Error> {
+        let last = self.cluster_count + 2;
+        for cluster in (self.free_hint..last).chain(2..self.free_hint) {
+            if self.fat_entry(cluster)? == 0 {
+                self.set_fat_entry(cluster, FAT_EOC)?;
+                self.free_hint = cluster + 1;
+                return Ok(cluster);
+            }
+        }
+        Err(Error::NoSpace)
+    }
+
+    fn free_chain(&mut self, first_cluster: u32) -> Result<(), Error> {
+        let mut cluster = Some(first_cluster);
+        while let Some(curr) = cluster {
+            if curr < 2 {
+                break;
+            }
+            cluster = self.next_cluster(curr)?;
+            self.set_fat_entry(curr, 0)?;
+            self.free_hint = self.free_hint.min(curr);
+        }
+        Ok(())
+    }
+
+    // all the entries of a directory, a long name may span its clusters
+    fn read_dir(&mut self, first_cluster: u32) -> Result<Directory, Error> {
+        let cluster_size = self.cluster_size();
+        let mut dir = Directory {
+            clusters: Vec::new(),
+            data: Vec::new(),
         };
+        let mut buffer = vec![0; cluster_size];
+        let mut cluster = Some(first_cluster);
+        while let Some(curr) = cluster {
+            self.read_cluster(curr, &mut buffer)?;
+            dir.clusters.push(curr);
+            dir.data.extend_from_slice(&buffer);
+            cluster = self.next_cluster(curr)?;
+        }
+        Ok(dir)
+    }
+
+    fn write_dir(&mut self, dir: &mut Directory) -> Result<(), Error> {
+        let cluster_size = self.cluster_size();
+        for (i, cluster) in dir.clusters.iter().enumerate() {
+            self.write_cluster(
+                *cluster,
+                &mut dir.data[i * cluster_size..(i + 1) * cluster_size],
+            )?;
+        }
+        Ok(())
+    }
+
+    // append a cluster of end entries
+    fn grow_dir(&mut self, dir: &mut Directory) -> Result<(), Error> {
+        let cluster = self.alloc_cluster()?;
+        let last = *dir.clusters.last().unwrap();
+        self.set_fat_entry(last, cluster)?;
+        dir.clusters.push(cluster);
+        dir.data.resize(dir.data.len() + self.cluster_size(), 0);
+        Ok(())
+    }
+
+    // the entry named `name`, with the range of entries holding it and its long name
+    fn find_in_dir(&self, dir: &Directory, name: &str) -> Option<(Range<usize>, DirEntry)> {
+        let mut run_start = None;
+        let mut long_name = String::new();
+        for index in 0..dir.entries() {
+            let entry = &dir.data[index * 32..(index + 1) * 32];
+            if entry[0] == 0x00 {
+                break;
+            }
+            if entry[0] == 0xE5 {
+                run_start = None;
+                continue;
+            }
+
+            if entry[11] == ATTR_LFN {
+                let lfn = unsafe { (entry.as_ptr() as *const LFNEntry).read_unaligned() };
+                if lfn.order & 0x40 != 0 || run_start.is_none() {
+                    run_start = Some(index);
+                    long_name.clear();
+                }
+                // the last part of the name comes first
+                long_name.insert_str(0, &self.lfn_to_string(lfn));
+                continue;
+            }
+
+            let dir_entry = unsafe { (entry.as_ptr() as *const DirEntry).read_unaligned() };
+            if (run_start.is_some() && long_name.eq_ignore_ascii_case(name))
+                || self.dir_entry_name(dir_entry).eq_ignore_ascii_case(name)
+            {
+                return Some((run_start.unwrap_or(index)..index + 1, dir_entry));
+            }
+            run_start = None;
+        }
+        None
+    }
+
+    // cluster of the directory `path`
+    fn dir_cluster(&mut self, path: &str) -> Result<u32, Error> {
+        if path.split('/').all(|name| name.is_empty()) {
+            return Ok(self.root_dir_first_cluster);
+        }
+
+        let entry = self.get_entry_from_path(path)?.ok_or(Error::FileNotExist)?;
+        if entry.attr & ATTR_DIRECTORY == 0 {
+            return Err(Error::NotDirectory);
+        }
+        Ok((entry.first_cluster_high as u32) << 16 | entry.first_cluster_low as u32)
+    }
+
+    fn next_cluster(&mut self, cluster_num: u32) -> Result<Option<u32>, Error> {
+        let table_value = self.fat_entry(cluster_num)?;
 
         if table_value >= 0x0FFFFFF8 {
             // no cluster in the chain
-            None
+            Ok(None)
         } else if table_value == 0x0FFFFFF7 {
-            // bad cluster
-            panic!("bad cluster");
+            // bad cluster, marked unreadable by the formatter
+            Err(Error::Disk(DiskError::Io))
         } else {
-            Some(table_value)
+            Ok(Some(table_value))
         }
     }
 
-    unsafe fn read_bootsector(&mut self) {
-        self.disk.read_sector(0, &mut self.buffer[0..]);
+    unsafe fn read_bootsector(&mut self) -> Result<(), Error> {
+        self.disk.read_sector(0, &mut self.buffer[0..])?;
+        Ok(())
     }
 }
 
+// 8.3 name of a new file, in upper case and padded with spaces
+fn short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let valid = |part: &str| {
+        part.bytes()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-' || ch == b'~')
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || !valid(base) || !valid(ext) {
+        return None;
+    }
+
+    let mut short_name = [b' '; 11];
+    for (i, ch) in base.bytes().enumerate() {
+        short_name[i] = ch.to_ascii_uppercase();
+    }
+    for (i, ch) in ext.bytes().enumerate() {
+        short_name[8 + i] = ch.to_ascii_uppercase();
+    }
+    Some(short_name)
+}
+
+// the entries of a directory, read whole
+struct Directory {
+    clusters: Vec<u32>,
+    data: Vec<u8>,
+}
+
+impl Directory {
+    fn entries(&self) -> usize {
+        self.data.len() / 32
+    }
+
+    // the first of `count` free entries in a row
+    fn free_run(&self, count: usize) -> Option<usize> {
+        let mut start = 0;
+        for index in 0..self.entries() {
+            match self.data[index * 32] {
+                // every entry after the end one is free
+                0x00 => break,
+                0xE5 => {
+                    if index + 1 - start >= count {
+                        return Some(start);
+                    }
+                }
+                _ => start = index + 1,
+            }
+        }
+        if self.entries() - start >= count {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    fn short_names(&self) -> Vec<[u8; 11]> {
+        let mut names = Vec::new();
+        for entry in self.data.chunks(32) {
+            if entry[0] == 0x00 {
+                break;
+            }
+            if entry[0] != 0xE5 && entry[11] != ATTR_LFN {
+                let mut name = [0; 11];
+                name.copy_from_slice(&entry[..11]);
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+// the short name stored along with a long name, "BASIS~N.EXT" with a number not taken yet
+fn short_alias(name: &str, dir: &Directory) -> [u8; 11] {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+    let convert = |part: &str| -> Vec<u8> {
+        part.bytes()
+            .filter(|ch| *ch != b'.' && *ch != b' ')
+            .map(|ch| {
+                if ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-' {
+                    ch.to_ascii_uppercase()
+                } else {
+                    b'_'
+                }
+            })
+            .collect()
+    };
+    let base = convert(base);
+    let ext = convert(ext);
+
+    let taken = dir.short_names();
+    let mut alias = [b' '; 11];
+    for (i, ch) in ext.iter().take(3).enumerate() {
+        alias[8 + i] = *ch;
+    }
+    for number in 1.. {
+        let tail = format!("~{}", number);
+        let keep = min(base.len(), 8 - tail.len());
+        alias[..8].fill(b' ');
+        alias[..keep].copy_from_slice(&base[..keep]);
+        alias[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        if !taken.contains(&alias) {
+            break;
+        }
+    }
+    alias
+}
+
+fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, ch| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*ch)
+    })
+}
+
+// the long name entries in the order they are stored, the last part of the name first
+fn lfn_entries(name: &str, short_name: [u8; 11]) -> Vec<u8> {
+    let chars: Vec<u16> = name.chars().map(|ch| ch as u16).collect();
+    let count = (chars.len() + 12) / 13;
+    let checksum = lfn_checksum(&short_name);
+    let mut entries = Vec::new();
+    for order in (1..=count).rev() {
+        // the name ends with a null, the rest is padded with 0xFFFF
+        let mut part = [0xFFFF; 13];
+        for (i, ch) in part.iter_mut().enumerate() {
+            let pos = (order - 1) * 13 + i;
+            if pos < chars.len() {
+                *ch = chars[pos];
+            } else if pos == chars.len() {
+                *ch = 0;
+            }
+        }
+
+        let mut name1 = [0; 5];
+        let mut name2 = [0; 6];
+        let mut name3 = [0; 2];
+        name1.copy_from_slice(&part[0..5]);
+        name2.copy_from_slice(&part[5..11]);
+        name3.copy_from_slice(&part[11..13]);
+        let lfn = LFNEntry {
+            order: if order == count {
+                order as u8 | 0x40
+            } else {
+                order as u8
+            },
+            name1,
+            attr: ATTR_LFN,
+            lfn_type: 0,
+            checksum,
+            name2,
+            _zero: 0,
+            name3,
+        };
+        let bytes = unsafe { slice::from_raw_parts(&lfn as *const LFNEntry as *const u8, 32) };
+        entries.extend_from_slice(bytes);
+    }
+    entries
+}
+
+fn dir_entry_bytes(short_name: [u8; 11], first_cluster: u32, size: usize) -> [u8; 32] {
+    let entry = DirEntry {
+        file_name: short_name,
+        attr: ATTR_ARCHIVE,
+        _reserved: 0,
+        creation_time_sec: 0,
+        creation_time: 0,
+        creation_date: 0,
+        last_access_date: 0,
+        first_cluster_high: (first_cluster >> 16) as u16,
+        last_mod_time: 0,
+        last_mod_date: 0,
+        first_cluster_low: first_cluster as u16,
+        size: size as u32,
+    };
+    unsafe { core::mem::transmute(entry) }
+}
+
 impl<'a, T: Disk> BackingFileSystem for Fat32<'a, T> {
     fn read_at(&mut self, buffer: &mut [u8], path: &str, offset: usize) -> Result<usize, Error> {
-        let entry = self
-            .get_entry_from_path(path)
-            .ok_or(Error::Msg(format!("file does not exist: {}", path)))?;
+        let entry = self.get_entry_from_path(path)?.ok_or(Error::FileNotExist)?;
 
         let mut cluster = (entry.first_cluster_high as u32) << 16 | entry.first_cluster_low as u32;
         let cluster_size = self.sector_size * self.sectors_per_cluster as u32;
@@ -439,16 +729,11 @@ impl<'a, T: Disk> BackingFileSystem for Fat32<'a, T> {
         let mut read_bytes = 0;
         let mut read_clusters = 0;
 
-        let layout = Layout::from_size_align(
-            self.sector_size as usize * self.sectors_per_cluster as usize,
-            8,
-        )
-        .unwrap();
-        let tmp_buf = unsafe { alloc_zeroed(layout) };
+        let mut tmp_buf = vec![0; cluster_size as usize];
         while read_bytes < buffer.len() {
             if read_clusters < offset_cluster {
                 read_clusters += 1;
-                if let Some(clus_num) = self.next_cluster(cluster) {
+                if let Some(clus_num) = self.next_cluster(cluster)? {
                     cluster = clus_num;
                 } else {
                     return Ok(read_bytes);
@@ -456,44 +741,108 @@ impl<'a, T: Disk> BackingFileSystem for Fat32<'a, T> {
                 continue;
             }
 
-            self.read_cluster(cluster, unsafe {
-                from_raw_parts_mut(tmp_buf, cluster_size as usize)
-            });
+            self.read_cluster(cluster, &mut tmp_buf)?;
 
             let count = min(
                 (cluster_size - offset_byte) as usize,
                 buffer.len() - read_bytes,
             );
-            unsafe {
-                buffer[read_bytes..]
-                    .as_mut_ptr()
-                    .copy_from_nonoverlapping(tmp_buf.add(offset_byte as usize), count);
-            }
+            buffer[read_bytes..(read_bytes + count)]
+                .copy_from_slice(&tmp_buf[offset_byte as usize..(offset_byte as usize + count)]);
 
             read_bytes += count;
             offset_byte = 0;
             read_clusters += 1;
 
-            if let Some(clus_num) = self.next_cluster(cluster) {
+            if let Some(clus_num) = self.next_cluster(cluster)? {
                 cluster = clus_num;
             } else {
                 return Ok(read_bytes);
             }
         }
 
-        unsafe {
-            dealloc(tmp_buf, layout);
-        }
-
         Ok(read_bytes)
     }
 
     fn file_size(&mut self, path: &str) -> Result<usize, Error> {
-        let entry = self
-            .get_entry_from_path(path)
-            .ok_or(Error::Msg(format!("file does not exist: {}", path)))?;
+        let entry = self.get_entry_from_path(path)?.ok_or(Error::FileNotExist)?;
         Ok(entry.size as usize)
     }
+
+    // an existing file is replaced, a name which isn't 8.3 gets long name entries
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let (dir_path, name) = match path.rfind('/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => ("", path),
+        };
+        if name.is_empty() {
+            return Err(Error::BadName);
+        }
+        if name.len() > 255 {
+            return Err(Error::NameTooLong);
+        }
+        let dir_cluster = self.dir_cluster(dir_path)?;
+        let mut dir = self.read_dir(dir_cluster)?;
+        if let Some((entries, old)) = self.find_in_dir(&dir, name) {
+            if old.attr & ATTR_DIRECTORY != 0 {
+                return Err(Error::IsDirectory);
+            }
+            self.free_chain((old.first_cluster_high as u32) << 16 | old.first_cluster_low as u32)?;
+            for index in entries {
+                dir.data[index * 32] = 0xE5;
+            }
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut buffer = vec![0; cluster_size];
+        let mut first_cluster = 0;
+        let mut prev_cluster = None;
+        for chunk in data.chunks(cluster_size) {
+            let cluster = match self.alloc_cluster() {
+                Ok(cluster) => cluster,
+                Err(err) => {
+                    // the old file is gone already, its entries are freed as well
+                    self.free_chain(first_cluster)?;
+                    self.write_dir(&mut dir)?;
+                    return Err(err);
+                }
+            };
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            buffer[chunk.len()..].fill(0);
+            self.write_cluster(cluster, &mut buffer)?;
+
+            match prev_cluster {
+                Some(prev) => self.set_fat_entry(prev, cluster)?,
+                None => first_cluster = cluster,
+            }
+            prev_cluster = Some(cluster);
+        }
+
+        let entries = match short_name(name) {
+            Some(short_name) => dir_entry_bytes(short_name, first_cluster, data.len()).to_vec(),
+            None => {
+                let short_name = short_alias(name, &dir);
+                let mut entries = lfn_entries(name, short_name);
+                entries.extend_from_slice(&dir_entry_bytes(short_name, first_cluster, data.len()));
+                entries
+            }
+        };
+        let count = entries.len() / 32;
+        let start = loop {
+            if let Some(start) = dir.free_run(count) {
+                break start;
+            }
+            if let Err(err) = self.grow_dir(&mut dir) {
+                self.free_chain(first_cluster)?;
+                self.write_dir(&mut dir)?;
+                return Err(err);
+            }
+        };
+        dir.data[start * 32..(start + count) * 32].copy_from_slice(&entries);
+        self.write_dir(&mut dir)?;
+
+        Ok(())
+    }
 }
 
 impl<'a, T: BackingFileSystem> BackingFileSystem for Mutex<T> {
@@ -504,6 +853,10 @@ impl<'a, T: BackingFileSystem> BackingFileSystem for Mutex<T> {
     fn file_size(&mut self, path: &str) -> Result<usize, Error> {
         self.lock().file_size(path)
     }
+
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        self.lock().write_file(path, data)
+    }
 }
 
 #[cfg(target_arch = "riscv64")]
@@ -0,0 +1,211 @@
+use crate::arch::target::interrupt::interrupt_disable;
+use crate::arch::target::interrupt::interrupt_restore;
+use crate::process::*;
+use crate::spinlock::*;
+use crate::timer::*;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+// operations of the futex system call
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const FUTEX_REQUEUE: usize = 3;
+// accepted for compatibility, every futex is keyed by its physical address anyway
+pub const FUTEX_PRIVATE_FLAG: usize = 128;
+
+// waiters are spread over the buckets by the hash of their key
+const FUTEX_HASH_BITS: usize = 6;
+const FUTEX_HASH_SIZE: usize = 1 << FUTEX_HASH_BITS;
+
+pub static mut FT: Option<FutexTable> = None;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FutexError {
+    WouldBlock, // the futex word didn't hold the expected value
+    TimedOut,
+    Interrupted, // woken up by a signal
+    Process(ProcessError),
+}
+
+impl From<ProcessError> for FutexError {
+    fn from(err: ProcessError) -> Self {
+        FutexError::Process(err)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Waiter {
+    key: usize,
+    pid: Pid,
+}
+
+pub struct FutexTable {
+    buckets: [SpinLock<VecDeque<Waiter>>; FUTEX_HASH_SIZE],
+}
+
+impl FutexTable {
+    pub fn new() -> Self {
+        FutexTable {
+            buckets: array_init::array_init(|_| SpinLock::new(VecDeque::new())),
+        }
+    }
+
+    fn bucket(&mut self, key: usize) -> &mut SpinLock<VecDeque<Waiter>> {
+        // futex words are 4 byte aligned, fold the upper bits into the index as well
+        let word = key >> 2;
+        let hash = word ^ (word >> FUTEX_HASH_BITS) ^ (word >> (FUTEX_HASH_BITS * 2));
+        &mut self.buckets[hash & (FUTEX_HASH_SIZE - 1)]
+    }
+
+    fn enqueue(&mut self, key: usize, pid: Pid) {
+        self.bucket(key).lock_mut().push_back(Waiter { key, pid });
+    }
+
+    // returns false if `pid` isn't waiting on `key`
+    fn remove(&mut self, key: usize, pid: Pid) -> bool {
+        let mut queue = self.bucket(key).lock_mut();
+        match queue
+            .iter()
+            .position(|waiter| waiter.key == key && waiter.pid == pid)
+        {
+            Some(index) => {
+                queue.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // dequeue up to `count` waiters of `key` in FIFO order
+    fn take(&mut self, key: usize, count: usize) -> Vec<Pid> {
+        let mut queue = self.bucket(key).lock_mut();
+        let mut taken = Vec::new();
+        let mut index = 0;
+        while index < queue.len() && taken.len() < count {
+            if queue[index].key == key {
+                taken.push(queue.remove(index).unwrap().pid);
+            } else {
+                index += 1;
+            }
+        }
+
+        taken
+    }
+}
+
+pub unsafe fn futex_table() -> &'static mut FutexTable {
+    match FT {
+        Some(ref mut ft) => &mut *ft,
+        None => panic!("futex table is uninitialized"),
+    }
+}
+
+// sleep while the word at `key` (a physical address) holds `val`
+// `timeout` is in ticks, None waits forever
+pub fn futex_wait(key: usize, val: u32, timeout: Option<Tick>) -> Result<(), FutexError> {
+    let mask = interrupt_disable();
+
+    let ft = unsafe { futex_table() };
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+
+    // the value is checked with interrupts disabled, so a wake can't slip in before we sleep
+    if unsafe { (key as *const u32).read_volatile() } != val {
+        interrupt_restore(mask);
+        return Err(FutexError::WouldBlock);
+    }
+
+    ft.enqueue(key, running);
+    {
+        let mut ptable = pm.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, running)?;
+        proc.state = State::FutexWait;
+        proc.futex = Some(key);
+        proc.timer = timeout.map(|delay| add_timer(delay, TimerAction::Wakeup(running)));
+    }
+
+    pm.schedule()?;
+
+    let mut ptable = pm.ptable_lock_mut();
+    let proc = get_process_mut!(ptable, running)?;
+    let timer = proc.timer.take();
+    // `futex_wake` clears the key, a requeue might have changed it
+    let result = match proc.futex.take() {
+        None => Ok(()),
+        Some(key) => {
+            ft.remove(key, running);
+            if proc.signals.has_deliverable() || timeout.is_none() {
+                Err(FutexError::Interrupted)
+            } else {
+                Err(FutexError::TimedOut)
+            }
+        }
+    };
+    drop(ptable);
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+
+    interrupt_restore(mask);
+
+    result
+}
+
+// wake up to `count` processes waiting on `key`, returns how many were woken
+pub fn futex_wake(key: usize, count: usize) -> Result<usize, FutexError> {
+    let mask = interrupt_disable();
+
+    let ft = unsafe { futex_table() };
+    let pm = unsafe { process_manager() };
+
+    let pids = ft.take(key, count);
+    pm.defer_schedule(DeferCommand::Start)?;
+    for pid in pids.iter() {
+        get_process_mut!(pm.ptable_lock_mut(), *pid)?.futex = None;
+        pm.ready(*pid)?;
+    }
+    pm.defer_schedule(DeferCommand::Stop)?;
+
+    interrupt_restore(mask);
+
+    Ok(pids.len())
+}
+
+// wake up to `count` waiters of `key` and move up to `requeue` of the rest to `key2`
+pub fn futex_requeue(
+    key: usize,
+    count: usize,
+    key2: usize,
+    requeue: usize,
+) -> Result<usize, FutexError> {
+    let mask = interrupt_disable();
+
+    let ft = unsafe { futex_table() };
+    let pm = unsafe { process_manager() };
+
+    // the woken processes must not run before the rest are moved
+    pm.defer_schedule(DeferCommand::Start)?;
+    let woken = futex_wake(key, count)?;
+    for pid in ft.take(key, requeue).into_iter() {
+        get_process_mut!(pm.ptable_lock_mut(), pid)?.futex = Some(key2);
+        ft.enqueue(key2, pid);
+    }
+    pm.defer_schedule(DeferCommand::Stop)?;
+
+    interrupt_restore(mask);
+
+    Ok(woken)
+}
+
+// called when a waiting process is killed
+pub fn futex_cancel(key: usize, pid: Pid) {
+    let mask = interrupt_disable();
+    unsafe { futex_table() }.remove(key, pid);
+    interrupt_restore(mask);
+}
+
+pub fn init() {
+    unsafe {
+        FT = Some(FutexTable::new());
+    }
+}
@@ -241,6 +241,10 @@ impl Window {
         self.title = title.to_string();
     }
 
+    pub fn get_title(&self) -> &str {
+        self.title.as_str()
+    }
+
     pub fn get_frame(&self) -> FrameBuffer {
         self.frame
     }
@@ -875,6 +879,12 @@ impl WindowManager {
         lm.update(*layer_id);
     }
 
+    pub fn get_window_position(&self, id: ObjectId) -> (u32, u32) {
+        let layer_id = self.map.get(&id).unwrap();
+        let lm = unsafe { layer_manager() };
+        (lm.get_layer_x(*layer_id), lm.get_layer_y(*layer_id))
+    }
+
     pub fn get_window_frame(&mut self, id: ObjectId) -> FrameBuffer {
         let arena = unsafe { object_arena() };
         let window = arena.get(id).unwrap();
@@ -885,19 +895,19 @@ impl WindowManager {
             .get_frame()
     }
 
-    pub fn update_window_frame(&mut self, id: ObjectId) {
+    // `None` if `id` is not a window shown by the manager
+    pub fn update_window_frame(&mut self, id: ObjectId) -> Option<()> {
         let arena = unsafe { object_arena() };
-        let window = arena.get_mut(id).unwrap();
+        let window = (&mut **arena.get_mut(id)?)
+            .as_mut_any()
+            .downcast_mut::<Window>()?;
 
-        let layer_id = self.map.get(&id).unwrap();
+        let layer_id = self.map.get(&id)?;
         let lm = unsafe { layer_manager() };
-        let layer = lm.layers.get_mut(&layer_id).unwrap();
-        (&mut **window)
-            .as_mut_any()
-            .downcast_mut::<Window>()
-            .unwrap()
-            .update_frame(&mut layer.buffer);
+        let layer = lm.layers.get_mut(&layer_id)?;
+        window.update_frame(&mut layer.buffer);
         lm.update(*layer_id);
+        Some(())
     }
 
     pub fn get_highest_window_layer(&self) -> Option<LayerId> {
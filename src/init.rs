@@ -2,6 +2,11 @@ use crate::*;
 
 pub unsafe fn init_all() {
     allocator::init();
+    random::init();
     process::init();
+    timer::init();
+    futex::init();
+    watchdog::init();
     arch::target::init::init_all();
+    aslr::init();
 }
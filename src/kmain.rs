@@ -3,6 +3,7 @@ use crate::arch::riscv64::virtio::keyboard_device;
 use crate::arch::riscv64::virtio::mouse_device;
 use crate::arch::target::interrupt;
 use crate::arch::target::virtio::virtio_input::*;
+use crate::deadline::DeadlineParams;
 use crate::graphics::layer_manager;
 use crate::graphics::*;
 use crate::process::*;
@@ -69,11 +70,13 @@ pub unsafe extern "C" fn fs_proc() {
     let pm = process_manager();
     // pm.defer_schedule(DeferCommand::Start).unwrap();
     let pid = pm.create_process("mandelbrot", 1, true).unwrap();
-    pm.load_program(pid, "/bin/mandelbrot").unwrap();
+    pm.load_program(pid, "/bin/mandelbrot", &["/bin/mandelbrot"], &[])
+        .unwrap();
     pm.ready(pid).unwrap();
 
     let pid = pm.create_process("main", 1, true).unwrap();
-    pm.load_program(pid, "/bin/main").unwrap();
+    pm.load_program(pid, "/bin/main", &["/bin/main"], &[])
+        .unwrap();
     pm.ready(pid).unwrap();
     // pm.defer_schedule(DeferCommand::Stop).unwrap();
 
@@ -122,6 +125,9 @@ pub extern "C" fn kmain() {
     let pid = pm
         .create_kernel_process("kproc", 2, kproc as usize)
         .expect("process");
+    // the mouse cursor and the compositor keep running under heavy user load
+    pm.set_deadline(pid, Some(DeadlineParams::new(2, 4, 4)))
+        .expect("process");
     pm.ready(pid).expect("process");
 
     pm.defer_schedule(DeferCommand::Stop).expect("process");
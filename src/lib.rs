@@ -23,7 +23,6 @@ extern crate embedded_graphics;
 extern crate fontdue;
 extern crate goblin;
 extern crate hashbrown;
-extern crate intrusive_collections;
 extern crate libm;
 extern crate linked_list_allocator;
 extern crate spin;
@@ -33,13 +32,32 @@ extern crate volatile_register;
 
 pub mod allocator;
 pub mod arch;
+pub mod aslr;
+pub mod checkpoint;
+pub mod coredump;
+pub mod deadline;
 pub mod debug;
+pub mod errno;
+pub mod exec;
 pub mod fs;
+pub mod futex;
 pub mod graphics;
 pub mod init;
 pub mod kmain;
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
 pub mod process;
+pub mod ptrace;
+pub mod random;
+pub mod rlimit;
+pub mod signal;
 pub mod spinlock;
+pub mod strace;
+pub mod sync;
+pub mod timer;
+pub mod uaccess;
+pub mod watchdog;
+pub mod writer;
 
 #[macro_export]
 macro_rules! test_harness {
@@ -0,0 +1,229 @@
+use crate::arch::target::interrupt::interrupt_disable;
+use crate::arch::target::interrupt::interrupt_restore;
+use crate::process::Pid;
+use crate::*;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::panic::Location;
+
+// lock dependency checker, enabled by the `lockdep` feature
+// a lock class is the place a lock was created, every acquisition records which classes
+// were held at that moment, and a new order that closes a cycle is a possible deadlock
+
+pub type LockClass = &'static Location<'static>;
+type Site = &'static Location<'static>;
+
+static mut LD: Option<LockDep> = None;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum LockKind {
+    Spin,
+    Mutex, // sleeping lock
+}
+
+#[derive(Copy, Clone)]
+struct HeldLock {
+    class: LockClass,
+    kind: LockKind,
+    site: Site, // where it was taken
+}
+
+// where an order between two classes was first seen
+#[derive(Copy, Clone)]
+struct Dependency {
+    held_site: Site,
+    site: Site,
+}
+
+struct LockDep {
+    held: BTreeMap<Pid, Vec<HeldLock>>,
+    // class -> classes taken while holding it
+    graph: BTreeMap<LockClass, BTreeMap<LockClass, Dependency>>,
+    // each violation is printed once
+    reported: BTreeSet<(&'static str, Site, Site)>,
+}
+
+impl LockDep {
+    fn new() -> Self {
+        LockDep {
+            held: BTreeMap::new(),
+            graph: BTreeMap::new(),
+            reported: BTreeSet::new(),
+        }
+    }
+
+    fn first_report(&mut self, kind: &'static str, a: Site, b: Site) -> bool {
+        self.reported.insert((kind, a, b))
+    }
+
+    // dependency chain from `from` to `to`, if `to` is reachable
+    fn find_path(&self, from: LockClass, to: LockClass) -> Option<Vec<(LockClass, LockClass)>> {
+        let mut visited = BTreeSet::new();
+        let mut path = Vec::new();
+        if self.search(from, to, &mut visited, &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn search(
+        &self,
+        class: LockClass,
+        to: LockClass,
+        visited: &mut BTreeSet<LockClass>,
+        path: &mut Vec<(LockClass, LockClass)>,
+    ) -> bool {
+        if class == to {
+            return true;
+        }
+        if !visited.insert(class) {
+            return false;
+        }
+
+        if let Some(next) = self.graph.get(&class) {
+            for after in next.keys() {
+                path.push((class, *after));
+                if self.search(*after, to, visited, path) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+
+        false
+    }
+
+    fn add_dependency(&mut self, pid: Pid, held: HeldLock, new: HeldLock) {
+        if let Some(next) = self.graph.get(&held.class) {
+            if next.contains_key(&new.class) {
+                return;
+            }
+        }
+
+        if let Some(path) = self.find_path(new.class, held.class) {
+            if self.first_report("cycle", held.site, new.site) {
+                println!(
+                    "lockdep: possible circular locking dependency (pid {})",
+                    pid
+                );
+                println!("  lock {} taken at {}", new.class, new.site);
+                println!("  while holding lock {} taken at {}", held.class, held.site);
+                println!("  but the reverse order has been seen before:");
+                for (before, after) in path.iter() {
+                    let dep = self.graph[before][after];
+                    println!("  lock {} taken at {}", after, dep.site);
+                    println!("  while holding lock {} taken at {}", before, dep.held_site);
+                }
+            }
+        }
+
+        self.graph
+            .entry(held.class)
+            .or_insert_with(BTreeMap::new)
+            .insert(
+                new.class,
+                Dependency {
+                    held_site: held.site,
+                    site: new.site,
+                },
+            );
+    }
+
+    fn acquire(&mut self, pid: Pid, new: HeldLock, trylock: bool) {
+        let held = self.held.entry(pid).or_insert_with(Vec::new).clone();
+
+        if new.kind == LockKind::Mutex && !trylock {
+            for lock in held.iter().filter(|lock| lock.kind == LockKind::Spin) {
+                if self.first_report("sleep", lock.site, new.site) {
+                    println!(
+                        "lockdep: sleeping lock taken while holding a spinlock (pid {})",
+                        pid
+                    );
+                    println!("  mutex {} taken at {}", new.class, new.site);
+                    println!(
+                        "  while holding spinlock {} taken at {}",
+                        lock.class, lock.site
+                    );
+                }
+            }
+        }
+
+        for lock in held.iter() {
+            if lock.class == new.class {
+                if self.first_report("recursive", lock.site, new.site) {
+                    println!("lockdep: recursive locking detected (pid {})", pid);
+                    println!("  lock {} taken again at {}", new.class, new.site);
+                    println!("  already held, taken at {}", lock.site);
+                }
+            } else if !trylock {
+                // a trylock can't wait, so it doesn't add an order
+                self.add_dependency(pid, *lock, new);
+            }
+        }
+
+        self.held.get_mut(&pid).unwrap().push(new);
+    }
+
+    fn release(&mut self, pid: Pid, class: LockClass) {
+        if let Some(held) = self.held.get_mut(&pid) {
+            if let Some(index) = held.iter().rposition(|lock| lock.class == class) {
+                held.remove(index);
+            }
+        }
+    }
+
+    fn might_sleep(&mut self, pid: Pid) {
+        let held = match self.held.get(&pid) {
+            Some(held) => held.clone(),
+            None => return,
+        };
+
+        for lock in held.iter().filter(|lock| lock.kind == LockKind::Spin) {
+            if self.first_report("sleep", lock.site, lock.class) {
+                println!("lockdep: sleeping while holding a spinlock (pid {})", pid);
+                println!("  spinlock {} taken at {}", lock.class, lock.site);
+            }
+        }
+    }
+}
+
+fn lockdep() -> &'static mut LockDep {
+    unsafe { LD.get_or_insert_with(LockDep::new) }
+}
+
+// called before waiting for the lock, so a deadlock is reported before it hangs
+pub fn acquire(pid: Pid, class: LockClass, kind: LockKind, site: Site, trylock: bool) {
+    let mask = interrupt_disable();
+    lockdep().acquire(pid, HeldLock { class, kind, site }, trylock);
+    interrupt_restore(mask);
+}
+
+pub fn release(pid: Pid, class: LockClass) {
+    let mask = interrupt_disable();
+    lockdep().release(pid, class);
+    interrupt_restore(mask);
+}
+
+// called when `pid` blocks
+pub fn might_sleep(pid: Pid) {
+    let mask = interrupt_disable();
+    lockdep().might_sleep(pid);
+    interrupt_restore(mask);
+}
+
+// whether a violation of `kind` ("cycle", "recursive" or "sleep") has been reported for the
+// locks taken at `held` and `site`; a sleep outside of a lock has the held class as `site`
+pub fn reported(kind: &'static str, held: Site, site: Site) -> bool {
+    let mask = interrupt_disable();
+    let reported = lockdep().reported.contains(&(kind, held, site));
+    interrupt_restore(mask);
+    reported
+}
+
+// forget the locks of a killed process
+pub fn exit(pid: Pid) {
+    let mask = interrupt_disable();
+    lockdep().held.remove(&pid);
+    interrupt_restore(mask);
+}
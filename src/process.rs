@@ -1,7 +1,20 @@
+use crate::arch::target::clint::TICKS_PER_SEC;
 use crate::arch::target::interrupt::interrupt_disable;
 use crate::arch::target::interrupt::interrupt_restore;
 use crate::arch::target::process::*;
+use crate::arch::target::signal::SignalFrame;
+use crate::arch::target::watchdog;
+use crate::deadline::*;
+use crate::fs::FileTable;
+use crate::futex::futex_cancel;
+#[cfg(feature = "lockdep")]
+use crate::lockdep::{self, LockClass, LockKind};
+use crate::ptrace::{self, TraceState};
+use crate::rlimit::*;
+use crate::signal::*;
 use crate::spinlock::*;
+use crate::strace::{STRACE_CHILDREN, STRACE_SYSCALLS};
+use crate::timer::*;
 use crate::*;
 use alloc::alloc::alloc;
 use alloc::alloc::dealloc;
@@ -9,15 +22,17 @@ use alloc::boxed::Box;
 use alloc::collections::binary_heap::BinaryHeap;
 use alloc::collections::BTreeMap;
 use alloc::collections::VecDeque;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::alloc::Layout;
+use core::cell::RefCell;
 use core::cmp::Ordering;
 use core::marker::PhantomData;
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
 use hashbrown::HashMap;
-use intrusive_collections::intrusive_adapter;
-use intrusive_collections::{LinkedList, LinkedListLink};
 
 pub type Pid = usize;
 pub type Sid = usize;
@@ -29,6 +44,7 @@ pub enum ProcessEvent {
     MouseEvent,
     KeyboardEvent,
     Exit(usize),
+    Trace(Pid), // a traced process stopped or exited
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -37,40 +53,96 @@ pub enum State {
     Ready,
     Suspend,
     Sleep,
+    FutexWait,
     SemaWait,
     IOWait,
     EventWait,
+    Blocked, // on a sync::WaitQueue
+    Stopped,
+    Traced,    // stopped for its tracer, which resumes it
+    Throttled, // out of deadline runtime until its next period
     Free,
 }
 
 pub const KERNEL_STACK_SIZE: usize = 0x10000;
 
+// how far priority inheritance follows a chain of mutex owners
+const MAX_INHERITANCE_DEPTH: usize = 8;
+
+// flags of clone
+pub const CLONE_VM: usize = 0x100;
+pub const CLONE_FILES: usize = 0x400;
+pub const CLONE_SIGHAND: usize = 0x800;
+pub const CLONE_THREAD: usize = 0x10000;
+pub const CLONE_SETTLS: usize = 0x80000;
+
+// a thread: every thread has its own entry in the process table
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct Process {
     pub state: State,
     pub arch_proc: ArchProcess,
     pub pid: Pid,
-    pub priority: usize,
+    pub tgid: Pid,       // pid of the main thread, shared by the threads of a process
+    pub priority: usize, // effective priority, see `update_priority`
+    pub base_priority: usize, // priority without inheritance
     children: VecDeque<Pid>,
     pub name: String,
     pub kernel_stack: usize,
     pub user_stack: usize,
+    pub timer: Option<TimerId>,      // pending sleep timer
+    pub futex: Option<usize>,        // key of the futex the process waits on
+    pub sema: Option<Sid>,           // semaphore the process waits on
+    pub held: Vec<Sid>,              // mutexes the process holds
+    pub wchan: Option<&'static str>, // device or queue of an IOWait or Blocked process
+    pub switches: usize,             // times the process has been switched to
+    pub signals: SignalState,
+    pub files: Rc<RefCell<FileTable>>,
+    pub rlimits: ResourceLimits,
+    pub cpu_time: Tick, // ticks spent running, the main thread counts for the whole process
+    pub deadline: Option<DeadlineEntity>, // reservation of the deadline scheduling class
+    pub trace: TraceState,
+    pub strace: usize, // STRACE_* flags
+    pub kernel: bool,  // runs kernel code only, see `setup_kernel_process`
 }
 
 impl Process {
     pub fn new(pid: Pid) -> Self {
+        let rlimits = ResourceLimits::new();
+        let mut files = FileTable::new();
+        files.set_max_files(rlimits.cur(RLIMIT_NOFILE));
         Process {
             state: State::Free,
             arch_proc: ArchProcess::new(pid),
             pid,
+            tgid: pid,
             priority: 0,
+            base_priority: 0,
             children: VecDeque::new(),
             name: String::new(),
             kernel_stack: 0,
             user_stack: 0,
+            timer: None,
+            futex: None,
+            sema: None,
+            held: Vec::new(),
+            wchan: None,
+            switches: 0,
+            signals: SignalState::new(),
+            files: Rc::new(RefCell::new(files)),
+            rlimits,
+            cpu_time: 0,
+            deadline: None,
+            trace: TraceState::new(),
+            strace: 0,
+            kernel: false,
         }
     }
+
+    // user space can signal and trace neither the null process nor kernel processes
+    pub fn is_user(&self) -> bool {
+        self.pid != 0 && !self.kernel
+    }
 }
 
 #[derive(Clone)]
@@ -105,25 +177,6 @@ impl PartialEq for ProcessDesc {
 
 impl Eq for ProcessDesc {}
 
-#[derive(Clone)]
-pub struct ProcessDelay {
-    pid: Pid,
-    delay: usize,
-    link: LinkedListLink,
-}
-
-intrusive_adapter!(ProcessDelayAdapter = Box<ProcessDelay>: ProcessDelay { link: LinkedListLink });
-
-impl ProcessDelay {
-    pub fn new(pid: Pid, delay: usize) -> Self {
-        ProcessDelay {
-            pid,
-            delay,
-            link: LinkedListLink::new(),
-        }
-    }
-}
-
 pub struct DeferScheduler {
     pub count: usize,
     pub attempt: bool,
@@ -153,6 +206,15 @@ pub struct Semaphore {
     state: SemaState,
     count: isize,
     queue: VecDeque<usize>, // wait process queue
+    name: Option<String>,
+    // processes (by tgid) which opened the semaphore from user space
+    // a user semaphore is deleted when the last of them closes it or exits
+    users: Vec<Pid>,
+    // a mutex is a binary semaphore with an owner, which inherits the priority of its waiters
+    mutex: bool,
+    owner: Option<Pid>,
+    #[cfg(feature = "lockdep")]
+    class: Option<LockClass>, // where the mutex was created
 }
 
 impl Semaphore {
@@ -161,14 +223,36 @@ impl Semaphore {
             state: SemaState::Free,
             count,
             queue: VecDeque::new(),
+            name: None,
+            users: Vec::new(),
+            mutex: false,
+            owner: None,
+            #[cfg(feature = "lockdep")]
+            class: None,
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ProcessError {
     ProcessNotFound(Pid),
     SemaphoreNotFound(Sid),
+    SemaphoreDeleted(Sid), // deleted while waiting on it
+    TooManyThreads(Pid),
+    TimedOut,
+    WouldBlock,
+    InvalidArgument,
+    PermissionDenied,
+    ResourceLimit(usize), // the resource whose limit was reached
+    Busy,                 // a deadline reservation doesn't fit in the bandwidth left
+    BadAddress,           // a user address which isn't mapped
+    NotImplemented,       // a system call number without a handler
+    BadExecutable,        // a program which can't be loaded
+    ArgumentListTooLong,  // arguments and environment which don't fit on the initial stack
+    FileNotFound,         // a program which doesn't exist
+    BadFileDescriptor,    // a descriptor which isn't open, or not for the operation
+    IoError,              // the file system failed
+    NoDevice,             // a file which can't be mapped
 }
 
 #[macro_export]
@@ -191,15 +275,16 @@ macro_rules! get_process {
 
 #[allow(dead_code)]
 pub struct ProcessManager<'a> {
-    pub ptable: SpinLock<BTreeMap<Pid, Process>>,
+    // boxed because the trap frame and the scheduler keep pointers into a process
+    pub ptable: SpinLock<BTreeMap<Pid, Box<Process>>>,
     pub stable: SpinLock<BTreeMap<Sid, Semaphore>>,
     pub pqueue: SpinLock<BinaryHeap<ProcessDesc>>, // Ready list
-    sleep_queue: SpinLock<LinkedList<ProcessDelayAdapter>>,
     event_queue: HashMap<ProcessEvent, Vec<Pid>>,
     defer: DeferScheduler,
     pub curr_pid: Pid,
     pub curr_sid: Sid,
     pub running: Pid,
+    dl_bandwidth: usize, // admitted by deadline reservations, see `set_deadline`
     phantom: PhantomData<&'a u8>,
 }
 
@@ -209,12 +294,12 @@ impl<'a> ProcessManager<'a> {
             ptable: SpinLock::new(BTreeMap::new()),
             stable: SpinLock::new(BTreeMap::new()),
             pqueue: SpinLock::new(BinaryHeap::new()),
-            sleep_queue: SpinLock::new(LinkedList::new(ProcessDelayAdapter::new())),
             event_queue: HashMap::new(),
             defer: DeferScheduler::new(),
             curr_pid: 0,
             curr_sid: 0,
             running: 0,
+            dl_bandwidth: 0,
             phantom: PhantomData,
         }
     }
@@ -233,18 +318,63 @@ impl<'a> ProcessManager<'a> {
         }
     }
 
-    pub fn ptable_lock(&mut self) -> Lock<BTreeMap<Pid, Process>> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn ptable_lock(&mut self) -> Lock<BTreeMap<Pid, Box<Process>>> {
         self.ptable.lock()
     }
 
-    pub fn ptable_lock_mut(&mut self) -> LockMut<BTreeMap<Pid, Process>> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn ptable_lock_mut(&mut self) -> LockMut<BTreeMap<Pid, Box<Process>>> {
         self.ptable.lock_mut()
     }
 
-    pub fn load_program(&mut self, pid: Pid, path: &str) -> Result<(), ProcessError> {
-        get_process_mut!(self.ptable_lock_mut(), pid)?
-            .arch_proc
-            .init_program(path);
+    // replace the program of `pid` by the one at `path`, a script runs through its interpreter
+    // `argv` and `envp` are copied onto the stack of the program
+    // the new program is loaded in full before the old one is freed, which is kept on failure
+    pub fn load_program(
+        &mut self,
+        pid: Pid,
+        path: &str,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Result<(), ProcessError> {
+        if args_size(argv, envp) > ARGS_MAX {
+            return Err(ProcessError::ArgumentListTooLong);
+        }
+        let (path, argv) = exec::resolve(path, argv)?;
+        let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+        if args_size(&argv, envp) > ARGS_MAX {
+            return Err(ProcessError::ArgumentListTooLong);
+        }
+
+        let (kernel_stack, max_memory) = {
+            let ptable = self.ptable_lock();
+            let proc = get_process!(ptable, pid)?;
+            (proc.kernel_stack, proc.rlimits.cur(RLIMIT_AS))
+        };
+        let mut arch_proc = ArchProcess::new(pid);
+        arch_proc.init(
+            ArchProcess::user_trap_return as usize,
+            kernel_stack,
+            KERNEL_STACK_SIZE,
+        );
+        if let Err(err) = arch_proc.init_program(&path, &argv, envp, max_memory) {
+            arch_proc.free();
+            return Err(exec::load_error(&path, err));
+        }
+
+        let mut ptable = self.ptable_lock_mut();
+        let proc = get_process!(ptable, pid)?;
+        let tgid = proc.tgid;
+        let cpu = match ptable.get(&tgid) {
+            Some(main_thread) => main_thread.cpu_time,
+            None => proc.cpu_time,
+        };
+        arch_proc.update_vdso(tgid, cpu);
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.arch_proc.free();
+        proc.arch_proc = arch_proc;
+        proc.signals.reset_handlers();
 
         Ok(())
     }
@@ -268,57 +398,321 @@ impl<'a> ProcessManager<'a> {
         let sid = self.curr_sid;
         self.curr_sid += 1;
 
-        self.stable.lock_mut().insert(sid, Semaphore::new(count));
+        let mut sema = Semaphore::new(count);
+        sema.state = SemaState::Used;
+        self.stable.lock_mut().insert(sid, sema);
 
         interrupt_restore(mask);
 
         sid
     }
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn create_mutex(&mut self) -> Sid {
+        let sid = self.create_semaphore(1);
+        let sema = self.get_semaphore_mut(sid).expect("semaphore must exist");
+        sema.mutex = true;
+        #[cfg(feature = "lockdep")]
+        {
+            sema.class = Some(Location::caller());
+        }
+        sid
+    }
+
+    fn take_ownership(&mut self, sid: Sid, pid: Pid) -> Result<(), ProcessError> {
+        let sema = self.get_semaphore_mut(sid)?;
+        if sema.mutex {
+            sema.owner = Some(pid);
+            get_process_mut!(self.ptable_lock_mut(), pid)?
+                .held
+                .push(sid);
+        }
+
+        Ok(())
+    }
+
+    // recompute the effective priority of `pid`: its base priority raised to the highest
+    // priority waiting on the mutexes it holds, then propagate it to the owner of the mutex
+    // `pid` itself waits on
+    pub fn update_priority(&mut self, pid: Pid) -> Result<(), ProcessError> {
+        let mut pid = pid;
+        for _ in 0..MAX_INHERITANCE_DEPTH {
+            let (base_priority, old_priority, held, state, waiting) = {
+                let ptable = self.ptable_lock();
+                let proc = get_process!(ptable, pid)?;
+                (
+                    proc.base_priority,
+                    proc.priority,
+                    proc.held.clone(),
+                    proc.state,
+                    proc.sema,
+                )
+            };
+            if state == State::Free {
+                break;
+            }
+
+            let mut priority = base_priority;
+            for sid in held.iter() {
+                let waiters = match self.get_semaphore(*sid) {
+                    Ok(sema) => sema.queue.clone(),
+                    Err(_) => continue,
+                };
+                for waiter in waiters.iter() {
+                    priority = priority.max(get_process!(self.ptable_lock(), *waiter)?.priority);
+                }
+            }
+            if priority == old_priority {
+                break;
+            }
+
+            get_process_mut!(self.ptable_lock_mut(), pid)?.priority = priority;
+            if state == State::Ready {
+                // the old entry is left in the queue, `pop_ready_proc` skips it once this one runs
+                self.pqueue.lock_mut().push(ProcessDesc::new(priority, pid));
+            }
+
+            let owner = match waiting {
+                Some(sid) => self.get_semaphore(sid).ok().and_then(|sema| sema.owner),
+                None => None,
+            };
+            match owner {
+                Some(owner) => pid = owner,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_process_priority(&mut self, pid: Pid) -> Result<usize, ProcessError> {
+        Ok(get_process!(self.ptable_lock(), pid)?.priority)
+    }
+
+    // semaphore for the running process, a named one is shared with every process opening the name
+    // the initial count can't be negative, it would stand for waiters there are not
+    pub fn open_semaphore(
+        &mut self,
+        name: Option<&str>,
+        count: isize,
+    ) -> Result<Sid, ProcessError> {
+        if count < 0 {
+            return Err(ProcessError::InvalidArgument);
+        }
+
+        let mask = interrupt_disable();
+
+        let running = self.running;
+        let tgid = get_process!(self.ptable_lock(), running)?.tgid;
+
+        if let Some(name) = name {
+            let mut stable = self.stable.lock_mut();
+            let found = stable.iter_mut().find(|(_, sema)| {
+                sema.state == SemaState::Used && sema.name.as_deref() == Some(name)
+            });
+            if let Some((sid, sema)) = found {
+                if !sema.users.contains(&tgid) {
+                    sema.users.push(tgid);
+                }
+                let sid = *sid;
+                drop(stable);
+                interrupt_restore(mask);
+                return Ok(sid);
+            }
+        }
+
+        let sid = self.create_semaphore(count);
+        let sema = self.get_semaphore_mut(sid)?;
+        sema.name = name.map(|name| name.to_string());
+        sema.users.push(tgid);
+
+        interrupt_restore(mask);
+
+        Ok(sid)
+    }
+
+    // user space can only use the semaphores it has opened
+    pub fn check_semaphore_access(&mut self, sid: Sid) -> Result<(), ProcessError> {
+        let running = self.running;
+        let tgid = get_process!(self.ptable_lock(), running)?.tgid;
+        if self.get_semaphore(sid)?.users.contains(&tgid) {
+            Ok(())
+        } else {
+            Err(ProcessError::SemaphoreNotFound(sid))
+        }
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn wait_semaphore(&mut self, sid: Sid) -> Result<(), ProcessError> {
+        self.wait_semaphore_timeout(sid, None)
+    }
+
+    // `timeout` is in ticks, None waits forever
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn wait_semaphore_timeout(
+        &mut self,
+        sid: Sid,
+        timeout: Option<Tick>,
+    ) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
 
         let pid = self.running;
+        #[cfg(feature = "lockdep")]
+        let class = self.get_semaphore(sid)?.class;
+        #[cfg(feature = "lockdep")]
+        if let Some(class) = class {
+            lockdep::acquire(pid, class, LockKind::Mutex, Location::caller(), false);
+        }
         self.get_semaphore_mut(sid)?.count -= 1;
         let count = self.get_semaphore_mut(sid)?.count;
-        if count < 0 {
-            get_process_mut!(self.ptable_lock_mut(), pid)?.state = State::SemaWait;
-            self.get_semaphore_mut(sid)?.queue.push_back(pid);
-            self.schedule()?;
+        if count >= 0 {
+            self.take_ownership(sid, pid)?;
+            interrupt_restore(mask);
+            return Ok(());
+        }
+
+        {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, pid)?;
+            proc.state = State::SemaWait;
+            proc.sema = Some(sid);
+            proc.timer = timeout.map(|delay| add_timer(delay, TimerAction::Wakeup(pid)));
+        }
+        self.get_semaphore_mut(sid)?.queue.push_back(pid);
+        // the owner runs at our priority at least until it releases the mutex
+        if let Some(owner) = self.get_semaphore(sid)?.owner {
+            self.update_priority(owner)?;
+        }
+        self.schedule()?;
+
+        let timer = {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, pid)?;
+            proc.sema = None;
+            proc.timer.take()
+        };
+        if let Some(timer) = timer {
+            cancel_timer(timer);
+        }
+
+        let result = match self.get_semaphore_mut(sid) {
+            Err(_) => Err(ProcessError::SemaphoreDeleted(sid)),
+            Ok(sema) => match sema.queue.iter().position(|waiter| *waiter == pid) {
+                // still queued: woken up by the timer, give the count back
+                Some(index) => {
+                    sema.queue.remove(index);
+                    sema.count += 1;
+                    Err(ProcessError::TimedOut)
+                }
+                // `signal_semaphore` has handed the semaphore over
+                None => Ok(()),
+            },
+        };
+        #[cfg(feature = "lockdep")]
+        if let (Err(_), Some(class)) = (result, class) {
+            lockdep::release(pid, class);
+        }
+        if result == Err(ProcessError::TimedOut) {
+            if let Some(owner) = self.get_semaphore(sid)?.owner {
+                self.update_priority(owner)?;
+            }
         }
 
         interrupt_restore(mask);
 
-        Ok(())
+        result
     }
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_wait_semaphore(&mut self, sid: Sid) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let pid = self.running;
+        let sema = self.get_semaphore_mut(sid)?;
+        let result = if sema.count > 0 {
+            sema.count -= 1;
+            self.take_ownership(sid, pid)
+        } else {
+            Err(ProcessError::WouldBlock)
+        };
+        #[cfg(feature = "lockdep")]
+        if let Some(class) = self.get_semaphore(sid)?.class {
+            if result.is_ok() {
+                lockdep::acquire(pid, class, LockKind::Mutex, Location::caller(), true);
+            }
+        }
+
+        interrupt_restore(mask);
+
+        result
+    }
+
+    // a mutex can be released by any process (e.g. in an interrupt handler) on behalf of its owner
     pub fn signal_semaphore(&mut self, sid: Sid) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
 
-        if self.get_semaphore(sid)?.count < 0 {
-            let pid = self.get_semaphore_mut(sid)?.queue.pop_front().unwrap();
-            self.get_semaphore_mut(sid)?.count += 1;
-            self.ready(pid)?;
+        let sema = self.get_semaphore_mut(sid)?;
+        let mutex = sema.mutex;
+        let owner = sema.owner.take();
+        if mutex && owner.is_none() && sema.count > 0 {
+            // releasing a mutex nobody holds
+            interrupt_restore(mask);
+            return Ok(());
+        }
+        if let Some(owner) = owner {
+            get_process_mut!(self.ptable_lock_mut(), owner)?
+                .held
+                .retain(|held| *held != sid);
+            #[cfg(feature = "lockdep")]
+            if let Some(class) = self.get_semaphore(sid)?.class {
+                lockdep::release(owner, class);
+            }
+        }
+
+        self.defer_schedule(DeferCommand::Start)?;
+        let sema = self.get_semaphore_mut(sid)?;
+        // nobody to wake if the queue is empty, whatever the count says
+        let waiter = if sema.count < 0 {
+            sema.queue.pop_front()
         } else {
-            self.get_semaphore_mut(sid)?.count += 1;
+            None
+        };
+        sema.count += 1;
+        if let Some(pid) = waiter {
+            self.take_ownership(sid, pid)?;
+            self.update_priority(pid)?;
+            self.ready(pid)?;
+        }
+        // give up the inherited priority
+        if let Some(owner) = owner {
+            self.update_priority(owner)?;
         }
+        self.defer_schedule(DeferCommand::Stop)?;
 
         interrupt_restore(mask);
 
         Ok(())
     }
 
+    // the waiters return with `ProcessError::SemaphoreDeleted`
     pub fn delete_semaphore(&mut self, sid: usize) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
 
-        self.get_semaphore_mut(sid)?.state = SemaState::Free;
+        let mut sema = self
+            .stable
+            .lock_mut()
+            .remove(&sid)
+            .ok_or(ProcessError::SemaphoreNotFound(sid))?;
+        sema.state = SemaState::Free;
+        #[cfg(feature = "lockdep")]
+        if let (Some(owner), Some(class)) = (sema.owner, sema.class) {
+            lockdep::release(owner, class);
+        }
 
         self.defer_schedule(DeferCommand::Start)?;
 
-        while self.get_semaphore(sid)?.count < 0 {
-            let pid = self.get_semaphore_mut(sid)?.queue.pop_front().unwrap();
-            self.ready(pid)?;
-            self.get_semaphore_mut(sid)?.count += 1;
+        for pid in sema.queue.iter() {
+            self.ready(*pid)?;
         }
 
         self.defer_schedule(DeferCommand::Stop)?;
@@ -330,6 +724,56 @@ impl<'a> ProcessManager<'a> {
         Ok(())
     }
 
+    // drop the reference of the process `tgid`, the last one deletes the semaphore
+    pub fn close_semaphore(&mut self, sid: Sid, tgid: Pid) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let sema = self.get_semaphore_mut(sid)?;
+        sema.users.retain(|user| *user != tgid);
+        if sema.users.is_empty() {
+            self.delete_semaphore(sid)?;
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // called when the last thread of the process `tgid` has gone
+    fn release_semaphores(&mut self, tgid: Pid) -> Result<(), ProcessError> {
+        let sids: Vec<Sid> = self
+            .stable
+            .lock()
+            .iter()
+            .filter(|(_, sema)| sema.users.contains(&tgid))
+            .map(|(sid, _)| *sid)
+            .collect();
+        for sid in sids.into_iter() {
+            self.close_semaphore(sid, tgid)?;
+        }
+
+        Ok(())
+    }
+
+    // leave the wait queue of a semaphore without taking it
+    fn cancel_semaphore_wait(&mut self, sid: Sid, pid: Pid) -> Result<(), ProcessError> {
+        let owner = match self.get_semaphore_mut(sid) {
+            Ok(sema) => {
+                if let Some(index) = sema.queue.iter().position(|waiter| *waiter == pid) {
+                    sema.queue.remove(index);
+                    sema.count += 1;
+                }
+                sema.owner
+            }
+            Err(_) => None,
+        };
+        if let Some(owner) = owner {
+            self.update_priority(owner)?;
+        }
+
+        Ok(())
+    }
+
     pub fn pop_ready_proc(&mut self) -> Result<Option<ProcessDesc>, ProcessError> {
         let proc_desc = &mut self.pqueue.lock_mut().pop();
 
@@ -366,6 +810,8 @@ impl<'a> ProcessManager<'a> {
     pub fn schedule(&mut self) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
 
+        watchdog::touch();
+
         if self.defer.count > 0 {
             self.defer.attempt = true;
             interrupt_restore(mask);
@@ -412,7 +858,18 @@ impl<'a> ProcessManager<'a> {
             }
         }
 
-        get_process_mut!(self.ptable_lock_mut(), new_pid)?.state = State::Running;
+        // a process that blocks must not keep a spinlock held
+        #[cfg(feature = "lockdep")]
+        if get_process!(self.ptable_lock(), old_pid)?.state != State::Ready {
+            lockdep::might_sleep(old_pid);
+        }
+
+        {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, new_pid)?;
+            proc.state = State::Running;
+            proc.switches += 1;
+        }
         self.running = new_pid;
 
         // println!("[hobo0xcc] switch: {} -> {}", old_pid, new_pid);
@@ -447,7 +904,10 @@ impl<'a> ProcessManager<'a> {
         let mut ptable = self.ptable_lock_mut();
         let kernel_stack = get_process!(ptable, pid)?.kernel_stack;
 
-        get_process_mut!(ptable, pid)?.arch_proc = ArchProcess::new(pid);
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.arch_proc.free();
+        proc.arch_proc = ArchProcess::new(pid);
+        proc.kernel = true;
 
         get_process_mut!(ptable, pid)?
             .arch_proc
@@ -460,7 +920,9 @@ impl<'a> ProcessManager<'a> {
         let mut ptable = self.ptable_lock_mut();
         let kernel_stack = get_process!(ptable, pid)?.kernel_stack;
 
-        get_process_mut!(ptable, pid)?.arch_proc = ArchProcess::new(pid);
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.arch_proc.free();
+        proc.arch_proc = ArchProcess::new(pid);
 
         get_process_mut!(ptable, pid)?.arch_proc.init(
             ArchProcess::user_trap_return as usize,
@@ -489,6 +951,21 @@ impl<'a> ProcessManager<'a> {
         priority: usize,
         do_setup: bool,
     ) -> Result<usize, ProcessError> {
+        // RLIMIT_NPROC of the creating process, there is none while booting
+        let running = self.running;
+        let max_procs = match get_process!(self.ptable_lock(), running) {
+            Ok(proc) => proc.rlimits.cur(RLIMIT_NPROC),
+            Err(_) => RLIM_INFINITY,
+        };
+        let procs = self
+            .ptable_lock()
+            .values()
+            .filter(|proc| proc.state != State::Free)
+            .count();
+        if procs >= max_procs {
+            return Err(ProcessError::ResourceLimit(RLIMIT_NPROC));
+        }
+
         self.defer_schedule(DeferCommand::Start)?;
         let pid = self.curr_pid;
 
@@ -496,10 +973,11 @@ impl<'a> ProcessManager<'a> {
 
         let mut ptable = self.ptable_lock_mut();
 
-        ptable.insert(pid, Process::new(pid));
+        ptable.insert(pid, Box::new(Process::new(pid)));
 
         let mut proc = get_process_mut!(ptable, pid)?;
         proc.priority = priority;
+        proc.base_priority = priority;
 
         proc.name = name.to_string();
 
@@ -523,77 +1001,12 @@ impl<'a> ProcessManager<'a> {
         Ok(pid)
     }
 
-    pub fn wakeup(&mut self) -> Result<(), ProcessError> {
-        let mask = interrupt_disable();
-
-        let ptr = self.sleep_queue.lock_mut().pop_front();
-        if let Some(mut p) = ptr {
-            let proc_delay = p.as_mut();
-            if proc_delay.delay >= 1 {
-                (*proc_delay).delay -= 1;
-            }
-
-            self.sleep_queue.lock_mut().push_front(p);
-
-            let mut cursor = self
-                .sleep_queue
-                .get_inner_spinlock_mut()
-                .get_mut()
-                .front_mut();
-            let mut pids = Vec::new();
-            while let Some(p) = cursor.get() {
-                if p.delay == 0 {
-                    pids.push(p.pid);
-                    cursor.remove();
-                } else {
-                    break;
-                }
-            }
-            drop(cursor);
-
-            self.defer_schedule(DeferCommand::Start)?;
-            for pid in pids.into_iter() {
-                if get_process!(self.ptable_lock(), pid)?.state == State::Sleep {
-                    self.ready(pid)?;
-                }
-            }
-            self.defer_schedule(DeferCommand::Stop)?;
-
-            self.schedule()?;
-        }
-
-        interrupt_restore(mask);
-
-        Ok(())
-    }
-
     pub fn sleep(&mut self, pid: Pid, delay: usize) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
         get_process_mut!(self.ptable_lock_mut(), pid)?.state = State::Sleep;
 
-        let mut que_spin = self.sleep_queue.lock_mut();
-        let mut insert_node = que_spin.front_mut();
-        let mut delay_sum: usize = 0;
-        while let Some(node) = insert_node.get() {
-            if delay < (delay_sum + node.delay) {
-                break;
-            }
-            delay_sum += node.delay;
-
-            insert_node.move_next();
-        }
-
-        let relative_delay = delay - delay_sum;
-        insert_node.insert_before(Box::new(ProcessDelay::new(pid, relative_delay)));
-        if let Some(node) = insert_node.get() {
-            let pid = node.pid;
-            let delay = node.delay;
-            drop(node);
-            let _ =
-                insert_node.replace_with(Box::new(ProcessDelay::new(pid, delay - relative_delay)));
-        }
-
-        drop(que_spin);
+        let timer = add_timer(delay, TimerAction::Wakeup(pid));
+        get_process_mut!(self.ptable_lock_mut(), pid)?.timer = Some(timer);
 
         self.schedule()?;
 
@@ -607,12 +1020,45 @@ impl<'a> ProcessManager<'a> {
 
         let state = get_process!(self.ptable_lock(), pid)?.state;
         match state {
-            State::Free => {}
+            State::Free => {
+                // already killed, its resources are gone
+                interrupt_restore(mask);
+                return Ok(());
+            }
             _ => {
                 get_process_mut!(self.ptable_lock_mut(), pid)?.state = State::Free;
             }
         }
 
+        // nothing below may switch away from a killed running process
+        self.defer_schedule(DeferCommand::Start)?;
+
+        #[cfg(feature = "lockdep")]
+        lockdep::exit(pid);
+
+        if let Some(timer) = get_process_mut!(self.ptable_lock_mut(), pid)?.timer.take() {
+            cancel_timer(timer);
+        }
+        if let Some(key) = get_process_mut!(self.ptable_lock_mut(), pid)?.futex.take() {
+            futex_cancel(key, pid);
+        }
+        self.set_deadline(pid, None)?;
+        if let Some(sid) = get_process_mut!(self.ptable_lock_mut(), pid)?.sema.take() {
+            self.cancel_semaphore_wait(sid, pid)?;
+        }
+        // pass the mutexes on to their next waiters
+        let held = get_process!(self.ptable_lock(), pid)?.held.clone();
+        for sid in held.into_iter() {
+            self.signal_semaphore(sid)?;
+        }
+
+        let tgid = get_process!(self.ptable_lock(), pid)?.tgid;
+        if self.threads_of(tgid).is_empty() {
+            self.release_semaphores(tgid)?;
+        }
+
+        ptrace::exit(self, pid)?;
+
         get_process_mut!(self.ptable_lock_mut(), pid)?
             .arch_proc
             .free();
@@ -626,6 +1072,8 @@ impl<'a> ProcessManager<'a> {
 
         self.event_signal(ProcessEvent::Exit(pid))?;
 
+        self.defer_schedule(DeferCommand::Stop)?;
+
         self.schedule()?;
 
         interrupt_restore(mask);
@@ -633,10 +1081,15 @@ impl<'a> ProcessManager<'a> {
         Ok(())
     }
 
-    pub fn io_wait(&mut self, pid: Pid) -> Result<(), ProcessError> {
+    // `device` is shown by the hung task detector
+    pub fn io_wait(&mut self, pid: Pid, device: &'static str) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
 
-        get_process_mut!(self.ptable_lock_mut(), pid)?.state = State::IOWait;
+        let mut ptable = self.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.state = State::IOWait;
+        proc.wchan = Some(device);
+        drop(ptable);
 
         interrupt_restore(mask);
 
@@ -672,6 +1125,13 @@ impl<'a> ProcessManager<'a> {
         Ok(())
     }
 
+    pub fn waiting_event(&self, pid: Pid) -> Option<ProcessEvent> {
+        self.event_queue
+            .iter()
+            .find(|(_, pids)| pids.contains(&pid))
+            .map(|(event, _)| *event)
+    }
+
     // signaling a process waiting `event` to wakeup
     pub fn event_signal(&mut self, event: ProcessEvent) -> Result<(), ProcessError> {
         let mask = interrupt_disable();
@@ -730,8 +1190,609 @@ impl<'a> ProcessManager<'a> {
         Ok(())
     }
 
-    pub fn fork(&mut self, _pid: Pid) -> usize {
-        0
+    // create a thread (CLONE_VM) or a process which resumes from the same point as the running one
+    // the new one returns 0 from the system call and has to be readied by `ready()`
+    pub fn clone_process(
+        &mut self,
+        flags: usize,
+        stack: usize,
+        tls: usize,
+    ) -> Result<Pid, ProcessError> {
+        let parent = self.running;
+        let (name, priority) = {
+            let ptable = self.ptable_lock();
+            let proc = get_process!(ptable, parent)?;
+            // a reservation isn't inherited
+            let priority = match proc.deadline {
+                Some(dl) => dl.normal_priority,
+                None => proc.priority,
+            };
+            (proc.name.clone(), priority)
+        };
+
+        let pid = self.create_process(&name, priority, false)?;
+
+        let mut ptable = self.ptable_lock_mut();
+        let proc = get_process!(ptable, parent)?;
+        let addr_space = proc.arch_proc.addr_space().clone();
+        let parent_trap_frame = proc.arch_proc.trap_frame;
+        let tgid = proc.tgid;
+        let files = proc.files.clone();
+        let rlimits = proc.rlimits;
+        let strace = if proc.strace & STRACE_CHILDREN != 0 {
+            proc.strace
+        } else {
+            0
+        };
+        let mut signals = proc.signals;
+        signals.pending = 0;
+
+        let child = get_process_mut!(ptable, pid)?;
+        let kernel_stack = child.kernel_stack;
+        if flags & CLONE_VM != 0 {
+            if !child
+                .arch_proc
+                .init_thread(addr_space, kernel_stack, KERNEL_STACK_SIZE)
+            {
+                drop(ptable);
+                self.kill(pid)?;
+                return Err(ProcessError::TooManyThreads(tgid));
+            }
+        } else {
+            let copied = addr_space.borrow().duplicate();
+            child
+                .arch_proc
+                .init_fork(copied, kernel_stack, KERNEL_STACK_SIZE);
+        }
+
+        unsafe {
+            let trap_frame = &mut *child.arch_proc.trap_frame;
+            *trap_frame = *parent_trap_frame;
+            trap_frame.a0 = 0;
+            if stack != 0 {
+                trap_frame.sp = stack;
+            }
+            if flags & CLONE_SETTLS != 0 {
+                trap_frame.tp = tls;
+            }
+        }
+
+        child.tgid = if flags & CLONE_THREAD != 0 { tgid } else { pid };
+        child.files = if flags & CLONE_FILES != 0 {
+            files
+        } else {
+            Rc::new(RefCell::new(files.borrow().clone()))
+        };
+        child.signals = signals;
+        child.rlimits = rlimits;
+        child.strace = strace;
+
+        if flags & CLONE_THREAD == 0 {
+            get_process_mut!(ptable, parent)?.children.push_back(pid);
+            drop(ptable);
+
+            // the new process shares the semaphores of its parent
+            for sema in self.stable.lock_mut().values_mut() {
+                if sema.users.contains(&tgid) {
+                    sema.users.push(pid);
+                }
+            }
+        }
+
+        Ok(pid)
+    }
+
+    // a process traces itself, its threads and its children
+    pub fn set_strace(&mut self, pid: Pid, flags: usize) -> Result<(), ProcessError> {
+        if flags & !(STRACE_SYSCALLS | STRACE_CHILDREN) != 0 {
+            return Err(ProcessError::InvalidArgument);
+        }
+
+        let running = self.running;
+        let mut ptable = self.ptable_lock_mut();
+        let caller = get_process!(ptable, running)?;
+        let allowed = caller.children.contains(&pid);
+        let tgid = caller.tgid;
+        let proc = get_process_mut!(ptable, pid)?;
+        if proc.state == State::Free {
+            return Err(ProcessError::ProcessNotFound(pid));
+        }
+        if proc.tgid != tgid && !allowed {
+            return Err(ProcessError::PermissionDenied);
+        }
+        proc.strace = flags;
+        Ok(())
+    }
+
+    pub fn get_rlimit(&mut self, pid: Pid, resource: usize) -> Result<RLimit, ProcessError> {
+        get_process!(self.ptable_lock(), pid)?.rlimits.get(resource)
+    }
+
+    // the limits are shared by the threads of a process
+    pub fn set_rlimit(
+        &mut self,
+        pid: Pid,
+        resource: usize,
+        limit: RLimit,
+    ) -> Result<(), ProcessError> {
+        let (tgid, mut rlimits) = {
+            let ptable = self.ptable_lock();
+            let proc = get_process!(ptable, pid)?;
+            (proc.tgid, proc.rlimits)
+        };
+        rlimits.set(resource, limit)?;
+
+        for thread in self.threads_of(tgid).into_iter() {
+            get_process_mut!(self.ptable_lock_mut(), thread)?.rlimits = rlimits;
+        }
+        if resource == RLIMIT_NOFILE {
+            get_process!(self.ptable_lock(), pid)?
+                .files
+                .borrow_mut()
+                .set_max_files(limit.cur);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_resource_usage(&mut self, pid: Pid) -> Result<ResourceUsage, ProcessError> {
+        let ptable = self.ptable_lock();
+        let proc = get_process!(ptable, pid)?;
+        let cpu = match ptable.get(&proc.tgid) {
+            Some(main_thread) => main_thread.cpu_time,
+            None => proc.cpu_time,
+        };
+        let memory = match proc.arch_proc.addr_space {
+            Some(ref addr_space) => addr_space.borrow().memory,
+            None => 0,
+        };
+
+        Ok(ResourceUsage {
+            cpu,
+            memory,
+            files: proc.files.borrow().len(),
+        })
+    }
+
+    // account a tick to the running process, called by the timer
+    // past the soft CPU limit the process gets SIGXCPU every second, the hard limit kills it
+    pub fn charge_cpu_tick(&mut self) -> Result<(), ProcessError> {
+        let running = self.running;
+        let (cpu, limit) = {
+            let mut ptable = self.ptable_lock_mut();
+            let tgid = match ptable.get(&running) {
+                Some(proc) if proc.state != State::Free => proc.tgid,
+                // the running process has just exited
+                _ => return Ok(()),
+            };
+            let owner = if ptable.contains_key(&tgid) {
+                tgid
+            } else {
+                running
+            };
+            let proc = get_process_mut!(ptable, owner)?;
+            proc.cpu_time += 1;
+            let cpu = proc.cpu_time;
+            let limit = proc.rlimits.get(RLIMIT_CPU)?;
+            // the owner may have left the address space, the running thread hasn't
+            get_process!(ptable, running)?
+                .arch_proc
+                .update_vdso(tgid, cpu);
+            (cpu, limit)
+        };
+
+        let ticks = |secs: usize| secs.saturating_mul(TICKS_PER_SEC);
+        if cpu >= ticks(limit.max) {
+            self.send_signal(running, Signal::SIGKILL)?;
+        } else if cpu >= ticks(limit.cur) && (cpu - ticks(limit.cur)) % TICKS_PER_SEC == 0 {
+            self.send_signal(running, Signal::SIGXCPU)?;
+        }
+
+        Ok(())
+    }
+
+    // reserve CPU time for `pid` in the deadline scheduling class, None drops the reservation
+    // fails with Busy if the reservation would take the total bandwidth over DL_MAX_BANDWIDTH
+    pub fn set_deadline(
+        &mut self,
+        pid: Pid,
+        params: Option<DeadlineParams>,
+    ) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let old = get_process!(self.ptable_lock(), pid)?.deadline;
+        let old_bandwidth = old.map_or(0, |dl| dl.params.bandwidth());
+        let new_bandwidth = match params {
+            Some(params) => {
+                if let Err(err) = params.validate() {
+                    interrupt_restore(mask);
+                    return Err(err);
+                }
+                params.bandwidth()
+            }
+            None => 0,
+        };
+        if self.dl_bandwidth - old_bandwidth + new_bandwidth > DL_MAX_BANDWIDTH {
+            interrupt_restore(mask);
+            return Err(ProcessError::Busy);
+        }
+        self.dl_bandwidth = self.dl_bandwidth - old_bandwidth + new_bandwidth;
+
+        let normal_priority = match old {
+            Some(dl) => {
+                cancel_timer(dl.timer);
+                dl.normal_priority
+            }
+            None => get_process!(self.ptable_lock(), pid)?.base_priority,
+        };
+        let deadline = params.map(|params| {
+            let timer = add_periodic_timer(
+                params.period,
+                TimerAction::Callback(Box::new(move |_| {
+                    let pm = unsafe { process_manager() };
+                    pm.replenish_deadline(pid).expect("process");
+                })),
+            );
+            DeadlineEntity::new(params, timer, normal_priority)
+        });
+
+        let throttled = {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, pid)?;
+            proc.deadline = deadline;
+            proc.base_priority = deadline.map_or(normal_priority, |dl| dl.priority());
+            proc.state == State::Throttled
+        };
+        self.update_priority(pid)?;
+        // the new reservation starts with a full runtime, and without one nothing throttles
+        if throttled {
+            get_process_mut!(self.ptable_lock_mut(), pid)?.state = State::Suspend;
+            self.ready(pid)?;
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    pub fn get_deadline(&mut self, pid: Pid) -> Result<Option<DeadlineParams>, ProcessError> {
+        Ok(get_process!(self.ptable_lock(), pid)?
+            .deadline
+            .map(|dl| dl.params))
+    }
+
+    // start a new period of the reservation of `pid`, called by its periodic timer
+    pub fn replenish_deadline(&mut self, pid: Pid) -> Result<(), ProcessError> {
+        let state = {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, pid)?;
+            let dl = match proc.deadline {
+                Some(ref mut dl) => dl,
+                None => return Ok(()),
+            };
+            dl.replenish();
+            proc.base_priority = dl.priority();
+            proc.state
+        };
+
+        self.update_priority(pid)?;
+        if state == State::Throttled {
+            self.ready(pid)?;
+        }
+
+        Ok(())
+    }
+
+    // account a tick to the reservation of the running process, throttling it when the runtime
+    // of the period is used up. the caller reschedules
+    pub fn charge_deadline_tick(&mut self) -> Result<(), ProcessError> {
+        let running = self.running;
+        let mut ptable = self.ptable_lock_mut();
+        let proc = match ptable.get_mut(&running) {
+            Some(proc) => proc,
+            None => return Ok(()),
+        };
+        if let Some(ref mut dl) = proc.deadline {
+            dl.remaining = dl.remaining.saturating_sub(1);
+            if dl.remaining == 0 && proc.state == State::Running {
+                proc.state = State::Throttled;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn fork(&mut self) -> Result<Pid, ProcessError> {
+        self.clone_process(0, 0, 0)
+    }
+
+    // wait for a thread of the running process to exit
+    pub fn join_thread(&mut self, tid: Pid) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let running = self.running;
+        loop {
+            let ptable = self.ptable_lock();
+            let tgid = get_process!(ptable, running)?.tgid;
+            let thread = get_process!(ptable, tid)?;
+            if thread.tgid != tgid || tid == running {
+                interrupt_restore(mask);
+                return Err(ProcessError::ProcessNotFound(tid));
+            }
+            if thread.state == State::Free {
+                break;
+            }
+            drop(ptable);
+
+            self.event_wait(running, ProcessEvent::Exit(tid))?;
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    pub fn threads_of(&mut self, tgid: Pid) -> Vec<Pid> {
+        self.ptable_lock()
+            .values()
+            .filter(|proc| proc.tgid == tgid && proc.state != State::Free)
+            .map(|proc| proc.pid)
+            .collect()
+    }
+
+    // kill the other threads of the process `pid` belongs to
+    pub fn kill_other_threads(&mut self, pid: Pid) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let tgid = get_process!(self.ptable_lock(), pid)?.tgid;
+        self.defer_schedule(DeferCommand::Start)?;
+        for tid in self.threads_of(tgid) {
+            if tid != pid {
+                self.kill(tid)?;
+            }
+        }
+        self.defer_schedule(DeferCommand::Stop)?;
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // kill all threads of the process `pid` belongs to
+    pub fn kill_group(&mut self, pid: Pid) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let running = self.running;
+        let tgid = get_process!(self.ptable_lock(), pid)?.tgid;
+        self.defer_schedule(DeferCommand::Start)?;
+        for tid in self.threads_of(tgid) {
+            if tid != running {
+                self.kill(tid)?;
+            }
+        }
+        self.defer_schedule(DeferCommand::Stop)?;
+
+        // the running thread goes last because it doesn't come back from `kill`
+        if get_process!(self.ptable_lock(), running)?.tgid == tgid {
+            self.kill(running)?;
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // post `sig` to `pid`, it is acted on when `pid` returns to user mode
+    pub fn send_signal(&mut self, pid: Pid, sig: Signal) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let mut ptable = self.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, pid)?;
+        if proc.state == State::Free {
+            interrupt_restore(mask);
+            return Err(ProcessError::ProcessNotFound(pid));
+        }
+        proc.signals.raise(sig);
+        let state = proc.state;
+        let deliverable = proc.signals.has_deliverable();
+        drop(ptable);
+
+        match sig {
+            Signal::SIGKILL if pid != self.running => {
+                self.kill_group(pid)?;
+            }
+            Signal::SIGCONT if state == State::Stopped => {
+                self.ready(pid)?;
+            }
+            _ => {
+                // cut a sleep short so that the signal doesn't wait for the timeout
+                if deliverable && (state == State::Sleep || state == State::FutexWait) {
+                    if let Some(timer) = get_process_mut!(self.ptable_lock_mut(), pid)?.timer.take()
+                    {
+                        cancel_timer(timer);
+                    }
+                    self.ready(pid)?;
+                }
+            }
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // signals caused by the process itself (faults) can't be blocked or ignored
+    pub fn force_signal(&mut self, pid: Pid, sig: Signal) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        get_process_mut!(self.ptable_lock_mut(), pid)?
+            .signals
+            .force(sig);
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // act on the pending signals of the running process right before it returns to user mode
+    pub fn handle_signals(&mut self) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let running = self.running;
+        loop {
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, running)?;
+            let blocked = proc.signals.blocked;
+            let (sig, disposition) = match proc.signals.dequeue() {
+                Some(signal) => signal,
+                None => break,
+            };
+
+            // a traced process stops instead, the tracer chooses what it gets
+            let (sig, disposition) = if proc.trace.tracer.is_some() && sig != Signal::SIGKILL {
+                drop(ptable);
+                match ptrace::trace_stop(self, running, sig)? {
+                    Some(sig) => (
+                        sig,
+                        get_process_mut!(self.ptable_lock_mut(), running)?
+                            .signals
+                            .disposition(sig),
+                    ),
+                    None => continue,
+                }
+            } else {
+                drop(ptable);
+                (sig, disposition)
+            };
+            let mut ptable = self.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, running)?;
+
+            match disposition {
+                Disposition::Default(DefaultAction::Ignore)
+                | Disposition::Default(DefaultAction::Continue) => {}
+                Disposition::Default(DefaultAction::Stop) => {
+                    proc.state = State::Stopped;
+                    drop(ptable);
+                    // SIGCONT makes the process ready again
+                    self.schedule()?;
+                }
+                Disposition::Default(DefaultAction::Terminate) => {
+                    println!("{}({}): killed by {:?}", proc.name, running, sig);
+                    drop(ptable);
+                    self.kill_group(running)?;
+                }
+                Disposition::Default(DefaultAction::CoreDump) => {
+                    let name = proc.name.clone();
+                    drop(ptable);
+                    if coredump::enabled() {
+                        // writing the file needs the disk interrupts
+                        interrupt_restore(mask);
+                        match coredump::dump(running, sig) {
+                            Ok(path) => println!(
+                                "{}({}): killed by {:?}, core dumped to {}",
+                                name, running, sig, path
+                            ),
+                            Err(err) => println!(
+                                "{}({}): killed by {:?}, core dump failed: {:?}",
+                                name, running, sig, err
+                            ),
+                        }
+                        interrupt_disable();
+                    } else {
+                        println!("{}({}): killed by {:?}", name, running, sig);
+                    }
+                    self.kill_group(running)?;
+                }
+                Disposition::Handler(action) => {
+                    let (sp, frame) = proc.arch_proc.signal_frame(sig, blocked);
+                    drop(ptable);
+                    if uaccess::put_user(self, sp, &frame).is_err() {
+                        // the handler can't run, the process gets SIGSEGV instead, which isn't
+                        // handled again if it was the one delivered
+                        let mut ptable = self.ptable_lock_mut();
+                        let signals = &mut get_process_mut!(ptable, running)?.signals;
+                        if sig == Signal::SIGSEGV {
+                            signals.actions[sig.val()] = SigAction::default();
+                        }
+                        signals.force(Signal::SIGSEGV);
+                        continue;
+                    }
+
+                    let mut ptable = self.ptable_lock_mut();
+                    let proc = get_process_mut!(ptable, running)?;
+                    proc.arch_proc.enter_signal_handler(sp, sig, &action);
+                    let mut handler_mask = action.mask;
+                    if action.flags & SA_NODEFER == 0 {
+                        handler_mask |= sig.bit();
+                    }
+                    proc.signals.set_blocked(SIG_BLOCK, handler_mask);
+                    // the other signals are handled when the handler returns
+                    break;
+                }
+            }
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // returns the value of a0 in the restored user context
+    pub fn signal_return(&mut self) -> Result<usize, ProcessError> {
+        let running = self.running;
+        let sp = get_process!(self.ptable_lock(), running)?
+            .arch_proc
+            .signal_frame_addr();
+        let frame = uaccess::get_user::<SignalFrame>(self, sp);
+        let mut ptable = self.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, running)?;
+        match frame {
+            Ok(frame) => {
+                let blocked = proc.arch_proc.restore_signal_frame(&frame);
+                proc.signals.set_blocked(SIG_SETMASK, blocked);
+                Ok(unsafe { (*proc.arch_proc.trap_frame).a0 })
+            }
+            Err(_) => {
+                proc.signals.force(Signal::SIGSEGV);
+                Ok(0)
+            }
+        }
+    }
+
+    pub fn set_signal_action(
+        &mut self,
+        pid: Pid,
+        sig: Signal,
+        action: SigAction,
+    ) -> Result<Option<SigAction>, ProcessError> {
+        let mask = interrupt_disable();
+        let old = get_process_mut!(self.ptable_lock_mut(), pid)?
+            .signals
+            .set_action(sig, action);
+        interrupt_restore(mask);
+        Ok(old)
+    }
+
+    pub fn get_signal_action(&mut self, pid: Pid, sig: Signal) -> Result<SigAction, ProcessError> {
+        Ok(get_process!(self.ptable_lock(), pid)?.signals.actions[sig.val()])
+    }
+
+    pub fn set_signal_mask(
+        &mut self,
+        pid: Pid,
+        how: usize,
+        set: SigSet,
+    ) -> Result<Option<SigSet>, ProcessError> {
+        let mask = interrupt_disable();
+        let old = get_process_mut!(self.ptable_lock_mut(), pid)?
+            .signals
+            .set_blocked(how, set);
+        interrupt_restore(mask);
+        Ok(old)
+    }
+
+    pub fn get_signal_mask(&mut self, pid: Pid) -> Result<SigSet, ProcessError> {
+        Ok(get_process!(self.ptable_lock(), pid)?.signals.blocked)
     }
 
     pub fn get_process_state(&mut self, pid: Pid) -> Result<State, ProcessError> {
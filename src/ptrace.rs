@@ -0,0 +1,416 @@
+use crate::arch::target::interrupt::interrupt_disable;
+use crate::arch::target::interrupt::interrupt_restore;
+use crate::arch::target::ptrace::*;
+use crate::process::*;
+use crate::signal::*;
+use crate::uaccess::*;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem;
+
+// process tracing
+// a tracer attaches to a process, which stops with SIGSTOP. from then on every signal the
+// traced process gets stops it instead of being acted on, and the tracer learns about the stop
+// from ptrace_wait. while the process is stopped the tracer reads and writes its memory and
+// registers, then resumes it with the signal to deliver, if any, possibly for a single step.
+// breakpoints are ebreak instructions written by the tracer, they stop the process with SIGTRAP.
+
+// requests of the ptrace system call, numbered as in Linux
+pub const PTRACE_PEEKDATA: usize = 2;
+pub const PTRACE_POKEDATA: usize = 5;
+pub const PTRACE_CONT: usize = 7;
+pub const PTRACE_KILL: usize = 8;
+pub const PTRACE_SINGLESTEP: usize = 9;
+pub const PTRACE_GETREGS: usize = 12;
+pub const PTRACE_SETREGS: usize = 13;
+pub const PTRACE_ATTACH: usize = 16;
+pub const PTRACE_DETACH: usize = 17;
+
+// statuses reported by ptrace_wait, encoded as by waitpid
+pub const EXITED_STATUS: usize = 0;
+
+pub fn stop_status(sig: Signal) -> usize {
+    sig.val() << 8 | 0x7f
+}
+
+const PAGE_SIZE: usize = 0x1000;
+const WORD_SIZE: usize = mem::size_of::<usize>();
+
+// a temporary breakpoint of a single step
+#[derive(Copy, Clone, Debug)]
+struct StepBreakpoint {
+    vaddr: usize,
+    saved: [u8; 4], // the instruction bytes under the breakpoint
+    len: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TraceState {
+    pub tracer: Option<Pid>,
+    pub stop: Option<Signal>,   // signal of the current stop
+    pub reported: bool,         // the current stop has been reported to the tracer
+    pub resume: Option<Signal>, // signal the tracer passes on when it resumes the process
+    steps: Vec<StepBreakpoint>,
+    pub tracees: Vec<Pid>, // processes traced by this one
+    pub exited: Vec<Pid>,  // tracees which exited and haven't been waited for
+}
+
+impl TraceState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// copy between `buffer` and the user memory of `pid` at `vaddr`, through its page table
+// the page permissions aren't checked, so a tracer can write breakpoints into code, and a page
+// not mapped yet is mapped first. system calls use crate::uaccess instead
+fn access_user(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    vaddr: usize,
+    buffer: &mut [u8],
+    write: bool,
+) -> Result<(), ProcessError> {
+    let ptable = pm.ptable_lock();
+    let mut addr_space = get_process!(ptable, pid)?
+        .arch_proc
+        .addr_space()
+        .borrow_mut();
+    let mut done = 0;
+    while done < buffer.len() {
+        let addr = vaddr.checked_add(done).ok_or(ProcessError::BadAddress)?;
+        let paddr = addr_space
+            .tracee_to_phys(addr, write)
+            .ok_or(ProcessError::BadAddress)? as *mut u8;
+        let count = min(buffer.len() - done, PAGE_SIZE - addr % PAGE_SIZE);
+        unsafe {
+            if write {
+                paddr.copy_from_nonoverlapping(buffer[done..].as_ptr(), count);
+            } else {
+                paddr.copy_to_nonoverlapping(buffer[done..].as_mut_ptr(), count);
+            }
+        }
+        done += count;
+    }
+    Ok(())
+}
+
+pub fn read_user(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    vaddr: usize,
+    buffer: &mut [u8],
+) -> Result<(), ProcessError> {
+    access_user(pm, pid, vaddr, buffer, false)
+}
+
+pub fn write_user(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    vaddr: usize,
+    data: &[u8],
+) -> Result<(), ProcessError> {
+    let mut data = data.to_vec();
+    access_user(pm, pid, vaddr, &mut data, true)
+}
+
+// `pid` is traced by the running process
+fn check_tracee(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, pid)?;
+    if proc.state == State::Free || proc.trace.tracer != Some(running) {
+        return Err(ProcessError::ProcessNotFound(pid));
+    }
+    Ok(())
+}
+
+// `pid` is traced by the running process and stopped
+fn check_stopped(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    check_tracee(pm, pid)?;
+    if get_process!(pm.ptable_lock(), pid)?.state != State::Traced {
+        return Err(ProcessError::ProcessNotFound(pid));
+    }
+    Ok(())
+}
+
+fn signal_arg(signo: usize) -> Result<Option<Signal>, ProcessError> {
+    match signo {
+        0 => Ok(None),
+        _ => Signal::from(signo)
+            .map(Some)
+            .ok_or(ProcessError::InvalidArgument),
+    }
+}
+
+// trace and stop `pid`, a user process of another thread group not traced yet
+pub fn attach(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    let mask = interrupt_disable();
+
+    let running = pm.running;
+    let mut ptable = pm.ptable_lock_mut();
+    let tgid = get_process!(ptable, running)?.tgid;
+    let proc = get_process_mut!(ptable, pid)?;
+    if proc.state == State::Free {
+        interrupt_restore(mask);
+        return Err(ProcessError::ProcessNotFound(pid));
+    }
+    if !proc.is_user() || proc.tgid == tgid || proc.trace.tracer.is_some() {
+        interrupt_restore(mask);
+        return Err(ProcessError::PermissionDenied);
+    }
+    proc.trace.tracer = Some(running);
+    get_process_mut!(ptable, running)?.trace.tracees.push(pid);
+    drop(ptable);
+
+    pm.send_signal(pid, Signal::SIGSTOP)?;
+
+    interrupt_restore(mask);
+
+    Ok(())
+}
+
+// the process goes on untraced, stopped processes are resumed with `sig`
+fn release(pm: &mut ProcessManager, pid: Pid, sig: Option<Signal>) -> Result<(), ProcessError> {
+    remove_step_breakpoints(pm, pid)?;
+
+    let mut ptable = pm.ptable_lock_mut();
+    let proc = get_process_mut!(ptable, pid)?;
+    let tracer = proc.trace.tracer.take();
+    let stopped = proc.state == State::Traced;
+    proc.trace.stop = None;
+    proc.trace.resume = sig;
+    if let Some(tracer) = tracer {
+        get_process_mut!(ptable, tracer)?
+            .trace
+            .tracees
+            .retain(|tracee| *tracee != pid);
+    }
+    drop(ptable);
+
+    if stopped {
+        pm.ready(pid)?;
+    }
+    Ok(())
+}
+
+pub fn detach(pm: &mut ProcessManager, pid: Pid, signo: usize) -> Result<(), ProcessError> {
+    let sig = signal_arg(signo)?;
+    check_tracee(pm, pid)?;
+    release(pm, pid, sig)
+}
+
+// resume a stopped process, with a step it stops again after the next instruction
+pub fn resume(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    signo: usize,
+    step: bool,
+) -> Result<(), ProcessError> {
+    let sig = signal_arg(signo)?;
+    check_stopped(pm, pid)?;
+    if step {
+        insert_step_breakpoints(pm, pid)?;
+    }
+
+    {
+        let mut ptable = pm.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.trace.stop = None;
+        proc.trace.resume = sig;
+    }
+    pm.ready(pid)
+}
+
+fn insert_step_breakpoints(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    let regs = get_process!(pm.ptable_lock(), pid)?
+        .arch_proc
+        .user_registers();
+    let pc = regs[0];
+    let mut inst = [0; 4];
+    read_user(pm, pid, pc, &mut inst[..2])?;
+    let len = instruction_len(u16::from_le_bytes([inst[0], inst[1]]));
+    read_user(pm, pid, pc, &mut inst[..len])?;
+
+    let mut steps = Vec::new();
+    for vaddr in next_pcs(pc, u32::from_le_bytes(inst), &regs) {
+        // an unmapped target faults, which stops the process as well
+        let mut saved = [0; 4];
+        if read_user(pm, pid, vaddr, &mut saved[..2]).is_err() {
+            continue;
+        }
+        let (breakpoint, len) = breakpoint_for(u16::from_le_bytes([saved[0], saved[1]]));
+        if read_user(pm, pid, vaddr, &mut saved[..len]).is_err() {
+            continue;
+        }
+        write_user(pm, pid, vaddr, &breakpoint[..len])?;
+        steps.push(StepBreakpoint { vaddr, saved, len });
+    }
+    flush_icache();
+
+    get_process_mut!(pm.ptable_lock_mut(), pid)?
+        .trace
+        .steps
+        .extend(steps);
+    Ok(())
+}
+
+fn remove_step_breakpoints(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    let steps = mem::take(&mut get_process_mut!(pm.ptable_lock_mut(), pid)?.trace.steps);
+    if steps.is_empty() {
+        return Ok(());
+    }
+    for step in steps.iter().rev() {
+        write_user(pm, pid, step.vaddr, &step.saved[..step.len])?;
+    }
+    flush_icache();
+    Ok(())
+}
+
+// called by a traced process instead of acting on `sig`
+// returns the signal to act on instead, chosen by the tracer when it resumes the process
+pub fn trace_stop(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    sig: Signal,
+) -> Result<Option<Signal>, ProcessError> {
+    remove_step_breakpoints(pm, pid)?;
+
+    {
+        let mut ptable = pm.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, pid)?;
+        proc.state = State::Traced;
+        proc.trace.stop = Some(sig);
+        proc.trace.reported = false;
+        proc.trace.resume = None;
+    }
+    pm.event_signal(ProcessEvent::Trace(pid))?;
+    // switch away unless waking up the tracer already did
+    if get_process!(pm.ptable_lock(), pid)?.state == State::Traced {
+        pm.schedule()?;
+    }
+
+    Ok(get_process_mut!(pm.ptable_lock_mut(), pid)?
+        .trace
+        .resume
+        .take())
+}
+
+// wait for the next stop of `pid`, or for its exit, returns the status
+pub fn wait(pm: &mut ProcessManager, pid: Pid) -> Result<usize, ProcessError> {
+    let mask = interrupt_disable();
+
+    let running = pm.running;
+    let status = loop {
+        let mut ptable = pm.ptable_lock_mut();
+        let tracer = get_process_mut!(ptable, running)?;
+        if let Some(index) = tracer.trace.exited.iter().position(|tracee| *tracee == pid) {
+            tracer.trace.exited.remove(index);
+            break EXITED_STATUS;
+        }
+        if !tracer.trace.tracees.contains(&pid) {
+            interrupt_restore(mask);
+            return Err(ProcessError::ProcessNotFound(pid));
+        }
+
+        let proc = get_process_mut!(ptable, pid)?;
+        if let (State::Traced, Some(sig), false) =
+            (proc.state, proc.trace.stop, proc.trace.reported)
+        {
+            proc.trace.reported = true;
+            break stop_status(sig);
+        }
+        drop(ptable);
+
+        pm.event_wait(running, ProcessEvent::Trace(pid))?;
+    };
+
+    interrupt_restore(mask);
+
+    Ok(status)
+}
+
+// called when `pid` is killed, before its memory is freed
+pub fn exit(pm: &mut ProcessManager, pid: Pid) -> Result<(), ProcessError> {
+    // the processes it traces go on untraced
+    let tracees = mem::take(&mut get_process_mut!(pm.ptable_lock_mut(), pid)?.trace.tracees);
+    for tracee in tracees {
+        release(pm, tracee, None)?;
+    }
+    get_process_mut!(pm.ptable_lock_mut(), pid)?
+        .trace
+        .exited
+        .clear();
+
+    let tracer = match get_process!(pm.ptable_lock(), pid)?.trace.tracer {
+        Some(tracer) => tracer,
+        None => return Ok(()),
+    };
+    remove_step_breakpoints(pm, pid)?;
+    {
+        let mut ptable = pm.ptable_lock_mut();
+        get_process_mut!(ptable, pid)?.trace = TraceState::new();
+        let tracer = &mut get_process_mut!(ptable, tracer)?.trace;
+        tracer.tracees.retain(|tracee| *tracee != pid);
+        tracer.exited.push(pid);
+    }
+    pm.event_signal(ProcessEvent::Trace(pid))
+}
+
+// the ptrace system call, `data` is the user address the result is written to
+pub fn request(
+    pm: &mut ProcessManager,
+    request: usize,
+    pid: Pid,
+    addr: usize,
+    data: usize,
+) -> Result<usize, ProcessError> {
+    match request {
+        PTRACE_ATTACH => attach(pm, pid)?,
+        PTRACE_DETACH => detach(pm, pid, data)?,
+        PTRACE_CONT => resume(pm, pid, data, false)?,
+        PTRACE_SINGLESTEP => resume(pm, pid, data, true)?,
+        PTRACE_KILL => {
+            check_tracee(pm, pid)?;
+            pm.send_signal(pid, Signal::SIGKILL)?;
+        }
+        PTRACE_PEEKDATA => {
+            check_stopped(pm, pid)?;
+            let mut word = [0; WORD_SIZE];
+            read_user(pm, pid, addr, &mut word)?;
+            copy_to_user(pm, data, &word)?;
+        }
+        PTRACE_POKEDATA => {
+            check_stopped(pm, pid)?;
+            write_user(pm, pid, addr, &data.to_le_bytes())?;
+            flush_icache();
+        }
+        PTRACE_GETREGS => {
+            check_stopped(pm, pid)?;
+            let regs = get_process!(pm.ptable_lock(), pid)?
+                .arch_proc
+                .user_registers();
+            let mut bytes = Vec::new();
+            for reg in regs.iter() {
+                bytes.extend_from_slice(&reg.to_le_bytes());
+            }
+            copy_to_user(pm, data, &bytes)?;
+        }
+        PTRACE_SETREGS => {
+            check_stopped(pm, pid)?;
+            let mut bytes = [0; 32 * WORD_SIZE];
+            copy_from_user(pm, data, &mut bytes)?;
+            let mut regs = [0; 32];
+            for (reg, bytes) in regs.iter_mut().zip(bytes.chunks(WORD_SIZE)) {
+                let mut word = [0; WORD_SIZE];
+                word.copy_from_slice(bytes);
+                *reg = usize::from_le_bytes(word);
+            }
+            get_process_mut!(pm.ptable_lock_mut(), pid)?
+                .arch_proc
+                .set_user_registers(&regs);
+        }
+        _ => return Err(ProcessError::InvalidArgument),
+    }
+    Ok(0)
+}
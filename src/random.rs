@@ -0,0 +1,38 @@
+use crate::arch::target::clint;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// kernel entropy pool
+// there is no hardware random number generator, so the pool is stirred with the timer at boot
+// and at every device interrupt, whose timing depends on the outside world. numbers are drawn
+// with splitmix64: good enough to randomize addresses, not for cryptography.
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+static POOL: AtomicU64 = AtomicU64::new(GOLDEN_GAMMA);
+
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn add_entropy(value: u64) {
+    let _ = POOL.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pool| {
+        Some(mix(pool ^ value))
+    });
+}
+
+pub fn next_u64() -> u64 {
+    let pool = POOL.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed);
+    mix(pool.wrapping_add(GOLDEN_GAMMA))
+}
+
+// a number below `bound`, which isn't 0
+pub fn below(bound: usize) -> usize {
+    (next_u64() % bound as u64) as usize
+}
+
+pub fn init() {
+    add_entropy(clint::now() as u64);
+}
@@ -0,0 +1,81 @@
+use crate::process::ProcessError;
+use crate::timer::Tick;
+
+// resources, numbered as on Linux
+pub const RLIMIT_CPU: usize = 0; // seconds of CPU time
+pub const RLIMIT_NPROC: usize = 6; // live processes (and threads)
+pub const RLIMIT_NOFILE: usize = 7; // open files
+pub const RLIMIT_AS: usize = 9; // bytes of user memory
+pub const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: usize = !0;
+
+const DEFAULT_NOFILE: usize = 1024;
+const DEFAULT_NOFILE_MAX: usize = 4096;
+
+// layout shared with user space (struct rlimit in bin/syscall.h)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RLimit {
+    pub cur: usize, // soft limit, what is enforced
+    pub max: usize, // hard limit, the ceiling for `cur`
+}
+
+impl RLimit {
+    pub const fn infinity() -> Self {
+        RLimit {
+            cur: RLIM_INFINITY,
+            max: RLIM_INFINITY,
+        }
+    }
+}
+
+// inherited by fork and shared by the threads of a process
+#[derive(Copy, Clone)]
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        let mut limits = [RLimit::infinity(); RLIM_NLIMITS];
+        limits[RLIMIT_NOFILE] = RLimit {
+            cur: DEFAULT_NOFILE,
+            max: DEFAULT_NOFILE_MAX,
+        };
+        ResourceLimits { limits }
+    }
+
+    pub fn get(&self, resource: usize) -> Result<RLimit, ProcessError> {
+        self.limits
+            .get(resource)
+            .copied()
+            .ok_or(ProcessError::InvalidArgument)
+    }
+
+    pub fn cur(&self, resource: usize) -> usize {
+        self.limits[resource].cur
+    }
+
+    // there are no privileged processes, so a hard limit can only be lowered
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> Result<(), ProcessError> {
+        let old = self.get(resource)?;
+        if limit.cur > limit.max {
+            return Err(ProcessError::InvalidArgument);
+        }
+        if limit.max > old.max {
+            return Err(ProcessError::PermissionDenied);
+        }
+
+        self.limits[resource] = limit;
+        Ok(())
+    }
+}
+
+// what a process currently uses of the limited resources
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub cpu: Tick,     // ticks spent running
+    pub memory: usize, // bytes of user memory in the address space
+    pub files: usize,  // open files
+}
@@ -0,0 +1,255 @@
+pub type SigSet = u64;
+
+pub const NSIG: usize = 32;
+
+// sa_handler values with special meanings
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+// sa_flags
+pub const SA_NODEFER: usize = 0x4000_0000;
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+// how of sigprocmask
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Signal {
+    SIGHUP = 1,
+    SIGINT = 2,
+    SIGQUIT = 3,
+    SIGILL = 4,
+    SIGTRAP = 5,
+    SIGABRT = 6,
+    SIGBUS = 7,
+    SIGFPE = 8,
+    SIGKILL = 9,
+    SIGUSR1 = 10,
+    SIGSEGV = 11,
+    SIGUSR2 = 12,
+    SIGPIPE = 13,
+    SIGALRM = 14,
+    SIGTERM = 15,
+    SIGCHLD = 17,
+    SIGCONT = 18,
+    SIGSTOP = 19,
+    SIGTSTP = 20,
+    SIGXCPU = 24,
+}
+
+impl Signal {
+    pub fn from(signo: usize) -> Option<Signal> {
+        match signo {
+            1 => Some(Signal::SIGHUP),
+            2 => Some(Signal::SIGINT),
+            3 => Some(Signal::SIGQUIT),
+            4 => Some(Signal::SIGILL),
+            5 => Some(Signal::SIGTRAP),
+            6 => Some(Signal::SIGABRT),
+            7 => Some(Signal::SIGBUS),
+            8 => Some(Signal::SIGFPE),
+            9 => Some(Signal::SIGKILL),
+            10 => Some(Signal::SIGUSR1),
+            11 => Some(Signal::SIGSEGV),
+            12 => Some(Signal::SIGUSR2),
+            13 => Some(Signal::SIGPIPE),
+            14 => Some(Signal::SIGALRM),
+            15 => Some(Signal::SIGTERM),
+            17 => Some(Signal::SIGCHLD),
+            18 => Some(Signal::SIGCONT),
+            19 => Some(Signal::SIGSTOP),
+            20 => Some(Signal::SIGTSTP),
+            24 => Some(Signal::SIGXCPU),
+            _ => None,
+        }
+    }
+
+    pub fn val(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn bit(&self) -> SigSet {
+        1 << self.val()
+    }
+
+    // SIGKILL and SIGSTOP can be neither caught, blocked nor ignored
+    pub fn is_catchable(&self) -> bool {
+        *self != Signal::SIGKILL && *self != Signal::SIGSTOP
+    }
+
+    pub fn default_action(&self) -> DefaultAction {
+        match *self {
+            Signal::SIGCHLD => DefaultAction::Ignore,
+            Signal::SIGCONT => DefaultAction::Continue,
+            Signal::SIGSTOP | Signal::SIGTSTP => DefaultAction::Stop,
+            Signal::SIGQUIT
+            | Signal::SIGILL
+            | Signal::SIGTRAP
+            | Signal::SIGABRT
+            | Signal::SIGBUS
+            | Signal::SIGFPE
+            | Signal::SIGSEGV
+            | Signal::SIGXCPU => DefaultAction::CoreDump,
+            _ => DefaultAction::Terminate,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DefaultAction {
+    Terminate,
+    CoreDump, // terminate and write a core file
+    Ignore,
+    Stop,
+    Continue,
+}
+
+// layout shared with user space (struct sigaction in bin/syscall.h)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub mask: SigSet,
+}
+
+impl Default for SigAction {
+    fn default() -> Self {
+        SigAction {
+            handler: SIG_DFL,
+            flags: 0,
+            mask: 0,
+        }
+    }
+}
+
+// what the process has to do with a signal taken from the pending set
+#[derive(Copy, Clone, Debug)]
+pub enum Disposition {
+    Default(DefaultAction),
+    Handler(SigAction),
+}
+
+#[derive(Copy, Clone)]
+pub struct SignalState {
+    pub pending: SigSet,
+    pub blocked: SigSet,
+    pub actions: [SigAction; NSIG],
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        SignalState {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::default(); NSIG],
+        }
+    }
+
+    // handlers don't survive execve, the mask and pending signals do
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::default();
+            }
+        }
+    }
+
+    pub fn is_ignored(&self, sig: Signal) -> bool {
+        let handler = self.actions[sig.val()].handler;
+        handler == SIG_IGN || (handler == SIG_DFL && sig.default_action() == DefaultAction::Ignore)
+    }
+
+    pub fn raise(&mut self, sig: Signal) {
+        match sig {
+            // stop and continue cancel each other
+            Signal::SIGCONT => {
+                self.pending &= !(Signal::SIGSTOP.bit() | Signal::SIGTSTP.bit());
+            }
+            Signal::SIGSTOP | Signal::SIGTSTP => {
+                self.pending &= !Signal::SIGCONT.bit();
+            }
+            _ => {}
+        }
+
+        if sig != Signal::SIGCONT && self.is_ignored(sig) {
+            return;
+        }
+
+        self.pending |= sig.bit();
+    }
+
+    // used for faults: a blocked or ignored signal would re-execute the faulting instruction forever
+    pub fn force(&mut self, sig: Signal) {
+        let action = &mut self.actions[sig.val()];
+        if action.handler == SIG_IGN || self.blocked & sig.bit() != 0 {
+            *action = SigAction::default();
+        }
+        self.blocked &= !sig.bit();
+        self.pending |= sig.bit();
+    }
+
+    pub fn has_deliverable(&self) -> bool {
+        self.pending & !self.blocked != 0
+    }
+
+    // take the lowest pending signal which is not blocked
+    pub fn dequeue(&mut self) -> Option<(Signal, Disposition)> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+
+        let signo = deliverable.trailing_zeros() as usize;
+        self.pending &= !(1 << signo);
+        let sig = Signal::from(signo)?;
+
+        Some((sig, self.disposition(sig)))
+    }
+
+    // what to do with `sig` now that it is delivered
+    pub fn disposition(&mut self, sig: Signal) -> Disposition {
+        let action = self.actions[sig.val()];
+        match action.handler {
+            SIG_DFL => Disposition::Default(sig.default_action()),
+            SIG_IGN => Disposition::Default(DefaultAction::Ignore),
+            _ => {
+                if action.flags & SA_RESETHAND != 0 {
+                    self.actions[sig.val()] = SigAction::default();
+                }
+                Disposition::Handler(action)
+            }
+        }
+    }
+
+    pub fn set_action(&mut self, sig: Signal, action: SigAction) -> Option<SigAction> {
+        if !sig.is_catchable() {
+            return None;
+        }
+
+        let old = self.actions[sig.val()];
+        self.actions[sig.val()] = action;
+        if self.is_ignored(sig) {
+            // discard what is already pending as well
+            self.pending &= !sig.bit();
+        }
+
+        Some(old)
+    }
+
+    pub fn set_blocked(&mut self, how: usize, set: SigSet) -> Option<SigSet> {
+        let old = self.blocked;
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return None,
+        };
+        self.blocked = blocked & !(Signal::SIGKILL.bit() | Signal::SIGSTOP.bit());
+
+        Some(old)
+    }
+}
@@ -1,5 +1,9 @@
+#[cfg(feature = "lockdep")]
+use crate::lockdep::{self, LockClass, LockKind};
 use crate::process::*;
 use core::ops::{Deref, DerefMut};
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
 use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub enum LockMutData<'a, T> {
@@ -14,15 +18,21 @@ pub enum LockData<'a, T> {
 
 pub struct LockMut<'a, T> {
     data: LockMutData<'a, T>,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     // mask: usize,
 }
 pub struct Lock<'a, T> {
     data: LockData<'a, T>,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
     // mask: usize,
 }
 
 impl<'a, T> Drop for LockMut<'a, T> {
     fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(unsafe { process_manager() }.running, self.class);
         // interrupt_restore(self.mask);
         // let pm = unsafe { process_manager() };
         // pm.defer_schedule(DeferCommand::Stop).ok();
@@ -31,6 +41,8 @@ impl<'a, T> Drop for LockMut<'a, T> {
 
 impl<'a, T> Drop for Lock<'a, T> {
     fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(unsafe { process_manager() }.running, self.class);
         // interrupt_restore(self.mask);
         // let pm = unsafe { process_manager() };
         // pm.defer_schedule(DeferCommand::Stop).ok();
@@ -71,29 +83,47 @@ impl<'a, T> Deref for Lock<'a, T> {
 pub struct SpinLock<T> {
     spin_lock: RwLock<T>,
     recent_pid: Pid,
+    #[cfg(feature = "lockdep")]
+    class: LockClass, // where the lock was created
 }
 
 impl<T> SpinLock<T> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn new(data: T) -> SpinLock<T> {
         SpinLock {
             spin_lock: RwLock::new(data),
             recent_pid: 0,
+            #[cfg(feature = "lockdep")]
+            class: Location::caller(),
         }
     }
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock_mut(&mut self) -> LockMut<T> {
         let pm = unsafe { process_manager() };
         // pm.defer_schedule(DeferCommand::Start).ok();
         // let mask = interrupt_disable();
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(
+            pm.running,
+            self.class,
+            LockKind::Spin,
+            Location::caller(),
+            false,
+        );
 
         let lock = if pm.running == self.recent_pid {
             LockMut {
                 data: LockMutData::SameProcess(self.spin_lock.get_mut()),
+                #[cfg(feature = "lockdep")]
+                class: self.class,
                 // mask,
             }
         } else {
             LockMut {
                 data: LockMutData::Locking(self.spin_lock.write()),
+                #[cfg(feature = "lockdep")]
+                class: self.class,
                 // mask,
             }
         };
@@ -103,19 +133,32 @@ impl<T> SpinLock<T> {
         lock
     }
 
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock(&mut self) -> Lock<T> {
         let pm = unsafe { process_manager() };
         // pm.defer_schedule(DeferCommand::Start).ok();
         // let mask = interrupt_disable();
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(
+            pm.running,
+            self.class,
+            LockKind::Spin,
+            Location::caller(),
+            false,
+        );
 
         let lock = if pm.running == self.recent_pid {
             Lock {
                 data: LockData::SameProcess(self.spin_lock.get_mut()),
+                #[cfg(feature = "lockdep")]
+                class: self.class,
                 // mask,
             }
         } else {
             Lock {
                 data: LockData::Locking(self.spin_lock.read()),
+                #[cfg(feature = "lockdep")]
+                class: self.class,
                 // mask,
             }
         };
@@ -0,0 +1,232 @@
+use crate::errno::Errno;
+use crate::process::*;
+use crate::ptrace;
+use crate::*;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// system call tracing
+// every system call of a traced process is logged with its arguments, its return value and how
+// long it took:
+//   [strace] sh(3) open("/data.txt") = 3 <120us>
+// paths are read from user memory, buffers are shown with their length. a process traced with
+// STRACE_CHILDREN passes both flags on to the processes and threads it creates.
+
+// flags of the strace system call
+pub const STRACE_SYSCALLS: usize = 1; // log the system calls of the process
+pub const STRACE_CHILDREN: usize = 2; // new children start with the same flags
+
+// longest string shown, the rest is cut off
+const MAX_STR_LEN: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Arg {
+    Int,
+    Hex,
+    Str,        // a user string, which may be null
+    Buf(usize), // a user buffer whose length is the argument at the index
+}
+
+pub struct SyscallDesc {
+    pub number: usize,
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub returns: bool, // false if a successful call doesn't come back
+}
+
+use Arg::*;
+
+const fn desc(number: usize, name: &'static str, args: &'static [Arg]) -> SyscallDesc {
+    SyscallDesc {
+        number,
+        name,
+        args,
+        returns: true,
+    }
+}
+
+static SYSCALLS: &[SyscallDesc] = &[
+    desc(0, "read", &[Int, Buf(2), Int]),
+    desc(1, "write", &[Int, Buf(2), Int]),
+    desc(2, "seek", &[Int, Int, Int]),
+    desc(3, "open", &[Str]),
+    desc(35, "sleep", &[Int]),
+    desc(56, "wait_exit", &[]),
+    desc(57, "fork", &[]),
+    SyscallDesc {
+        number: 62,
+        name: "exit",
+        args: &[],
+        returns: false,
+    },
+    SyscallDesc {
+        number: 63,
+        name: "execve",
+        args: &[Str, Hex, Hex],
+        returns: false,
+    },
+    desc(98, "futex", &[Hex, Int, Int, Hex, Hex]),
+    desc(117, "ptrace", &[Int, Int, Hex, Hex]),
+    desc(129, "kill", &[Int, Int]),
+    desc(134, "sigaction", &[Int, Hex, Hex]),
+    desc(135, "sigprocmask", &[Int, Hex, Hex]),
+    desc(139, "sigreturn", &[]),
+    desc(163, "getrlimit", &[Int, Hex]),
+    desc(164, "setrlimit", &[Int, Hex]),
+    desc(172, "getpid", &[]),
+    desc(178, "gettid", &[]),
+    desc(220, "clone", &[Hex, Hex, Hex]),
+    desc(1000, "create_window", &[Buf(1), Int, Int, Int, Int, Int]),
+    desc(1001, "map_window", &[Int, Hex]),
+    desc(1002, "sync_window", &[Int]),
+    desc(1100, "thread_join", &[Int]),
+    desc(1200, "sem_open", &[Str, Int]),
+    desc(1201, "sem_wait", &[Int, Int]),
+    desc(1202, "sem_trywait", &[Int]),
+    desc(1203, "sem_post", &[Int]),
+    desc(1204, "sem_close", &[Int]),
+    desc(1205, "sem_delete", &[Int]),
+    desc(1300, "sched_deadline", &[Hex]),
+    desc(1400, "checkpoint", &[Int, Str]),
+    desc(1401, "restore", &[Str]),
+    desc(1500, "coredump", &[Int]),
+    desc(1600, "ptrace_wait", &[Int, Hex]),
+    desc(1700, "strace", &[Int, Hex]),
+];
+
+// programs of the Linux ABI, see arch::target::linux
+static LINUX_SYSCALLS: &[SyscallDesc] = &[
+    desc(56, "openat", &[Int, Str, Hex]),
+    desc(57, "close", &[Int]),
+    desc(62, "lseek", &[Int, Int, Int]),
+    desc(63, "read", &[Int, Buf(2), Int]),
+    desc(64, "write", &[Int, Buf(2), Int]),
+    desc(66, "writev", &[Int, Hex, Int]),
+    desc(80, "fstat", &[Int, Hex]),
+    SyscallDesc {
+        number: 93,
+        name: "exit",
+        args: &[Int],
+        returns: false,
+    },
+    SyscallDesc {
+        number: 94,
+        name: "exit_group",
+        args: &[Int],
+        returns: false,
+    },
+    desc(96, "set_tid_address", &[Hex]),
+    desc(113, "clock_gettime", &[Int, Hex]),
+    desc(160, "uname", &[Hex]),
+    desc(172, "getpid", &[]),
+    desc(214, "brk", &[Hex]),
+    desc(215, "munmap", &[Hex, Int]),
+    desc(222, "mmap", &[Hex, Int, Hex, Hex, Int, Int]),
+];
+
+// numbers without a handler show up as syscall_<number>, or linux_<number>
+pub fn describe(number: usize) -> Option<&'static SyscallDesc> {
+    SYSCALLS.iter().find(|desc| desc.number == number)
+}
+
+pub fn describe_linux(number: usize) -> Option<&'static SyscallDesc> {
+    LINUX_SYSCALLS.iter().find(|desc| desc.number == number)
+}
+
+// a string of `pid` at `vaddr`, quoted and escaped
+fn user_str(pm: &mut ProcessManager, pid: Pid, vaddr: usize) -> String {
+    if vaddr == 0 {
+        return String::from("NULL");
+    }
+
+    let mut s = String::from("\"");
+    for index in 0..=MAX_STR_LEN {
+        let mut ch = [0];
+        if ptrace::read_user(pm, pid, vaddr + index, &mut ch).is_err() {
+            return format!("{:#x}", vaddr);
+        }
+        if ch[0] == 0 {
+            s.push('"');
+            return s;
+        }
+        if index == MAX_STR_LEN {
+            break;
+        }
+        s.extend((ch[0] as char).escape_default());
+    }
+    s.push_str("\"...");
+    s
+}
+
+// `args` are the arguments of the system call, from a1
+pub fn format_call(pm: &mut ProcessManager, pid: Pid, number: usize, args: &[usize]) -> String {
+    match describe(number) {
+        Some(desc) => format_args(pm, pid, desc, args),
+        None => format!(
+            "syscall_{}({:#x}, {:#x}, {:#x})",
+            number, args[0], args[1], args[2]
+        ),
+    }
+}
+
+// `args` are the arguments of the system call, from a0
+pub fn format_linux_call(
+    pm: &mut ProcessManager,
+    pid: Pid,
+    number: usize,
+    args: &[usize],
+) -> String {
+    match describe_linux(number) {
+        Some(desc) => format_args(pm, pid, desc, args),
+        None => format!(
+            "linux_{}({:#x}, {:#x}, {:#x})",
+            number, args[0], args[1], args[2]
+        ),
+    }
+}
+
+fn format_args(pm: &mut ProcessManager, pid: Pid, desc: &SyscallDesc, args: &[usize]) -> String {
+    let formatted: Vec<String> = desc
+        .args
+        .iter()
+        .zip(args.iter())
+        .map(|(arg, value)| match *arg {
+            Int => format!("{}", *value as isize),
+            Hex => format!("{:#x}", value),
+            Str => user_str(pm, pid, *value),
+            Buf(len) => format!("[{} bytes]", args[len]),
+        })
+        .collect();
+    format!("{}({})", desc.name, formatted.join(", "))
+}
+
+pub fn format_ret(ret: usize) -> String {
+    let value = ret as isize;
+    if value < 0 && value > -4096 {
+        let errno = (-value) as usize;
+        match Errno::from_val(errno) {
+            Some(errno) => format!("-{} {}", errno.val(), errno.name()),
+            None => format!("-{}", errno),
+        }
+    } else if value >= 0 && value < 0x10000 {
+        format!("{}", value)
+    } else {
+        format!("{:#x}", ret)
+    }
+}
+
+// `elapsed` is in microseconds
+pub fn log_call(name: &str, pid: Pid, call: &str, ret: Option<usize>, elapsed: usize) {
+    match ret {
+        Some(ret) => println!(
+            "[strace] {}({}) {} = {} <{}us>",
+            name,
+            pid,
+            call,
+            format_ret(ret),
+            elapsed
+        ),
+        None => println!("[strace] {}({}) {} = ?", name, pid, call),
+    }
+}
@@ -0,0 +1,246 @@
+use crate::arch::target::interrupt::interrupt_disable;
+use crate::arch::target::interrupt::interrupt_restore;
+use crate::process::*;
+use alloc::collections::VecDeque;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+// processes sleeping until another one wakes them up
+// all operations run with interrupts disabled, so a queue can be shared through `&`
+pub struct WaitQueue {
+    name: &'static str, // shown by the hung task detector
+    waiters: UnsafeCell<VecDeque<Pid>>,
+}
+
+unsafe impl Sync for WaitQueue {}
+unsafe impl Send for WaitQueue {}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        WaitQueue::named("wait queue")
+    }
+
+    pub fn named(name: &'static str) -> Self {
+        WaitQueue {
+            name,
+            waiters: UnsafeCell::new(VecDeque::new()),
+        }
+    }
+
+    fn waiters(&self) -> &mut VecDeque<Pid> {
+        unsafe { &mut *self.waiters.get() }
+    }
+
+    // block the running process, `before_sleep` runs after it is queued (e.g. to drop a lock)
+    // a wakeup can be spurious, callers re-check their condition (see `wait_until`)
+    pub fn sleep<F: FnOnce()>(&self, before_sleep: F) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        let pm = unsafe { process_manager() };
+        let running = pm.running;
+        {
+            let mut ptable = pm.ptable_lock_mut();
+            let proc = get_process_mut!(ptable, running)?;
+            proc.state = State::Blocked;
+            proc.wchan = Some(self.name);
+        }
+        if !self.waiters().contains(&running) {
+            self.waiters().push_back(running);
+        }
+
+        before_sleep();
+
+        // `before_sleep` may have switched away and we may have been woken up already
+        if pm.get_process_state(running)? == State::Blocked {
+            pm.schedule()?;
+        }
+
+        // still queued if `schedule` found nothing else to run
+        self.waiters().retain(|pid| *pid != running);
+        let mut ptable = pm.ptable_lock_mut();
+        let proc = get_process_mut!(ptable, running)?;
+        if proc.state == State::Blocked {
+            proc.state = State::Running;
+        }
+        drop(ptable);
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    pub fn wait(&self) -> Result<(), ProcessError> {
+        self.sleep(|| {})
+    }
+
+    // sleep until `cond` holds, it is evaluated with interrupts disabled so no wakeup is lost
+    pub fn wait_until<F: FnMut() -> bool>(&self, mut cond: F) -> Result<(), ProcessError> {
+        let mask = interrupt_disable();
+
+        while !cond() {
+            self.wait()?;
+        }
+
+        interrupt_restore(mask);
+
+        Ok(())
+    }
+
+    // returns false if nobody was waiting
+    pub fn wake_one(&self) -> Result<bool, ProcessError> {
+        let mask = interrupt_disable();
+
+        let pm = unsafe { process_manager() };
+        while let Some(pid) = self.waiters().pop_front() {
+            // killed while waiting
+            if pm.get_process_state(pid)? != State::Blocked {
+                continue;
+            }
+
+            pm.ready(pid)?;
+            interrupt_restore(mask);
+            return Ok(true);
+        }
+
+        interrupt_restore(mask);
+
+        Ok(false)
+    }
+
+    // returns the number of processes woken up
+    pub fn wake_all(&self) -> Result<usize, ProcessError> {
+        let mask = interrupt_disable();
+
+        let pm = unsafe { process_manager() };
+        let mut woken = 0;
+        pm.defer_schedule(DeferCommand::Start)?;
+        while let Some(pid) = self.waiters().pop_front() {
+            if pm.get_process_state(pid)? == State::Blocked {
+                pm.ready(pid)?;
+                woken += 1;
+            }
+        }
+        pm.defer_schedule(DeferCommand::Stop)?;
+
+        interrupt_restore(mask);
+
+        Ok(woken)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters().is_empty()
+    }
+}
+
+// sleeping lock, built on a mutex semaphore so that the holder inherits the priority of its waiters
+pub struct KMutex<T> {
+    sid: Sid,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for KMutex<T> {}
+unsafe impl<T: Send> Send for KMutex<T> {}
+
+pub struct KMutexGuard<'a, T> {
+    mutex: &'a KMutex<T>,
+}
+
+impl<T> KMutex<T> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn new(data: T) -> Self {
+        let pm = unsafe { process_manager() };
+        KMutex {
+            sid: pm.create_mutex(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock(&self) -> KMutexGuard<T> {
+        let pm = unsafe { process_manager() };
+        pm.wait_semaphore(self.sid).expect("process");
+        KMutexGuard { mutex: self }
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_lock(&self) -> Option<KMutexGuard<T>> {
+        let pm = unsafe { process_manager() };
+        match pm.try_wait_semaphore(self.sid) {
+            Ok(_) => Some(KMutexGuard { mutex: self }),
+            Err(_) => None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T> Drop for KMutex<T> {
+    fn drop(&mut self) {
+        let pm = unsafe { process_manager() };
+        pm.delete_semaphore(self.sid).ok();
+    }
+}
+
+impl<'a, T> Deref for KMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for KMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for KMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        let pm = unsafe { process_manager() };
+        pm.signal_semaphore(self.mutex.sid).expect("process");
+    }
+}
+
+pub struct Condvar {
+    queue: WaitQueue,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Condvar {
+            queue: WaitQueue::named("condvar"),
+        }
+    }
+
+    // release the mutex while sleeping, it is held again on return
+    pub fn wait<'a, T>(&self, guard: KMutexGuard<'a, T>) -> KMutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        // queued before the mutex is released, so a notify in between isn't lost
+        self.queue.sleep(|| drop(guard)).expect("process");
+        mutex.lock()
+    }
+
+    // wait until `cond` holds for the protected data
+    pub fn wait_until<'a, T, F: FnMut(&mut T) -> bool>(
+        &self,
+        guard: KMutexGuard<'a, T>,
+        mut cond: F,
+    ) -> KMutexGuard<'a, T> {
+        let mut guard = guard;
+        while !cond(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) -> bool {
+        self.queue.wake_one().expect("process")
+    }
+
+    pub fn notify_all(&self) -> usize {
+        self.queue.wake_all().expect("process")
+    }
+}
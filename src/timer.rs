@@ -0,0 +1,266 @@
+use crate::arch::target::interrupt::interrupt_disable;
+use crate::arch::target::interrupt::interrupt_restore;
+use crate::process::*;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::mem;
+
+pub type TimerId = usize;
+pub type Tick = usize;
+
+// hierarchical timer wheel (same layout as the classic Linux timer wheel)
+// level 0 has one slot per tick, each upper level covers WHEEL_SIZE slots of the level below
+const WHEEL_BITS: usize = 6;
+const WHEEL_SIZE: usize = 1 << WHEEL_BITS;
+const WHEEL_MASK: usize = WHEEL_SIZE - 1;
+const WHEEL_LEVELS: usize = 4;
+const MAX_TIMEOUT: Tick = (1 << (WHEEL_BITS * WHEEL_LEVELS)) - 1;
+
+pub static mut TW: Option<TimerWheel> = None;
+
+pub enum TimerAction {
+    // make a sleeping process ready
+    Wakeup(Pid),
+    // called with interrupts disabled, so it must not block
+    Callback(Box<dyn FnMut(TimerId)>),
+}
+
+pub struct Timer {
+    pub id: TimerId,
+    pub expires: Tick,
+    pub period: Option<Tick>,
+    pub action: TimerAction,
+}
+
+pub struct TimerWheel {
+    // number of ticks processed so far
+    pub jiffies: Tick,
+    wheels: [[Vec<TimerId>; WHEEL_SIZE]; WHEEL_LEVELS],
+    timers: BTreeMap<TimerId, Timer>,
+    firing: BTreeSet<TimerId>, // periodic timers taken by `advance`, rearmed unless cancelled
+    curr_id: TimerId,
+}
+
+impl TimerWheel {
+    pub fn new() -> Self {
+        TimerWheel {
+            jiffies: 0,
+            wheels: array_init::array_init(|_| array_init::array_init(|_| Vec::new())),
+            timers: BTreeMap::new(),
+            firing: BTreeSet::new(),
+            curr_id: 0,
+        }
+    }
+
+    fn enqueue(&mut self, id: TimerId, expires: Tick) {
+        let delta = expires.wrapping_sub(self.jiffies) as isize;
+        if delta < 0 {
+            // already expired: run it on the next tick
+            self.wheels[0][self.jiffies & WHEEL_MASK].push(id);
+            return;
+        }
+
+        let delta = delta as usize;
+        for level in 0..WHEEL_LEVELS {
+            if delta < (1 << (WHEEL_BITS * (level + 1))) {
+                let slot = (expires >> (WHEEL_BITS * level)) & WHEEL_MASK;
+                self.wheels[level][slot].push(id);
+                return;
+            }
+        }
+
+        // out of range: park it at the farthest slot, it is re-queued when cascaded
+        let expires = self.jiffies + MAX_TIMEOUT;
+        let level = WHEEL_LEVELS - 1;
+        let slot = (expires >> (WHEEL_BITS * level)) & WHEEL_MASK;
+        self.wheels[level][slot].push(id);
+    }
+
+    // a timer with `delay` ticks fires on the `delay`-th call of `advance` from now
+    fn expires_after(&self, delay: Tick) -> Tick {
+        self.jiffies + delay.max(1) - 1
+    }
+
+    // move the timers of `wheels[level][slot]` to the lower levels
+    fn cascade(&mut self, level: usize, slot: usize) {
+        let ids = mem::take(&mut self.wheels[level][slot]);
+        for id in ids.into_iter() {
+            // cancelled timers are dropped lazily here
+            if let Some(timer) = self.timers.get(&id) {
+                let expires = timer.expires;
+                self.enqueue(id, expires);
+            }
+        }
+    }
+
+    pub fn add(&mut self, delay: Tick, period: Option<Tick>, action: TimerAction) -> TimerId {
+        let id = self.curr_id;
+        self.curr_id += 1;
+
+        let expires = self.expires_after(delay);
+        self.timers.insert(
+            id,
+            Timer {
+                id,
+                expires,
+                period,
+                action,
+            },
+        );
+        self.enqueue(id, expires);
+
+        id
+    }
+
+    // returns the timer if it was still pending
+    // a periodic timer whose action is running, which may cancel it, is not rearmed
+    pub fn cancel(&mut self, id: TimerId) -> Option<Timer> {
+        self.firing.remove(&id);
+        // the slot entry stays behind and is skipped when its slot is processed
+        self.timers.remove(&id)
+    }
+
+    pub fn is_pending(&self, id: TimerId) -> bool {
+        self.timers.contains_key(&id)
+    }
+
+    pub fn remaining(&self, id: TimerId) -> Option<Tick> {
+        self.timers
+            .get(&id)
+            .map(|timer| (timer.expires + 1).saturating_sub(self.jiffies))
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    // advance the wheel by one tick and return the expired timers
+    pub fn advance(&mut self) -> Vec<Timer> {
+        let index = self.jiffies & WHEEL_MASK;
+        if index == 0 {
+            for level in 1..WHEEL_LEVELS {
+                let slot = (self.jiffies >> (WHEEL_BITS * level)) & WHEEL_MASK;
+                self.cascade(level, slot);
+                if slot != 0 {
+                    break;
+                }
+            }
+        }
+
+        self.jiffies += 1;
+
+        let ids = mem::take(&mut self.wheels[0][index]);
+        let mut expired = Vec::new();
+        for id in ids.into_iter() {
+            if let Some(timer) = self.timers.remove(&id) {
+                if timer.period.is_some() {
+                    self.firing.insert(id);
+                }
+                expired.push(timer);
+            }
+        }
+
+        expired
+    }
+
+    // put a periodic timer back after its action has run, unless it was cancelled meanwhile
+    pub fn rearm(&mut self, mut timer: Timer) {
+        if !self.firing.remove(&timer.id) {
+            return;
+        }
+        if let Some(period) = timer.period {
+            timer.expires = self.expires_after(period);
+            let id = timer.id;
+            let expires = timer.expires;
+            self.timers.insert(id, timer);
+            self.enqueue(id, expires);
+        }
+    }
+}
+
+pub unsafe fn timer_wheel() -> &'static mut TimerWheel {
+    match TW {
+        Some(ref mut tw) => &mut *tw,
+        None => panic!("timer wheel is uninitialized"),
+    }
+}
+
+pub fn add_timer(delay: Tick, action: TimerAction) -> TimerId {
+    let mask = interrupt_disable();
+    let id = unsafe { timer_wheel() }.add(delay, None, action);
+    interrupt_restore(mask);
+    id
+}
+
+pub fn add_periodic_timer(period: Tick, action: TimerAction) -> TimerId {
+    let mask = interrupt_disable();
+    let id = unsafe { timer_wheel() }.add(period, Some(period), action);
+    interrupt_restore(mask);
+    id
+}
+
+pub fn cancel_timer(id: TimerId) -> bool {
+    let mask = interrupt_disable();
+    let cancelled = unsafe { timer_wheel() }.cancel(id).is_some();
+    interrupt_restore(mask);
+    cancelled
+}
+
+pub fn jiffies() -> Tick {
+    unsafe { timer_wheel() }.jiffies
+}
+
+// called on every timer interrupt
+pub fn tick() -> Result<(), ProcessError> {
+    let mask = interrupt_disable();
+
+    let tw = unsafe { timer_wheel() };
+    let pm = unsafe { process_manager() };
+    pm.charge_cpu_tick()?;
+    pm.charge_deadline_tick()?;
+    let expired = tw.advance();
+    if expired.is_empty() {
+        interrupt_restore(mask);
+        return Ok(());
+    }
+
+    // deferring is required because several processes might wake up on the same tick
+    pm.defer_schedule(DeferCommand::Start)?;
+    for mut timer in expired.into_iter() {
+        match timer.action {
+            TimerAction::Wakeup(pid) => {
+                let state = match get_process_mut!(pm.ptable_lock_mut(), pid) {
+                    Ok(proc) => {
+                        if proc.timer == Some(timer.id) {
+                            proc.timer = None;
+                        }
+                        Some(proc.state)
+                    }
+                    // the process has already gone
+                    Err(_) => None,
+                };
+                // only timed waits have a timer
+                if matches!(
+                    state,
+                    Some(State::Sleep) | Some(State::FutexWait) | Some(State::SemaWait)
+                ) {
+                    pm.ready(pid)?;
+                }
+            }
+            TimerAction::Callback(ref mut callback) => callback(timer.id),
+        }
+        tw.rearm(timer);
+    }
+    pm.defer_schedule(DeferCommand::Stop)?;
+
+    interrupt_restore(mask);
+
+    Ok(())
+}
+
+pub fn init() {
+    unsafe {
+        TW = Some(TimerWheel::new());
+    }
+}
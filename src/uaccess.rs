@@ -0,0 +1,120 @@
+use crate::process::*;
+use crate::rlimit::RLIMIT_AS;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem;
+use core::mem::MaybeUninit;
+use core::slice;
+
+// user memory access
+// system calls and signal frames reach the memory of the running process only through these
+// helpers. a copy goes page by page through the page table of the process, and every page must
+// be mapped for user mode with the permission the copy needs, read to copy from user space and
+// write to copy to it. a page not mapped yet is mapped as a fault of user mode would, see
+// AddressSpace::fault. anything else fails with BadAddress (EFAULT) before or partway through
+// the copy, so a bad pointer never reaches kernel or unrelated memory.
+
+const PAGE_SIZE: usize = 0x1000;
+
+// largest transfer of one read or write, longer ones are cut short
+pub const MAX_RW_COUNT: usize = 0x10000;
+
+// copy `len` bytes between `kernel` and `vaddr`, to user space if `write`
+fn copy_user(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    kernel: *mut u8,
+    len: usize,
+    write: bool,
+) -> Result<(), ProcessError> {
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, running)?;
+    let max_memory = proc.rlimits.cur(RLIMIT_AS);
+    let mut addr_space = proc.arch_proc.addr_space().borrow_mut();
+    let mut done = 0;
+    while done < len {
+        let addr = vaddr.checked_add(done).ok_or(ProcessError::BadAddress)?;
+        let paddr = addr_space
+            .fault_in(addr, write, max_memory)
+            .ok_or(ProcessError::BadAddress)? as *mut u8;
+        let count = min(len - done, PAGE_SIZE - addr % PAGE_SIZE);
+        unsafe {
+            if write {
+                paddr.copy_from_nonoverlapping(kernel.add(done), count);
+            } else {
+                paddr.copy_to_nonoverlapping(kernel.add(done), count);
+            }
+        }
+        done += count;
+    }
+    Ok(())
+}
+
+// fill `buffer` from `vaddr` of the running process
+pub fn copy_from_user(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    buffer: &mut [u8],
+) -> Result<(), ProcessError> {
+    copy_user(pm, vaddr, buffer.as_mut_ptr(), buffer.len(), false)
+}
+
+// copy `data` to `vaddr` of the running process
+pub fn copy_to_user(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    data: &[u8],
+) -> Result<(), ProcessError> {
+    copy_user(pm, vaddr, data.as_ptr() as *mut u8, data.len(), true)
+}
+
+// the string at `vaddr` of the running process, None if it takes more than `max` bytes with the
+// terminating NUL
+pub fn strncpy_from_user(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    max: usize,
+) -> Result<Option<String>, ProcessError> {
+    let mut bytes = Vec::new();
+    loop {
+        let addr = vaddr
+            .checked_add(bytes.len())
+            .ok_or(ProcessError::BadAddress)?;
+        // up to the end of the page, the string may end before an unmapped one
+        let len = min(PAGE_SIZE - addr % PAGE_SIZE, max - bytes.len());
+        if len == 0 {
+            return Ok(None);
+        }
+        let mut chunk = vec![0; len];
+        copy_from_user(pm, addr, &mut chunk)?;
+        match chunk.iter().position(|ch| *ch == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+            }
+            None => bytes.extend_from_slice(&chunk),
+        }
+    }
+}
+
+// a value with the layout shared with user space, read from `vaddr` of the running process
+pub fn get_user<T: Copy>(pm: &mut ProcessManager, vaddr: usize) -> Result<T, ProcessError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes =
+        unsafe { slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>()) };
+    copy_from_user(pm, vaddr, bytes)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+pub fn put_user<T: Copy>(
+    pm: &mut ProcessManager,
+    vaddr: usize,
+    value: &T,
+) -> Result<(), ProcessError> {
+    let bytes =
+        unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
+    copy_to_user(pm, vaddr, bytes)
+}
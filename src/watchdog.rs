@@ -0,0 +1,124 @@
+use crate::arch::target::clint::TICKS_PER_SEC;
+use crate::process::*;
+use crate::timer::*;
+use crate::*;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// hung task detector
+// a process that stays blocked without being switched to for HUNG_TASK_TIMEOUT_SECS is
+// listed on the console with what it waits on, once per blocking episode
+
+pub const HUNG_TASK_TIMEOUT_SECS: usize = 120;
+
+pub static mut HT: Option<HungTaskDetector> = None;
+
+pub struct HungTaskDetector {
+    // blocked processes at the previous check and their switch counts
+    blocked: BTreeMap<Pid, usize>,
+    // processes already reported, until they run again
+    reported: BTreeMap<Pid, usize>,
+}
+
+impl HungTaskDetector {
+    pub fn new() -> Self {
+        HungTaskDetector {
+            blocked: BTreeMap::new(),
+            reported: BTreeMap::new(),
+        }
+    }
+
+    pub fn check(&mut self) {
+        let pm = unsafe { process_manager() };
+        let mut blocked = BTreeMap::new();
+        let mut hung = Vec::new();
+        for (pid, proc) in pm.ptable_lock().iter() {
+            if !is_blocked(proc) {
+                continue;
+            }
+
+            blocked.insert(*pid, proc.switches);
+            if self.blocked.get(pid) == Some(&proc.switches)
+                && self.reported.get(pid) != Some(&proc.switches)
+            {
+                hung.push(*pid);
+            }
+        }
+
+        for pid in hung.iter() {
+            report(*pid);
+            self.reported.insert(*pid, blocked[pid]);
+        }
+        self.reported
+            .retain(|pid, switches| blocked.get(pid) == Some(switches));
+        self.blocked = blocked;
+    }
+}
+
+// waits without a timeout
+fn is_blocked(proc: &Process) -> bool {
+    match proc.state {
+        State::IOWait | State::EventWait | State::Blocked => true,
+        State::SemaWait | State::FutexWait => proc.timer.is_none(),
+        _ => false,
+    }
+}
+
+// what `pid` waits on
+pub fn wait_target(pid: Pid) -> Result<String, ProcessError> {
+    let pm = unsafe { process_manager() };
+    let (state, sema, futex, wchan) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, pid)?;
+        (proc.state, proc.sema, proc.futex, proc.wchan)
+    };
+
+    let target = match (state, sema, futex, wchan) {
+        (State::SemaWait, Some(sid), _, _) => format!("semaphore {}", sid),
+        (State::FutexWait, _, Some(key), _) => format!("futex {:#x}", key),
+        (State::IOWait, _, _, Some(device)) | (State::Blocked, _, _, Some(device)) => {
+            String::from(device)
+        }
+        (State::EventWait, _, _, _) => match pm.waiting_event(pid) {
+            Some(event) => format!("event {:?}", event),
+            None => String::from("unknown event"),
+        },
+        _ => String::from("unknown"),
+    };
+
+    Ok(target)
+}
+
+fn report(pid: Pid) {
+    let pm = unsafe { process_manager() };
+    let (name, state) = match get_process!(pm.ptable_lock(), pid) {
+        Ok(proc) => (proc.name.clone(), proc.state),
+        Err(_) => return,
+    };
+    let target = wait_target(pid).unwrap_or_default();
+    println!(
+        "hung task: pid {} ({}) blocked for more than {}s",
+        pid, name, HUNG_TASK_TIMEOUT_SECS
+    );
+    println!("  {:?} on {}", state, target);
+}
+
+pub unsafe fn hung_task_detector() -> &'static mut HungTaskDetector {
+    match HT {
+        Some(ref mut ht) => &mut *ht,
+        None => panic!("hung task detector is uninitialized"),
+    }
+}
+
+pub fn init() {
+    unsafe {
+        HT = Some(HungTaskDetector::new());
+    }
+    add_periodic_timer(
+        HUNG_TASK_TIMEOUT_SECS * TICKS_PER_SEC,
+        TimerAction::Callback(Box::new(|_| unsafe { hung_task_detector() }.check())),
+    );
+}
@@ -0,0 +1,33 @@
+use alloc::vec::Vec;
+
+// builds the little endian files written by the kernel, like checkpoints and core dumps
+pub struct Writer {
+    pub data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer { data: Vec::new() }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: usize) {
+        self.data.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    pub fn pad_to(&mut self, align: usize) {
+        let len = (self.data.len() + align - 1) / align * align;
+        self.data.resize(len, 0);
+    }
+}
@@ -0,0 +1,83 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::arch::target::process::*;
+use citron::arch::target::trampoline::KERNEL_MAPPED_START;
+use citron::aslr;
+use citron::process::ProcessError;
+use citron::random;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+#[test_case]
+fn test_aslr_random_offset() {
+    let enabled = aslr::set_enabled(true);
+    for _ in 0..100 {
+        let offset = aslr::random_offset(16);
+        assert_eq!(offset % 0x1000, 0);
+        assert!(offset < 16 * 0x1000);
+    }
+    assert!(random::below(7) < 7);
+    // not stuck at one value
+    let first = random::next_u64();
+    assert!((0..8).any(|_| random::next_u64() != first));
+
+    aslr::set_enabled(false);
+    assert_eq!(aslr::random_offset(16), 0);
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_aslr_layout() {
+    let enabled = aslr::set_enabled(false);
+    let addr_space = AddressSpace::new();
+    assert_eq!(addr_space.stack_top, USER_STACK_START);
+    assert_eq!(addr_space.mmap_next, MMAP_BASE);
+    assert!(addr_space.in_stack_guard(USER_STACK_START - USER_STACK_MAX - 1));
+    assert!(!addr_space.in_stack_guard(USER_STACK_START - USER_STACK_MAX));
+
+    aslr::set_enabled(true);
+    let addr_space = AddressSpace::new();
+    assert_eq!(addr_space.stack_top % 0x1000, 0);
+    assert!(addr_space.stack_top > USER_STACK_START - STACK_RANDOM_PAGES * 0x1000);
+    assert!(addr_space.mmap_next >= MMAP_BASE);
+    assert!(addr_space.mmap_next < MMAP_BASE + MMAP_RANDOM_PAGES * 0x1000);
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_aslr_map_window() {
+    let enabled = aslr::set_enabled(false);
+    let mut addr_space = AddressSpace::new();
+    let buffer = [0_u32; 0x800];
+    let buffer = buffer.as_ptr() as usize;
+
+    // a fixed address is kept, 0 takes the next free range from MMAP_BASE
+    assert_eq!(
+        addr_space.map_window(1, MMAP_BASE, buffer, 0x1800),
+        Ok(MMAP_BASE)
+    );
+    assert_eq!(
+        addr_space.map_window(2, 0, buffer, 0x100),
+        Ok(MMAP_BASE + 0x2000)
+    );
+    assert_eq!(
+        addr_space.map_window(3, 0, buffer, 0x100),
+        Ok(MMAP_BASE + 0x3000)
+    );
+
+    // a fixed address has to be aligned, in user memory and free
+    for vaddr in [MMAP_BASE + 0x4800, MMAP_BASE + 0x1000, KERNEL_MAPPED_START] {
+        assert_eq!(
+            addr_space.map_window(4, vaddr, buffer, 0x100),
+            Err(ProcessError::InvalidArgument)
+        );
+    }
+    aslr::set_enabled(enabled);
+}
@@ -0,0 +1,160 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use citron::arch::target::loader::Abi;
+use citron::arch::target::paging::EntryBits;
+use citron::arch::target::process::{USER_STACK_MAX, USER_STACK_START};
+use citron::arch::target::trampoline;
+use citron::checkpoint::*;
+use citron::process::ProcessError;
+use citron::rlimit::*;
+use citron::signal::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn image() -> CheckpointImage {
+    let mut registers = [0; 32];
+    registers[0] = 0x1000;
+    registers[2] = 0xffff_ffff_ffff_f000;
+    CheckpointImage {
+        name: String::from("job"),
+        priority: 1,
+        registers,
+        blocked: Signal::SIGUSR1.bit(),
+        actions: vec![SigAction::default(); NSIG],
+        limits: vec![RLimit::infinity(); RLIM_NLIMITS],
+        files: vec![OpenFile {
+            fd: 3,
+            offset: 42,
+            path: String::from("/data.txt"),
+        }],
+        windows: vec![WindowImage {
+            vaddr: 0x4000_0000,
+            x: 10,
+            y: 20,
+            width: 2,
+            height: 2,
+            title: String::from("job"),
+            pixels: vec![0xff00_0000, 0xffff_ffff, 0, 1],
+        }],
+        entry: 0x1000,
+        abi: Abi::Linux,
+        brk_start: 0x2000,
+        brk: 0x2800,
+        stack_top: USER_STACK_START,
+        stack_bottom: USER_STACK_START - 0x1000,
+        mappings: vec![0x3000..0x5000],
+        pages: vec![
+            PageImage {
+                vaddr: 0x1000,
+                flags: 0x1f,
+                data: vec![0x13; 0x1000],
+            },
+            PageImage {
+                vaddr: USER_STACK_START - 0x1000,
+                flags: 0x17,
+                data: vec![0x37; 0x1000],
+            },
+        ],
+    }
+}
+
+#[test_case]
+fn test_checkpoint_image() {
+    let image = image();
+    let data = image.encode();
+    assert_eq!(CheckpointImage::decode(&data).unwrap(), image);
+}
+
+#[test_case]
+fn test_checkpoint_bad_image() {
+    let data = image().encode();
+    assert!(matches!(
+        CheckpointImage::decode(&data[..data.len() - 1]),
+        Err(CheckpointError::BadImage)
+    ));
+
+    let mut data = data;
+    data[0] ^= 0xff;
+    assert!(matches!(
+        CheckpointImage::decode(&data),
+        Err(CheckpointError::BadImage)
+    ));
+}
+
+#[test_case]
+fn test_checkpoint_bad_page() {
+    // a kernel page, one without access, one over the pages mapped by the kernel and one outside
+    // of Sv39
+    for (vaddr, flags) in [
+        (0x1000, 0x0f),
+        (0x1000, 0x11),
+        (trampoline::TRAMPOLINE, 0x1f),
+        (0x40_0000_0000, 0x1f),
+    ]
+    .iter()
+    {
+        let mut image = image();
+        image.pages[0].vaddr = *vaddr;
+        image.pages[0].flags = *flags;
+        assert!(matches!(
+            CheckpointImage::decode(&image.encode()),
+            Err(CheckpointError::BadImage)
+        ));
+    }
+
+    let mut image = image();
+    image.windows[0].vaddr = trampoline::TRAPFRAME;
+    assert!(matches!(
+        CheckpointImage::decode(&image.encode()),
+        Err(CheckpointError::BadImage)
+    ));
+    let mut image = image();
+    image.mappings[0] = 0x3000..trampoline::TRAMPOLINE;
+    assert!(matches!(
+        CheckpointImage::decode(&image.encode()),
+        Err(CheckpointError::BadImage)
+    ));
+    let mut image = image();
+    image.stack_bottom = image.stack_top - USER_STACK_MAX - 0x1000;
+    assert!(matches!(
+        CheckpointImage::decode(&image.encode()),
+        Err(CheckpointError::BadImage)
+    ));
+}
+
+#[test_case]
+fn test_checkpoint_restore_stack() {
+    // the restored stack grows down from where it was saved
+    let image = image();
+    let mut addr_space = image.address_space(usize::MAX).unwrap();
+    assert_eq!(addr_space.stack_top, image.stack_top);
+    assert_eq!(addr_space.stack_bottom, image.stack_bottom);
+    let vaddr = image.stack_bottom - 0x800;
+    assert!(addr_space.fault(vaddr, EntryBits::W.val(), usize::MAX));
+    assert_eq!(addr_space.stack_bottom, image.stack_bottom - 0x1000);
+}
+
+#[test_case]
+fn test_checkpoint_restore_limit() {
+    // the pages and the windows have to fit in RLIMIT_AS of the caller
+    let image = image();
+    let size = 2 * 0x1000 + 4 * 4;
+    assert!(matches!(
+        image.address_space(size - 1),
+        Err(CheckpointError::Process(ProcessError::ResourceLimit(
+            RLIMIT_AS
+        )))
+    ));
+    assert!(image.address_space(size).is_ok());
+}
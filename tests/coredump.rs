@@ -0,0 +1,116 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use citron::coredump::*;
+use citron::signal::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> usize {
+    let mut value = [0; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(value) as usize
+}
+
+fn image() -> CoreImage {
+    let mut registers = [0; 32];
+    registers[0] = 0x1234;
+    registers[2] = 0x7000;
+    CoreImage {
+        name: String::from("crash"),
+        pid: 7,
+        tgid: 7,
+        signal: Signal::SIGSEGV,
+        pending: 0,
+        blocked: 0,
+        cpu_time: 0,
+        registers,
+        segments: vec![
+            CoreSegment {
+                vaddr: 0x1000,
+                flags: PF_R | PF_X,
+                data: vec![0x13; 0x2000],
+            },
+            CoreSegment {
+                vaddr: 0x6000,
+                flags: PF_R | PF_W,
+                data: vec![0xaa; 0x1000],
+            },
+        ],
+    }
+}
+
+#[test_case]
+fn test_core_header() {
+    let data = image().encode();
+    assert_eq!(&data[0..4], b"\x7fELF");
+    assert_eq!(data[4], 2); // 64 bit
+    assert_eq!(u16_at(&data, 16), ET_CORE);
+    assert_eq!(u16_at(&data, 18), EM_RISCV);
+    assert_eq!(u64_at(&data, 32), 64); // e_phoff
+    assert_eq!(u16_at(&data, 56), 3); // e_phnum
+}
+
+#[test_case]
+fn test_core_segments() {
+    let image = image();
+    let data = image.encode();
+    let phdr = |index: usize| 64 + 56 * index;
+
+    assert_eq!(u32_at(&data, phdr(0)), PT_NOTE);
+    let notes = u64_at(&data, phdr(0) + 8);
+    assert_eq!(u32_at(&data, notes + 8), NT_PRSTATUS);
+    assert_eq!(&data[notes + 12..notes + 17], b"CORE\0");
+    // pr_pid, and pc which is the first of pr_reg
+    let prstatus = notes + 20;
+    assert_eq!(u32_at(&data, prstatus), Signal::SIGSEGV.val() as u32);
+    assert_eq!(u32_at(&data, prstatus + 32), 7);
+    assert_eq!(u64_at(&data, prstatus + 112), 0x1234);
+    assert_eq!(u64_at(&data, prstatus + 112 + 2 * 8), 0x7000);
+
+    for (index, segment) in image.segments.iter().enumerate() {
+        let header = phdr(index + 1);
+        assert_eq!(u32_at(&data, header), PT_LOAD);
+        assert_eq!(u32_at(&data, header + 4), segment.flags);
+        let offset = u64_at(&data, header + 8);
+        assert_eq!(offset % 0x1000, 0);
+        assert_eq!(u64_at(&data, header + 16), segment.vaddr);
+        assert_eq!(u64_at(&data, header + 32), segment.data.len());
+        assert_eq!(
+            &data[offset..offset + segment.data.len()],
+            &segment.data[..]
+        );
+    }
+}
+
+#[test_case]
+fn test_core_dump_signals() {
+    assert_eq!(Signal::SIGSEGV.default_action(), DefaultAction::CoreDump);
+    assert_eq!(Signal::SIGABRT.default_action(), DefaultAction::CoreDump);
+    assert_eq!(Signal::SIGTERM.default_action(), DefaultAction::Terminate);
+    assert_eq!(core_path("/bin/crash", 7), "/core.crash.7");
+
+    assert!(set_enabled(false));
+    assert!(!enabled());
+    assert!(!set_enabled(true));
+}
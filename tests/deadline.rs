@@ -0,0 +1,91 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::deadline::*;
+use citron::process::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn spinner() {
+    loop {}
+}
+
+#[test_case]
+fn test_deadline_admission() {
+    let pm = unsafe { process_manager() };
+    let first = pm
+        .create_kernel_process("first", 1, spinner as usize)
+        .unwrap();
+    let second = pm
+        .create_kernel_process("second", 1, spinner as usize)
+        .unwrap();
+
+    assert_eq!(
+        pm.set_deadline(first, Some(DeadlineParams::new(3, 2, 4))),
+        Err(ProcessError::InvalidArgument)
+    );
+    // the bandwidth and the absolute deadline of periods this long would overflow
+    for max in [DL_PERIOD_MAX + 1, usize::MAX].iter() {
+        assert_eq!(
+            pm.set_deadline(first, Some(DeadlineParams::new(*max, *max, *max))),
+            Err(ProcessError::InvalidArgument)
+        );
+    }
+    assert_eq!(
+        pm.set_deadline(first, Some(DeadlineParams::new(6, 10, 10))),
+        Ok(())
+    );
+    assert!(pm.get_process_priority(first).unwrap() > pm.get_process_priority(second).unwrap());
+    assert_eq!(
+        pm.set_deadline(second, Some(DeadlineParams::new(4, 10, 10))),
+        Err(ProcessError::Busy)
+    );
+    assert_eq!(pm.get_deadline(second), Ok(None));
+
+    // dropping a reservation gives its bandwidth back
+    pm.set_deadline(first, None).unwrap();
+    assert_eq!(pm.get_process_priority(first), Ok(1));
+    assert_eq!(
+        pm.set_deadline(second, Some(DeadlineParams::new(4, 10, 10))),
+        Ok(())
+    );
+
+    pm.kill(first).unwrap();
+    pm.kill(second).unwrap();
+}
+
+#[test_case]
+fn test_deadline_throttle() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    let pid = pm
+        .create_kernel_process("spinner", 1, spinner as usize)
+        .unwrap();
+    pm.set_deadline(pid, Some(DeadlineParams::new(1, 4, 4)))
+        .unwrap();
+    pm.ready(pid).unwrap();
+
+    // the spinner never yields, so getting here again means its runtime ran out
+    while pm.get_process_state(pid).unwrap() != State::Throttled {
+        pm.schedule().unwrap();
+    }
+
+    pm.kill(pid).unwrap();
+    // the bandwidth of a killed process is free again
+    let pid = pm
+        .create_kernel_process("spinner", 1, spinner as usize)
+        .unwrap();
+    assert_eq!(
+        pm.set_deadline(pid, Some(DeadlineParams::new(9, 10, 10))),
+        Ok(())
+    );
+    pm.kill(pid).unwrap();
+}
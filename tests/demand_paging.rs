@@ -0,0 +1,204 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::arch::target::paging::{translate, EntryBits};
+use citron::arch::target::process::*;
+use citron::arch::target::signal::SignalFrame;
+use citron::aslr;
+use citron::process::{process_manager, ProcessManager};
+use citron::signal::*;
+use citron::uaccess::get_user;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+// EntryBits
+const R: usize = 1 << 1;
+const W: usize = 1 << 2;
+const X: usize = 1 << 3;
+const U: usize = 1 << 4;
+
+#[test_case]
+fn test_demand_paging_bits() {
+    assert_eq!(
+        [R, W, X, U],
+        [
+            EntryBits::R.val(),
+            EntryBits::W.val(),
+            EntryBits::X.val(),
+            EntryBits::U.val()
+        ]
+    );
+}
+
+fn mapped(addr_space: &AddressSpace, vaddr: usize) -> Option<(usize, usize)> {
+    translate(unsafe { addr_space.page_table.as_ref() }, vaddr)
+        .map(|(paddr, flags)| (paddr, flags & (R | W | X | U)))
+}
+
+#[test_case]
+fn test_demand_paging_zero_page() {
+    let mut addr_space = AddressSpace::new();
+    let vaddr = addr_space
+        .map_anonymous(0, 0x2000, R | W | U, usize::MAX)
+        .unwrap();
+    assert_eq!(mapped(&addr_space, vaddr), None);
+
+    // a read maps the zero page, the first write a private page
+    assert!(addr_space.fault(vaddr, R, usize::MAX));
+    assert_eq!(mapped(&addr_space, vaddr), Some((zero_page(), R | U)));
+    assert!(addr_space.exec_info.segment_buffers.is_empty());
+    let paddr = addr_space.fault_in(vaddr + 8, true, usize::MAX).unwrap();
+    assert_ne!(paddr & !0xfff, zero_page());
+    assert_eq!(mapped(&addr_space, vaddr), Some((paddr - 8, R | W | U)));
+    assert_eq!(addr_space.exec_info.segment_buffers.len(), 1);
+    unsafe { (paddr as *mut u64).write(0x1234) };
+    assert_eq!(unsafe { *(zero_page() as *const u64).add(1) }, 0);
+
+    // the other page is still untouched
+    assert_eq!(mapped(&addr_space, vaddr + 0x1000), None);
+    assert_eq!(addr_space.memory, 0x2000);
+}
+
+#[test_case]
+fn test_demand_paging_access() {
+    let mut addr_space = AddressSpace::new();
+    let vaddr = addr_space
+        .map_anonymous(0, 0x1000, R | U, usize::MAX)
+        .unwrap();
+    assert!(!addr_space.fault(vaddr, W, usize::MAX));
+    assert!(!addr_space.fault(vaddr, X, usize::MAX));
+    assert_eq!(addr_space.fault_in(vaddr, true, usize::MAX), None);
+    assert_eq!(
+        addr_space.fault_in(vaddr, false, usize::MAX),
+        Some(zero_page())
+    );
+
+    // a tracer writes its own copy of the zero page
+    let paddr = addr_space.tracee_to_phys(vaddr, true).unwrap();
+    assert_ne!(paddr, zero_page());
+    assert_eq!(mapped(&addr_space, vaddr), Some((paddr, R | U)));
+
+    // a reserved range has no pages
+    let reserved = addr_space.map_anonymous(0, 0x1000, U, usize::MAX).unwrap();
+    assert!(!addr_space.fault(reserved, R, usize::MAX));
+    assert_eq!(addr_space.tracee_to_phys(reserved, false), None);
+}
+
+#[test_case]
+fn test_demand_paging_fork() {
+    let mut addr_space = AddressSpace::new();
+    let vaddr = addr_space
+        .map_anonymous(0, 0x3000, R | W | U, usize::MAX)
+        .unwrap();
+    assert!(addr_space.fault(vaddr, R, usize::MAX));
+    let paddr = addr_space
+        .fault_in(vaddr + 0x1000, true, usize::MAX)
+        .unwrap();
+    unsafe { (paddr as *mut u8).write(0x5a) };
+
+    // the zero page stays shared and an untouched page untouched, a written page is copied
+    let mut copy = addr_space.duplicate();
+    assert_eq!(copy.memory, addr_space.memory);
+    assert_eq!(mapped(&copy, vaddr), Some((zero_page(), R | U)));
+    assert_eq!(mapped(&copy, vaddr + 0x2000), None);
+    let copied = copy.user_to_phys(vaddr + 0x1000).unwrap();
+    assert_ne!(copied, paddr);
+    assert_eq!(unsafe { *(copied as *const u8) }, 0x5a);
+    assert!(copy.fault(vaddr, W, usize::MAX));
+    assert_ne!(copy.user_to_phys(vaddr), Some(zero_page()));
+    assert_eq!(addr_space.user_to_phys(vaddr), Some(zero_page()));
+
+    // both see every page as writable
+    let rw = R | W | U;
+    assert!(addr_space
+        .user_pages()
+        .iter()
+        .all(|(_, _, flags)| flags & rw == rw));
+}
+
+#[test_case]
+fn test_demand_paging_stack() {
+    let enabled = aslr::set_enabled(false);
+    let mut addr_space = AddressSpace::new();
+    let top = addr_space.stack_top;
+    assert_eq!(addr_space.stack_bottom, top);
+
+    // the stack grows to a fault below it
+    assert!(addr_space.fault(top - 0x2800, W, usize::MAX));
+    assert_eq!(addr_space.stack_bottom, top - 0x3000);
+    assert_eq!(addr_space.memory, 0x3000);
+    assert_eq!(addr_space.areas.len(), 1);
+    assert_eq!(mapped(&addr_space, top - 0x1000), None);
+    assert!(addr_space.fault(top - 0x1000, R, usize::MAX));
+    assert_eq!(addr_space.areas.len(), 1);
+
+    // but not past USER_STACK_MAX or the limit of user memory
+    assert!(!addr_space.fault(top - 0x5000, W, 0x4000));
+    assert!(!addr_space.fault(top - USER_STACK_MAX - 1, W, usize::MAX));
+    assert!(addr_space.in_stack_guard(top - USER_STACK_MAX - 1));
+    assert!(addr_space.fault(top - USER_STACK_MAX, W, usize::MAX));
+    assert_eq!(addr_space.stack_bottom, top - USER_STACK_MAX);
+    assert_eq!(addr_space.memory, USER_STACK_MAX);
+    aslr::set_enabled(enabled);
+}
+
+fn stack_page(pm: &mut ProcessManager, vaddr: usize) -> Option<usize> {
+    let running = pm.running;
+    get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow()
+        .user_to_phys(vaddr)
+}
+
+#[test_case]
+fn test_demand_paging_signal_frame() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let (tf, bottom) = {
+        let ptable = pm.ptable_lock();
+        let proc = get_process!(ptable, running).unwrap();
+        let mut addr_space = proc.arch_proc.addr_space().borrow_mut();
+        let bottom = addr_space.stack_bottom;
+        // the page below the stack is only read, the one below it untouched
+        assert!(addr_space.fault(bottom - 0x1000, R, usize::MAX));
+        (proc.arch_proc.trap_frame, bottom)
+    };
+    assert_eq!(stack_page(pm, bottom - 0x1000), Some(zero_page()));
+    assert_eq!(stack_page(pm, bottom - 0x2000), None);
+
+    // a frame across both of them is pushed
+    let tf = unsafe { &mut *tf };
+    tf.sp = bottom - 0x1000 + 0x40;
+    tf.epc = 0x5000;
+    let action = SigAction {
+        handler: 0x1234,
+        flags: 0,
+        mask: 0,
+    };
+    pm.set_signal_action(running, Signal::SIGUSR1, action)
+        .unwrap();
+    pm.send_signal(running, Signal::SIGUSR1).unwrap();
+    pm.handle_signals().unwrap();
+    assert_eq!(tf.epc, 0x1234);
+    assert!(tf.sp < bottom - 0x1000);
+    for page in [bottom - 0x2000, bottom - 0x1000].iter() {
+        let paddr = stack_page(pm, *page).unwrap();
+        assert_ne!(paddr, zero_page());
+    }
+    assert_eq!(unsafe { *(zero_page() as *const u64) }, 0);
+    let frame = get_user::<SignalFrame>(pm, tf.sp).unwrap();
+    assert_eq!(frame.trap_frame.epc, 0x5000);
+
+    assert!(pm.signal_return().is_ok());
+    assert_eq!(tf.epc, 0x5000);
+    pm.set_signal_action(running, Signal::SIGUSR1, SigAction::default())
+        .unwrap();
+}
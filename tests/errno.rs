@@ -0,0 +1,58 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::checkpoint::CheckpointError;
+use citron::errno::*;
+use citron::fs;
+use citron::futex::FutexError;
+use citron::process::ProcessError;
+use citron::rlimit::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+#[test_case]
+fn test_errno_values() {
+    assert_eq!(Errno::EPERM.val(), 1);
+    assert_eq!(Errno::ETIMEDOUT.val(), 110);
+    assert_eq!(Errno::EINVAL.ret(), -22_isize as usize);
+    assert_eq!(Errno::from_val(2), Some(Errno::ENOENT));
+    assert_eq!(Errno::from_val(6), None);
+    assert_eq!(Errno::ENOSPC.name(), "ENOSPC");
+    for errno in ERRNOS.iter() {
+        assert_eq!(Errno::from_val(errno.val()), Some(*errno));
+    }
+}
+
+#[test_case]
+fn test_errno_from() {
+    assert_eq!(Errno::from(ProcessError::ProcessNotFound(5)), Errno::ESRCH);
+    assert_eq!(
+        Errno::from(ProcessError::ResourceLimit(RLIMIT_NOFILE)),
+        Errno::EMFILE
+    );
+    assert_eq!(Errno::from(fs::Error::FileNotOpen), Errno::EBADF);
+    assert_eq!(Errno::from(fs::Error::NoSpace), Errno::ENOSPC);
+    assert_eq!(Errno::from(fs::Error::Disk(fs::DiskError::Io)), Errno::EIO);
+    assert_eq!(
+        Errno::from(FutexError::Process(ProcessError::BadAddress)),
+        Errno::EFAULT
+    );
+    assert_eq!(Errno::from(FutexError::Interrupted), Errno::EINTR);
+    assert_eq!(
+        Errno::from(CheckpointError::Fs(fs::Error::FileNotExist)),
+        Errno::ENOENT
+    );
+}
+
+// the published codes are generated, run c_header and rust_module again after changing them
+#[test_case]
+fn test_errno_published() {
+    assert_eq!(include_str!("../bin/errno.h"), c_header());
+    assert_eq!(include_str!("../bin/errno.rs"), rust_module());
+}
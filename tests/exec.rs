@@ -0,0 +1,63 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use citron::errno::Errno;
+use citron::exec::*;
+use citron::fs;
+use citron::process::ProcessError;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+#[test_case]
+fn test_exec_shebang() {
+    assert_eq!(parse_shebang(b"\x7fELF"), Ok(None));
+    assert_eq!(
+        parse_shebang(b"#!/bin/sh\necho hi\n"),
+        Ok(Some((String::from("/bin/sh"), None)))
+    );
+    // one argument, with its inner spaces kept
+    assert_eq!(
+        parse_shebang(b"#! /bin/awk  -f  x \r\n"),
+        Ok(Some((
+            String::from("/bin/awk"),
+            Some(String::from("-f  x"))
+        )))
+    );
+    assert_eq!(
+        parse_shebang(b"#!/bin/sh"),
+        Ok(Some((String::from("/bin/sh"), None)))
+    );
+
+    assert_eq!(parse_shebang(b"#!\n"), Err(ProcessError::BadExecutable));
+    let mut long = [b'a'; 300];
+    long[..3].copy_from_slice(b"#!/");
+    assert_eq!(parse_shebang(&long), Err(ProcessError::BadExecutable));
+}
+
+#[test_case]
+fn test_exec_load_error() {
+    assert_eq!(
+        Errno::from(load_error("/bin/x", fs::Error::FileNotExist)),
+        Errno::ENOENT
+    );
+    assert_eq!(
+        Errno::from(load_error("/bin/x", fs::Error::MemoryLimit)),
+        Errno::ENOMEM
+    );
+    assert_eq!(
+        Errno::from(load_error(
+            "/bin/x",
+            fs::Error::BadExecutable(String::from("bad"))
+        )),
+        Errno::ENOEXEC
+    );
+}
@@ -0,0 +1,67 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::futex::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+static mut WORD: u32 = 0;
+static mut WOKEN: bool = false;
+
+fn word_key() -> usize {
+    unsafe { &WORD as *const u32 as usize }
+}
+
+fn waiter() {
+    let pm = unsafe { process::process_manager() };
+    futex_wait(word_key(), 0, None).unwrap();
+    unsafe {
+        WOKEN = true;
+    }
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_futex_wait_mismatch() {
+    assert_eq!(futex_wait(word_key(), 1, None), Err(FutexError::WouldBlock));
+}
+
+#[test_case]
+fn test_futex_timeout() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    assert_eq!(
+        futex_wait(word_key(), 0, Some(5)),
+        Err(FutexError::TimedOut)
+    );
+}
+
+#[test_case]
+fn test_futex_wake() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process::process_manager() };
+    let pid = pm
+        .create_kernel_process("waiter", 1, waiter as usize)
+        .unwrap();
+    pm.ready(pid).unwrap();
+
+    while pm.get_process_state(pid).unwrap() != process::State::FutexWait {
+        pm.schedule().unwrap();
+    }
+    assert_eq!(futex_wake(word_key(), 1), Ok(1));
+
+    while pm.get_process_state(pid).unwrap() != process::State::Free {
+        pm.schedule().unwrap();
+    }
+    assert!(unsafe { WOKEN });
+}
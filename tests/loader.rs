@@ -0,0 +1,491 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use citron::arch::target::loader::*;
+use citron::arch::target::paging::{translate, EntryBits};
+use citron::arch::target::process::{args_size, AddressSpace, ARGS_MAX};
+use citron::aslr;
+use citron::fs::Error;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const EM_RISCV: u16 = 243;
+
+// p_type, p_flags, p_offset, p_vaddr, p_filesz, p_memsz
+type Phdr = (u32, u32, usize, usize, usize, usize);
+
+// an executable with `phdrs` followed by `size` bytes of 0xaa from offset 0x100
+fn elf(machine: u16, entry: usize, phdrs: &[Phdr], size: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    data.extend_from_slice(&2_u16.to_le_bytes()); // ET_EXEC
+    data.extend_from_slice(&machine.to_le_bytes());
+    data.extend_from_slice(&1_u32.to_le_bytes());
+    data.extend_from_slice(&(entry as u64).to_le_bytes());
+    data.extend_from_slice(&64_u64.to_le_bytes()); // e_phoff
+    data.extend_from_slice(&0_u64.to_le_bytes()); // e_shoff
+    data.extend_from_slice(&0_u32.to_le_bytes()); // e_flags
+    data.extend_from_slice(&64_u16.to_le_bytes());
+    data.extend_from_slice(&56_u16.to_le_bytes());
+    data.extend_from_slice(&(phdrs.len() as u16).to_le_bytes());
+    data.extend_from_slice(&[0; 6]); // no section headers
+    for (p_type, flags, offset, vaddr, filesz, memsz) in phdrs.iter() {
+        data.extend_from_slice(&p_type.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        for value in [*offset, *vaddr, *vaddr, *filesz, *memsz, 0x1000].iter() {
+            data.extend_from_slice(&(*value as u64).to_le_bytes());
+        }
+    }
+    data.resize(0x100, 0);
+    data.resize(0x100 + size, 0xaa);
+    data
+}
+
+fn flags_at(addr_space: &AddressSpace, vaddr: usize) -> Option<usize> {
+    let bits = EntryBits::R.val() | EntryBits::W.val() | EntryBits::X.val() | EntryBits::U.val();
+    translate(unsafe { addr_space.page_table.as_ref() }, vaddr).map(|(_, flags)| flags & bits)
+}
+
+// map the page of `vaddr` as a read by user mode would
+fn touch(addr_space: &mut AddressSpace, vaddr: usize) -> bool {
+    addr_space.fault(vaddr, EntryBits::R.val(), usize::MAX)
+}
+
+fn set_dyn(data: &mut [u8]) {
+    data[16..18].copy_from_slice(&3_u16.to_le_bytes()); // ET_DYN
+}
+
+fn load(data: &[u8], max_memory: usize) -> (AddressSpace, Result<usize, Error>) {
+    load_with_interp(data, None, max_memory)
+}
+
+fn load_with_interp(
+    data: &[u8],
+    interp: Option<&[u8]>,
+    max_memory: usize,
+) -> (AddressSpace, Result<usize, Error>) {
+    let mut addr_space = AddressSpace::new();
+    let result = load_elf(data, interp, &mut addr_space.areas, max_memory).map(|exec_info| {
+        let entry = exec_info.entry;
+        addr_space.exec_info = exec_info;
+        entry
+    });
+    (addr_space, result)
+}
+
+#[test_case]
+fn test_loader_segments() {
+    let data = elf(
+        EM_RISCV,
+        0x10040,
+        &[
+            (PT_LOAD, PF_R | PF_X, 0, 0x10000, 0x100, 0x100),
+            (PT_LOAD, PF_R | PF_W, 0x100, 0x11100, 0x10, 0x2000),
+            (PT_NOTE, PF_R, 0x100, 0x50000, 0x10, 0x10),
+        ],
+        0x10,
+    );
+    let (mut addr_space, result) = load(&data, usize::MAX);
+    assert_eq!(result.unwrap(), 0x10040);
+    assert_eq!(addr_space.areas.len(), 2);
+    // nothing is mapped before it is touched
+    assert_eq!(flags_at(&addr_space, 0x10000), None);
+
+    let r = EntryBits::R.val() | EntryBits::U.val();
+    let rx = r | EntryBits::X.val();
+    let rw = r | EntryBits::W.val();
+    for vaddr in [0x10000, 0x11000, 0x13000].iter() {
+        assert!(touch(&mut addr_space, *vaddr));
+    }
+    assert!(!touch(&mut addr_space, 0x14000));
+    assert!(!touch(&mut addr_space, 0x50000));
+    assert_eq!(flags_at(&addr_space, 0x10000), Some(rx));
+    assert_eq!(flags_at(&addr_space, 0x11000), Some(rw));
+    // a page of bss only is the zero page until it is written
+    assert_eq!(flags_at(&addr_space, 0x13000), Some(r));
+    assert_eq!(flags_at(&addr_space, 0x12000), None);
+    assert_eq!(flags_at(&addr_space, 0x14000), None);
+    assert_eq!(flags_at(&addr_space, 0x50000), None);
+    assert_eq!(addr_space.exec_info.segment_buffers.len(), 2);
+
+    // the file contents are at their unaligned address, the bss after them is zero
+    let (paddr, _) = translate(unsafe { addr_space.page_table.as_ref() }, 0x11100).unwrap();
+    let page = unsafe { core::slice::from_raw_parts(paddr as *const u8, 0x20) };
+    assert_eq!(page[..0x10], [0xaa; 0x10]);
+    assert_eq!(page[0x10..], [0; 0x10]);
+}
+
+#[test_case]
+fn test_loader_release_contents() {
+    let data = elf(
+        EM_RISCV,
+        0x10100,
+        &[(PT_LOAD, PF_R | PF_W, 0x100, 0x10100, 0x1800, 0x3000)],
+        0x1800,
+    );
+    let (mut addr_space, result) = load(&data, usize::MAX);
+    assert!(result.is_ok());
+    let contents = match &addr_space.areas[0].backing {
+        Backing::Image(contents) => contents.clone(),
+        Backing::Zero => panic!("the segment has file contents"),
+    };
+
+    // the file contents go once the pages which have them are mapped, the bss stays zero filled
+    assert!(touch(&mut addr_space, 0x11000));
+    assert!(matches!(addr_space.areas[0].backing, Backing::Image(_)));
+    assert!(touch(&mut addr_space, 0x10000));
+    assert!(matches!(addr_space.areas[0].backing, Backing::Zero));
+    assert_eq!(Rc::strong_count(&contents), 1);
+    assert!(touch(&mut addr_space, 0x12000));
+    let byte = |vaddr: usize| unsafe { *(addr_space.user_to_phys(vaddr).unwrap() as *const u8) };
+    assert_eq!([byte(0x118ff), byte(0x11900), byte(0x12000)], [0xaa, 0, 0]);
+}
+
+#[test_case]
+fn test_loader_shared_page() {
+    // text and rodata in one page
+    let data = elf(
+        EM_RISCV,
+        0x10000,
+        &[
+            (PT_LOAD, PF_R | PF_X, 0, 0x10000, 0x100, 0x100),
+            (PT_LOAD, PF_R, 0x100, 0x10100, 0x10, 0x10),
+        ],
+        0x10,
+    );
+    let (mut addr_space, result) = load(&data, usize::MAX);
+    assert!(result.is_ok());
+    assert_eq!(addr_space.areas.len(), 1);
+    assert!(touch(&mut addr_space, 0x10100));
+    let rx = EntryBits::R.val() | EntryBits::X.val() | EntryBits::U.val();
+    assert_eq!(flags_at(&addr_space, 0x10000), Some(rx));
+    assert_eq!(addr_space.exec_info.segment_buffers.len(), 1);
+}
+
+#[test_case]
+fn test_loader_reject() {
+    let text = (PT_LOAD, PF_R | PF_X, 0, 0x10000, 0x100, 0x100);
+    let bad = [
+        elf(62, 0x10000, &[text], 0),
+        elf(EM_RISCV, 0x20000, &[text], 0),
+        elf(
+            EM_RISCV,
+            0x10000,
+            &[text, (PT_LOAD, PF_R, 0x100, 0xf100, 0x10, 0x1000)],
+            0x10,
+        ),
+        elf(
+            EM_RISCV,
+            0x10000,
+            &[text, (PT_LOAD, PF_R, 0x100, 0x11100, 0x20, 0x10)],
+            0x20,
+        ),
+        elf(
+            EM_RISCV,
+            0x10000,
+            &[text, (PT_LOAD, PF_R, 0x100, 0x11100, 0x1000, 0x1000)],
+            0x10,
+        ),
+        Vec::from(&b"#!/bin/sh\n"[..]),
+    ];
+    for data in bad.iter() {
+        let (addr_space, result) = load(data, usize::MAX);
+        assert!(matches!(result, Err(Error::BadExecutable(_))));
+        assert!(addr_space.areas.is_empty());
+    }
+
+    let data = elf(EM_RISCV, 0x10000, &[text], 0);
+    let (addr_space, result) = load(&data, 0);
+    assert!(matches!(result, Err(Error::MemoryLimit)));
+    assert!(addr_space.areas.is_empty());
+}
+
+#[test_case]
+fn test_loader_pie() {
+    // a relocation table at 0x1140 and a word at 0x1160 which points to 0x20
+    let mut data = elf(
+        EM_RISCV,
+        0x40,
+        &[
+            (PT_LOAD, PF_R | PF_X, 0, 0, 0x100, 0x100),
+            (PT_LOAD, PF_R | PF_W, 0x100, 0x1100, 0x80, 0x80),
+            (PT_DYNAMIC, PF_R | PF_W, 0x100, 0x1100, 0x40, 0x40),
+        ],
+        0x80,
+    );
+    set_dyn(&mut data);
+    let words: [u64; 11] = [7, 0x1140, 8, 24, 9, 24, 0, 0, 0x1160, 3, 0x20];
+    for (index, word) in words.iter().enumerate() {
+        data[0x100 + index * 8..0x108 + index * 8].copy_from_slice(&word.to_le_bytes());
+    }
+    data[0x160..0x168].copy_from_slice(&[0; 8]);
+
+    let enabled = aslr::set_enabled(false);
+    let (mut addr_space, result) = load(&data, usize::MAX);
+    assert_eq!(result.unwrap(), PIE_BASE + 0x40);
+    assert_eq!(addr_space.exec_info.bias, PIE_BASE);
+    assert!(touch(&mut addr_space, PIE_BASE));
+    assert!(touch(&mut addr_space, PIE_BASE + 0x1000));
+    assert!(!touch(&mut addr_space, 0));
+    let (paddr, _) =
+        translate(unsafe { addr_space.page_table.as_ref() }, PIE_BASE + 0x1160).unwrap();
+    assert_eq!(
+        unsafe { (paddr as *const u64).read() },
+        PIE_BASE as u64 + 0x20
+    );
+
+    // the base moves by whole pages when randomized
+    aslr::set_enabled(true);
+    let (addr_space, result) = load(&data, usize::MAX);
+    let bias = addr_space.exec_info.bias;
+    assert_eq!(result.unwrap(), bias + 0x40);
+    assert_eq!(bias % 0x1000, 0);
+    assert!(bias >= PIE_BASE && bias < PIE_BASE + PIE_RANDOM_PAGES * 0x1000);
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_loader_interp() {
+    // the program names /lib/ld.so, its program headers are loaded with the text
+    let mut data = elf(
+        EM_RISCV,
+        0x10040,
+        &[
+            (PT_LOAD, PF_R | PF_X, 0, 0x10000, 0x100, 0x100),
+            (PT_INTERP, PF_R, 0x100, 0x10100, 11, 11),
+        ],
+        0x10,
+    );
+    data[0x100..0x10b].copy_from_slice(b"/lib/ld.so\0");
+    let mut interp = elf(
+        EM_RISCV,
+        0x20,
+        &[(PT_LOAD, PF_R | PF_X, 0, 0, 0x100, 0x100)],
+        0,
+    );
+    set_dyn(&mut interp);
+
+    let enabled = aslr::set_enabled(false);
+    let (mut addr_space, result) = load_with_interp(&data, Some(&interp), usize::MAX);
+    assert_eq!(result.unwrap(), INTERP_BASE + 0x20);
+    let exec_info = &addr_space.exec_info;
+    assert_eq!(exec_info.program_entry, 0x10040);
+    assert_eq!(exec_info.interp_base, INTERP_BASE);
+    assert_eq!(exec_info.phdr, 0x10040);
+    assert_eq!(exec_info.phnum, 2);
+    let auxv = exec_info.auxv();
+    assert!(touch(&mut addr_space, 0x10000));
+    assert!(touch(&mut addr_space, INTERP_BASE));
+    assert!(auxv.contains(&(AT_ENTRY, 0x10040)));
+    assert!(auxv.contains(&(AT_BASE, INTERP_BASE)));
+
+    // a missing interpreter, one asking for an interpreter itself and one over the program
+    let mut fixed = interp.clone();
+    fixed[16..18].copy_from_slice(&2_u16.to_le_bytes());
+    fixed[24..32].copy_from_slice(&0x10020_u64.to_le_bytes());
+    fixed[64 + 16..64 + 24].copy_from_slice(&0x10000_u64.to_le_bytes());
+    for interp in [None, Some(&data[..]), Some(&fixed[..])].iter() {
+        let (addr_space, result) = load_with_interp(&data, *interp, usize::MAX);
+        assert!(matches!(result, Err(Error::BadExecutable(_))));
+        assert!(addr_space.areas.is_empty());
+    }
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_loader_init_stack() {
+    // the stack grows to the pages written
+    let mut addr_space = AddressSpace::new();
+    let sp = addr_space.init_stack(&["/bin/ls", "-l"], &["HOME=/"], &[(AT_PAGESZ, 0x1000)]);
+    assert_eq!(sp % 16, 0);
+    assert_eq!(addr_space.stack_bottom, sp & !0xfff);
+    let byte = |vaddr: usize| unsafe { *(addr_space.user_to_phys(vaddr).unwrap() as *const u8) };
+    let word = |vaddr: usize| unsafe { *(addr_space.user_to_phys(vaddr).unwrap() as *const usize) };
+    let string = |vaddr: usize| {
+        (vaddr..)
+            .map(byte)
+            .take_while(|byte| *byte != 0)
+            .collect::<Vec<u8>>()
+    };
+    // argc, argv, envp, then the auxiliary vector
+    assert_eq!(word(sp), 2);
+    assert_eq!(string(word(sp + 8)), b"/bin/ls");
+    assert_eq!(string(word(sp + 16)), b"-l");
+    assert_eq!(word(sp + 24), 0);
+    assert_eq!(string(word(sp + 32)), b"HOME=/");
+    assert_eq!(word(sp + 40), 0);
+    assert_eq!([word(sp + 48), word(sp + 56)], [AT_PAGESZ, 0x1000]);
+    assert_eq!(word(sp + 64), AT_RANDOM);
+    assert_eq!(word(sp + 72), addr_space.stack_top - 16);
+    assert_eq!([word(sp + 80), word(sp + 88)], [AT_NULL, 0]);
+}
+
+#[test_case]
+fn test_loader_init_stack_pages() {
+    // the strings can take more than the first page of the stack
+    let mut addr_space = AddressSpace::new();
+    let arg = "a".repeat(0x2800);
+    let sp = addr_space.init_stack(&[&arg], &[], &[]);
+    assert!(addr_space.stack_bottom <= addr_space.stack_top - 0x3000);
+    let word = |vaddr: usize| unsafe { *(addr_space.user_to_phys(vaddr).unwrap() as *const usize) };
+    assert_eq!(word(sp), 1);
+    let start = word(sp + 8);
+    for vaddr in [start, start + 0x1000, start + 0x27ff].iter() {
+        let byte = unsafe { *(addr_space.user_to_phys(*vaddr).unwrap() as *const u8) };
+        assert_eq!(byte, b'a');
+    }
+    assert!(args_size(&[&arg], &[]) <= ARGS_MAX);
+}
+
+#[test_case]
+fn test_loader_args_too_long() {
+    let pm = unsafe { process::process_manager() };
+    let running = pm.running;
+    let arg = "x".repeat(ARGS_MAX);
+    assert_eq!(
+        pm.load_program(running, "/bin/main", &[&arg], &[]),
+        Err(process::ProcessError::ArgumentListTooLong)
+    );
+    assert!(args_size(&["x"; 4], &[]) <= ARGS_MAX);
+}
+
+#[test_case]
+fn test_loader_abi() {
+    let phdrs = [
+        (PT_LOAD, PF_R | PF_X, 0, 0x10000, 0x200, 0x1800),
+        (PT_NOTE, PF_R, 0x100, 0x10100, 20, 20),
+    ];
+    let mut data = elf(EM_RISCV, 0x10000, &phdrs, 0x100);
+    let (addr_space, result) = load(&data, usize::MAX);
+    assert!(result.is_ok());
+    assert_eq!(addr_space.exec_info.abi, Abi::Linux);
+    assert_eq!(addr_space.exec_info.program_end, 0x11800);
+
+    // namesz, descsz and type, then the name padded to 4 bytes
+    let mut note = Vec::new();
+    for word in [7_u32, 0, NT_CITRON_ABI].iter() {
+        note.extend_from_slice(&word.to_le_bytes());
+    }
+    note.extend_from_slice(b"citron\0\0");
+    data[0x100..0x114].copy_from_slice(&note);
+    let (addr_space, result) = load(&data, usize::MAX);
+    assert!(result.is_ok());
+    assert_eq!(addr_space.exec_info.abi, Abi::Native);
+}
+
+#[test_case]
+fn test_loader_brk() {
+    let enabled = aslr::set_enabled(false);
+    let rw = EntryBits::R.val() | EntryBits::W.val() | EntryBits::U.val();
+    let mut addr_space = AddressSpace::new();
+    addr_space.init_brk(0x10800);
+    assert_eq!(addr_space.brk, 0x11000);
+
+    assert_eq!(addr_space.set_brk(0x12800, usize::MAX), 0x12800);
+    assert_eq!(addr_space.memory, 0x2000);
+    assert_eq!(flags_at(&addr_space, 0x12000), None);
+    assert!(addr_space.fault(0x12000, EntryBits::W.val(), usize::MAX));
+    assert_eq!(flags_at(&addr_space, 0x12000), Some(rw));
+    // the heap can't shrink below its start or grow past the limit
+    assert_eq!(addr_space.set_brk(0x10000, usize::MAX), 0x12800);
+    assert_eq!(addr_space.set_brk(0x20000, 0x3000), 0x12800);
+
+    assert_eq!(addr_space.set_brk(0x11000, usize::MAX), 0x11000);
+    assert_eq!(flags_at(&addr_space, 0x11000), None);
+    assert_eq!(addr_space.memory, 0);
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_loader_map_anonymous() {
+    let enabled = aslr::set_enabled(false);
+    let rw = EntryBits::R.val() | EntryBits::W.val() | EntryBits::U.val();
+    let mut addr_space = AddressSpace::new();
+    let vaddr = addr_space.map_anonymous(0, 0x2800, rw, usize::MAX).unwrap();
+    assert_eq!(vaddr % 0x1000, 0);
+    assert_eq!(addr_space.memory, 0x3000);
+    for page in (vaddr..(vaddr + 0x3000)).step_by(0x1000) {
+        assert!(addr_space.fault(page, EntryBits::W.val(), usize::MAX));
+    }
+    assert_eq!(flags_at(&addr_space, vaddr + 0x2000), Some(rw));
+    assert_eq!(
+        addr_space.map_anonymous(vaddr + 0x1000, 0x1000, rw, usize::MAX),
+        Err(process::ProcessError::InvalidArgument)
+    );
+    assert_eq!(
+        addr_space.map_anonymous(0, 0x1000, rw, 0x3000),
+        Err(process::ProcessError::ResourceLimit(rlimit::RLIMIT_AS))
+    );
+
+    // unmapping the middle page splits the mapping
+    addr_space.unmap_anonymous(vaddr + 0x1000, 0x1000);
+    assert_eq!(flags_at(&addr_space, vaddr + 0x1000), None);
+    assert_eq!(flags_at(&addr_space, vaddr + 0x2000), Some(rw));
+    assert_eq!(
+        addr_space.mappings,
+        [vaddr..(vaddr + 0x1000), (vaddr + 0x2000)..(vaddr + 0x3000)]
+    );
+    assert_eq!(
+        addr_space.map_anonymous(vaddr + 0x1000, 0x1000, rw, usize::MAX),
+        Ok(vaddr + 0x1000)
+    );
+    addr_space.unmap_anonymous(vaddr, 0x3000);
+    assert!(addr_space.mappings.is_empty());
+    assert_eq!(addr_space.memory, 0);
+    aslr::set_enabled(enabled);
+}
+
+#[test_case]
+fn test_loader_map_anonymous_fixed() {
+    let enabled = aslr::set_enabled(false);
+    let r = EntryBits::R.val() | EntryBits::U.val();
+    let rw = r | EntryBits::W.val();
+    let mut addr_space = AddressSpace::new();
+    let vaddr = addr_space.map_anonymous(0, 0x2000, rw, usize::MAX).unwrap();
+    assert!(addr_space.fault(vaddr, EntryBits::W.val(), usize::MAX));
+
+    // a mapping which doesn't fit keeps the old one
+    assert_eq!(
+        addr_space.map_anonymous_fixed(vaddr, 0x3000, rw, 0x2000),
+        Err(process::ProcessError::ResourceLimit(rlimit::RLIMIT_AS))
+    );
+    assert_eq!(flags_at(&addr_space, vaddr), Some(rw));
+    // so does one over the heap
+    addr_space.init_brk(0x10000);
+    assert_eq!(addr_space.set_brk(0x11000, usize::MAX), 0x11000);
+    assert_eq!(
+        addr_space.map_anonymous_fixed(0x10000, 0x1000, rw, usize::MAX),
+        Err(process::ProcessError::InvalidArgument)
+    );
+
+    // the pages under it are replaced
+    assert_eq!(
+        addr_space.map_anonymous_fixed(vaddr + 0x1000, 0x2000, r, usize::MAX),
+        Ok(vaddr + 0x1000)
+    );
+    assert_eq!(
+        addr_space.mappings,
+        [vaddr..(vaddr + 0x1000), (vaddr + 0x1000)..(vaddr + 0x3000)]
+    );
+    assert_eq!(addr_space.memory, 0x4000);
+    assert_eq!(flags_at(&addr_space, vaddr), Some(rw));
+    assert!(!addr_space.fault(vaddr + 0x1000, EntryBits::W.val(), usize::MAX));
+    aslr::set_enabled(enabled);
+}
@@ -0,0 +1,67 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+#[cfg(feature = "lockdep")]
+use citron::lockdep::{self, LockKind};
+use citron::*;
+use core::arch::asm;
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+
+test_harness!();
+
+// not a process, its locks are only known to lockdep
+#[cfg(feature = "lockdep")]
+const PID: usize = 1000;
+
+// every class is taken at the place it is created
+#[cfg(feature = "lockdep")]
+fn take(class: lockdep::LockClass, kind: LockKind) {
+    lockdep::acquire(PID, class, kind, class, false);
+}
+
+#[cfg(feature = "lockdep")]
+#[test_case]
+fn test_lockdep_cycle() {
+    let a = Location::caller();
+    let b = Location::caller();
+    take(a, LockKind::Spin);
+    take(b, LockKind::Spin);
+    lockdep::release(PID, b);
+    lockdep::release(PID, a);
+    assert!(!lockdep::reported("cycle", b, a));
+
+    take(b, LockKind::Spin);
+    take(a, LockKind::Spin);
+    assert!(lockdep::reported("cycle", b, a));
+    lockdep::exit(PID);
+}
+
+#[cfg(feature = "lockdep")]
+#[test_case]
+fn test_lockdep_recursive() {
+    let a = Location::caller();
+    take(a, LockKind::Mutex);
+    take(a, LockKind::Mutex);
+    assert!(lockdep::reported("recursive", a, a));
+    lockdep::exit(PID);
+}
+
+#[cfg(feature = "lockdep")]
+#[test_case]
+fn test_lockdep_sleep() {
+    let spin = Location::caller();
+    let mutex = Location::caller();
+    take(spin, LockKind::Spin);
+    take(mutex, LockKind::Mutex);
+    assert!(lockdep::reported("sleep", spin, mutex));
+
+    lockdep::release(PID, mutex);
+    lockdep::might_sleep(PID);
+    assert!(lockdep::reported("sleep", spin, spin));
+    lockdep::exit(PID);
+}
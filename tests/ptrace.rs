@@ -0,0 +1,90 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::vec;
+use citron::arch::target::ptrace::*;
+use citron::process::*;
+use citron::ptrace::*;
+use citron::signal::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+const PC: usize = 0x1000;
+
+fn registers() -> [usize; 32] {
+    let mut regs = [0; 32];
+    regs[0] = PC;
+    regs[1] = 0x2001; // ra
+    regs[10] = 0x3000; // a0
+    regs
+}
+
+#[test_case]
+fn test_next_pcs() {
+    let regs = registers();
+    // addi a0, a0, 1
+    assert_eq!(next_pcs(PC, 0x00150513, &regs), vec![PC + 4]);
+    // jal ra, 8 and j -4
+    assert_eq!(next_pcs(PC, 0x008000ef, &regs), vec![PC + 8]);
+    assert_eq!(next_pcs(PC, 0xffdff06f, &regs), vec![PC - 4]);
+    // beq a0, a1, 16
+    assert_eq!(next_pcs(PC, 0x00b50863, &regs), vec![PC + 4, PC + 16]);
+    // ret and jalr ra, 4(a0)
+    assert_eq!(next_pcs(PC, 0x00008067, &regs), vec![0x2000]);
+    assert_eq!(next_pcs(PC, 0x004500e7, &regs), vec![0x3004]);
+}
+
+#[test_case]
+fn test_next_pcs_compressed() {
+    let regs = registers();
+    // c.nop, c.ebreak
+    assert_eq!(next_pcs(PC, 0x0001, &regs), vec![PC + 2]);
+    assert_eq!(next_pcs(PC, 0x9002, &regs), vec![PC + 2]);
+    // c.j 8 and c.j -2
+    assert_eq!(next_pcs(PC, 0xa021, &regs), vec![PC + 8]);
+    assert_eq!(next_pcs(PC, 0xbffd, &regs), vec![PC - 2]);
+    // c.beqz a0, 4
+    assert_eq!(next_pcs(PC, 0xc111, &regs), vec![PC + 2, PC + 4]);
+    // c.jr ra
+    assert_eq!(next_pcs(PC, 0x8082, &regs), vec![0x2000]);
+}
+
+#[test_case]
+fn test_breakpoint_for() {
+    assert_eq!(breakpoint_for(0x0513), (EBREAK.to_le_bytes(), 4));
+    assert_eq!(breakpoint_for(0x0001), ([0x02, 0x90, 0, 0], 2));
+}
+
+#[test_case]
+fn test_stop_status() {
+    let status = stop_status(Signal::SIGTRAP);
+    assert_eq!(status & 0xff, 0x7f);
+    assert_eq!(status >> 8, Signal::SIGTRAP.val());
+}
+
+#[test_case]
+fn test_ptrace_errors() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    // a process can't trace itself, and only a tracer can resume
+    assert_eq!(
+        request(pm, PTRACE_ATTACH, running, 0, 0).unwrap_err(),
+        ProcessError::PermissionDenied
+    );
+    assert_eq!(
+        request(pm, PTRACE_CONT, running, 0, 0).unwrap_err(),
+        ProcessError::ProcessNotFound(running)
+    );
+    assert_eq!(
+        request(pm, 100, running, 0, 0).unwrap_err(),
+        ProcessError::InvalidArgument
+    );
+}
@@ -0,0 +1,75 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::process::*;
+use citron::rlimit::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn idle() {
+    let pm = unsafe { process_manager() };
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_rlimit_set() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let nofile = pm.get_rlimit(running, RLIMIT_NOFILE).unwrap();
+    assert!(nofile.cur <= nofile.max);
+
+    let lowered = RLimit {
+        cur: 8,
+        max: nofile.max - 1,
+    };
+    assert_eq!(pm.set_rlimit(running, RLIMIT_NOFILE, lowered), Ok(()));
+    assert_eq!(pm.get_rlimit(running, RLIMIT_NOFILE), Ok(lowered));
+
+    // the hard limit can't be raised back
+    assert_eq!(
+        pm.set_rlimit(running, RLIMIT_NOFILE, nofile),
+        Err(ProcessError::PermissionDenied)
+    );
+    assert_eq!(
+        pm.set_rlimit(running, RLIMIT_NOFILE, RLimit { cur: 9, max: 8 }),
+        Err(ProcessError::InvalidArgument)
+    );
+    assert_eq!(
+        pm.get_rlimit(running, RLIM_NLIMITS),
+        Err(ProcessError::InvalidArgument)
+    );
+}
+
+#[test_case]
+fn test_rlimit_nproc() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let procs = pm
+        .ptable_lock()
+        .values()
+        .filter(|proc| proc.state != State::Free)
+        .count();
+    let limit = RLimit {
+        cur: procs,
+        max: RLIM_INFINITY,
+    };
+    pm.set_rlimit(running, RLIMIT_NPROC, limit).unwrap();
+    assert_eq!(
+        pm.create_kernel_process("idle", 1, idle as usize),
+        Err(ProcessError::ResourceLimit(RLIMIT_NPROC))
+    );
+
+    pm.set_rlimit(running, RLIMIT_NPROC, RLimit::infinity())
+        .unwrap();
+    let pid = pm.create_kernel_process("idle", 1, idle as usize).unwrap();
+    pm.ready(pid).unwrap();
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::arch::target::syscall::sys_sem_open;
+use citron::errno::Errno;
+use citron::process::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+static mut SID: Sid = 0;
+static mut RESULT: Option<Result<(), ProcessError>> = None;
+
+fn waiter() {
+    let pm = unsafe { process_manager() };
+    let res = pm.wait_semaphore(unsafe { SID });
+    unsafe {
+        RESULT = Some(res);
+    }
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_semaphore_try_wait() {
+    let pm = unsafe { process_manager() };
+    let sid = pm.create_semaphore(1);
+    assert_eq!(pm.try_wait_semaphore(sid), Ok(()));
+    assert_eq!(pm.try_wait_semaphore(sid), Err(ProcessError::WouldBlock));
+    pm.signal_semaphore(sid).unwrap();
+    assert_eq!(pm.try_wait_semaphore(sid), Ok(()));
+}
+
+#[test_case]
+fn test_semaphore_timeout() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    let sid = pm.create_semaphore(0);
+    assert_eq!(
+        pm.wait_semaphore_timeout(sid, Some(5)),
+        Err(ProcessError::TimedOut)
+    );
+
+    // the count taken by the timed out wait is given back
+    pm.signal_semaphore(sid).unwrap();
+    assert_eq!(pm.try_wait_semaphore(sid), Ok(()));
+}
+
+#[test_case]
+fn test_semaphore_delete_wakes_waiters() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    let sid = pm.create_semaphore(0);
+    unsafe {
+        SID = sid;
+    }
+    let pid = pm
+        .create_kernel_process("waiter", 1, waiter as usize)
+        .unwrap();
+    pm.ready(pid).unwrap();
+
+    while pm.get_process_state(pid).unwrap() != State::SemaWait {
+        pm.schedule().unwrap();
+    }
+    pm.delete_semaphore(sid).unwrap();
+
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+    assert_eq!(
+        unsafe { RESULT },
+        Some(Err(ProcessError::SemaphoreDeleted(sid)))
+    );
+}
+
+fn high() {
+    let pm = unsafe { process_manager() };
+    pm.wait_semaphore(unsafe { SID }).unwrap();
+    pm.signal_semaphore(unsafe { SID }).unwrap();
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_mutex_priority_inheritance() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let base_priority = pm.get_process_priority(running).unwrap();
+
+    let sid = pm.create_mutex();
+    unsafe {
+        SID = sid;
+    }
+    pm.wait_semaphore(sid).unwrap();
+
+    // runs at once and blocks on the mutex
+    let pid = pm
+        .create_kernel_process("high", base_priority + 2, high as usize)
+        .unwrap();
+    pm.ready(pid).unwrap();
+    assert_eq!(pm.get_process_state(pid).unwrap(), State::SemaWait);
+    assert_eq!(pm.get_process_priority(running).unwrap(), base_priority + 2);
+
+    pm.signal_semaphore(sid).unwrap();
+    assert_eq!(pm.get_process_priority(running).unwrap(), base_priority);
+
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+}
+
+#[test_case]
+fn test_semaphore_negative_count() {
+    let pm = unsafe { process_manager() };
+    assert_eq!(
+        pm.open_semaphore(None, -1),
+        Err(ProcessError::InvalidArgument)
+    );
+    assert_eq!(
+        unsafe { sys_sem_open(pm, 0, usize::MAX) },
+        Errno::EINVAL.ret()
+    );
+
+    // a post with nobody waiting only counts up
+    let sid = pm.open_semaphore(None, 0).unwrap();
+    pm.signal_semaphore(sid).unwrap();
+    assert_eq!(pm.try_wait_semaphore(sid), Ok(()));
+    assert_eq!(pm.try_wait_semaphore(sid), Err(ProcessError::WouldBlock));
+    pm.delete_semaphore(sid).unwrap();
+}
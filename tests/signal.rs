@@ -0,0 +1,163 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::arch::target::process::TrapFrame;
+use citron::arch::target::signal::*;
+use citron::arch::target::trampoline;
+use citron::process::*;
+use citron::signal::*;
+use citron::uaccess::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+const HANDLER: usize = 0x1234;
+const PC: usize = 0x5000;
+
+// the user context of the null process, which the tests deliver signals to
+fn trap_frame(pm: &mut ProcessManager) -> &'static mut TrapFrame {
+    let running = pm.running;
+    let tf = get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .trap_frame;
+    unsafe { &mut *tf }
+}
+
+// the user context interrupted at PC with the stack pointer in the stack of the process
+fn enter_user(pm: &mut ProcessManager) {
+    let running = pm.running;
+    let stack_top = get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow()
+        .stack_top;
+    let tf = trap_frame(pm);
+    tf.epc = PC;
+    tf.sp = stack_top - 0x100;
+    tf.a0 = 7;
+}
+
+fn set_handler(pm: &mut ProcessManager, sig: Signal, handler: usize, mask: SigSet) {
+    let running = pm.running;
+    let action = SigAction {
+        handler,
+        flags: 0,
+        mask,
+    };
+    pm.set_signal_action(running, sig, action).unwrap();
+}
+
+#[test_case]
+fn test_signal_handler() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    enter_user(pm);
+    set_handler(pm, Signal::SIGUSR1, HANDLER, Signal::SIGUSR2.bit());
+    trap_frame(pm).kernel_sp = 0xdead_0000;
+    let sp = trap_frame(pm).sp;
+
+    pm.send_signal(running, Signal::SIGUSR1).unwrap();
+    pm.handle_signals().unwrap();
+    let tf = trap_frame(pm);
+    assert_eq!(tf.epc, HANDLER);
+    assert_eq!(tf.a0, Signal::SIGUSR1.val());
+    assert_eq!(tf.ra, trampoline::SIGRETURN);
+    assert!(tf.sp < sp && tf.sp % 16 == 0);
+    assert_eq!(
+        pm.get_signal_mask(running).unwrap(),
+        Signal::SIGUSR1.bit() | Signal::SIGUSR2.bit()
+    );
+
+    // the frame holds the interrupted context without the kernel half
+    let frame = get_user::<SignalFrame>(pm, tf.sp).unwrap();
+    assert_eq!(frame.trap_frame.epc, PC);
+    assert_eq!(frame.trap_frame.sp, sp);
+    assert_eq!(frame.trap_frame.kernel_sp, 0);
+    assert_eq!(frame.signo, Signal::SIGUSR1.val());
+    assert_eq!(frame.blocked, 0);
+
+    // sigreturn goes back to it
+    assert_eq!(pm.signal_return(), Ok(7));
+    let tf = trap_frame(pm);
+    assert_eq!(tf.epc, PC);
+    assert_eq!(tf.sp, sp);
+    assert_eq!(tf.kernel_sp, 0xdead_0000);
+    assert_eq!(pm.get_signal_mask(running).unwrap(), 0);
+    set_handler(pm, Signal::SIGUSR1, SIG_DFL, 0);
+}
+
+#[test_case]
+fn test_signal_blocked() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    enter_user(pm);
+    set_handler(pm, Signal::SIGUSR2, HANDLER, 0);
+
+    // a blocked signal waits until it is unblocked
+    pm.set_signal_mask(running, SIG_BLOCK, Signal::SIGUSR2.bit())
+        .unwrap();
+    pm.send_signal(running, Signal::SIGUSR2).unwrap();
+    pm.handle_signals().unwrap();
+    assert_eq!(trap_frame(pm).epc, PC);
+
+    pm.set_signal_mask(running, SIG_UNBLOCK, Signal::SIGUSR2.bit())
+        .unwrap();
+    pm.handle_signals().unwrap();
+    assert_eq!(trap_frame(pm).epc, HANDLER);
+    assert_eq!(pm.signal_return(), Ok(7));
+    assert_eq!(trap_frame(pm).epc, PC);
+
+    // an ignored one is dropped
+    set_handler(pm, Signal::SIGUSR2, SIG_IGN, 0);
+    pm.send_signal(running, Signal::SIGUSR2).unwrap();
+    pm.handle_signals().unwrap();
+    assert_eq!(trap_frame(pm).epc, PC);
+    set_handler(pm, Signal::SIGUSR2, SIG_DFL, 0);
+}
+
+#[test_case]
+fn test_signal_bad_frame() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+
+    // sigreturn with a frame in the pages mapped by the kernel faults
+    enter_user(pm);
+    trap_frame(pm).sp = trampoline::TRAPFRAME;
+    assert_eq!(pm.signal_return(), Ok(0));
+    let mut ptable = pm.ptable_lock_mut();
+    let signals = &mut get_process_mut!(ptable, running).unwrap().signals;
+    assert_ne!(signals.pending & Signal::SIGSEGV.bit(), 0);
+    signals.pending = 0;
+}
+
+#[test_case]
+fn test_signal_force() {
+    // a fault can't be blocked or ignored
+    let mut signals = SignalState::new();
+    signals.set_action(
+        Signal::SIGSEGV,
+        SigAction {
+            handler: SIG_IGN,
+            flags: 0,
+            mask: 0,
+        },
+    );
+    signals.set_blocked(SIG_BLOCK, Signal::SIGSEGV.bit());
+    signals.force(Signal::SIGSEGV);
+    assert!(matches!(
+        signals.dequeue(),
+        Some((
+            Signal::SIGSEGV,
+            Disposition::Default(DefaultAction::CoreDump)
+        ))
+    ));
+    assert_eq!(exception_signal(13), Signal::SIGSEGV);
+    assert_eq!(exception_signal(2), Signal::SIGILL);
+}
@@ -0,0 +1,90 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::process::*;
+use citron::strace::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn idle() {
+    let pm = unsafe { process_manager() };
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_strace_describe() {
+    let open = describe(3).unwrap();
+    assert_eq!(open.name, "open");
+    assert_eq!(open.args, &[Arg::Str]);
+    assert!(open.returns);
+    assert!(!describe(62).unwrap().returns);
+    assert!(describe(4242).is_none());
+}
+
+#[test_case]
+fn test_strace_format() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    assert_eq!(
+        format_call(pm, running, 129, &[7, 15, 0, 0, 0, 0]),
+        "kill(7, 15)"
+    );
+    assert_eq!(
+        format_call(pm, running, 4242, &[1, 0x20, 3, 0, 0, 0]),
+        "syscall_4242(0x1, 0x20, 0x3)"
+    );
+    assert_eq!(format_call(pm, running, 3, &[0; 6]), "open(NULL)");
+
+    assert_eq!(format_ret(3), "3");
+    assert_eq!(format_ret(-38_isize as usize), "-38 ENOSYS");
+    assert_eq!(format_ret(-99_isize as usize), "-99");
+    assert_eq!(format_ret(0x8000_0000), "0x80000000");
+}
+
+#[test_case]
+fn test_strace_linux() {
+    let write = describe_linux(64).unwrap();
+    assert_eq!(write.name, "write");
+    assert!(!describe_linux(94).unwrap().returns);
+    assert!(describe_linux(3).is_none());
+
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    assert_eq!(
+        format_linux_call(pm, running, 64, &[1, 0x1000, 12, 0, 0, 0]),
+        "write(1, [12 bytes], 12)"
+    );
+    assert_eq!(
+        format_linux_call(pm, running, 4242, &[1, 0x20, 3, 0, 0, 0]),
+        "linux_4242(0x1, 0x20, 0x3)"
+    );
+}
+
+#[test_case]
+fn test_strace_set() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    assert_eq!(
+        pm.set_strace(running, 4),
+        Err(ProcessError::InvalidArgument)
+    );
+    assert_eq!(pm.set_strace(running, STRACE_SYSCALLS), Ok(()));
+    assert_eq!(pm.set_strace(running, 0), Ok(()));
+
+    // an unrelated process can't be traced
+    let pid = pm.create_kernel_process("idle", 1, idle as usize).unwrap();
+    assert_eq!(
+        pm.set_strace(pid, STRACE_SYSCALLS),
+        Err(ProcessError::PermissionDenied)
+    );
+    pm.ready(pid).unwrap();
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::process::*;
+use citron::sync::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+static mut QUEUE: Option<WaitQueue> = None;
+static mut MUTEX: Option<KMutex<usize>> = None;
+static mut CONDVAR: Option<Condvar> = None;
+static mut WOKEN: bool = false;
+
+fn sleeper() {
+    let pm = unsafe { process_manager() };
+    unsafe { QUEUE.as_ref().unwrap() }.wait().unwrap();
+    unsafe {
+        WOKEN = true;
+    }
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_wait_queue_wake() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    unsafe {
+        QUEUE = Some(WaitQueue::new());
+    }
+    let queue = unsafe { QUEUE.as_ref().unwrap() };
+    assert_eq!(queue.wake_one(), Ok(false));
+
+    let pm = unsafe { process_manager() };
+    let pid = pm
+        .create_kernel_process("sleeper", 1, sleeper as usize)
+        .unwrap();
+    pm.ready(pid).unwrap();
+
+    while pm.get_process_state(pid).unwrap() != State::Blocked {
+        pm.schedule().unwrap();
+    }
+    assert_eq!(queue.wake_all(), Ok(1));
+
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+    assert!(unsafe { WOKEN });
+    assert!(queue.is_empty());
+}
+
+fn producer() {
+    let pm = unsafe { process_manager() };
+    let mut count = unsafe { MUTEX.as_ref().unwrap() }.lock();
+    *count += 1;
+    unsafe { CONDVAR.as_ref().unwrap() }.notify_one();
+    drop(count);
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_condvar_wait_until() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    unsafe {
+        MUTEX = Some(KMutex::new(0));
+        CONDVAR = Some(Condvar::new());
+    }
+    let mutex = unsafe { MUTEX.as_ref().unwrap() };
+    let condvar = unsafe { CONDVAR.as_ref().unwrap() };
+
+    let pm = unsafe { process_manager() };
+    let guard = mutex.lock();
+    let pid = pm
+        .create_kernel_process("producer", 1, producer as usize)
+        .unwrap();
+    pm.ready(pid).unwrap();
+
+    // the producer can only take the mutex while we sleep on the condvar
+    let guard = condvar.wait_until(guard, |count| *count == 1);
+    assert_eq!(*guard, 1);
+    drop(guard);
+
+    assert!(mutex.try_lock().is_some());
+
+    while pm.get_process_state(pid).unwrap() != State::Free {
+        pm.schedule().unwrap();
+    }
+}
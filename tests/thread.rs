@@ -0,0 +1,124 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::rc::Rc;
+use citron::arch::target::paging::{translate, EntryBits};
+use citron::arch::target::trampoline;
+use citron::process::*;
+use citron::uaccess::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+const BASE: usize = 0x7000_0000;
+const STACK: usize = 0x7000_1000;
+const TLS: usize = 0x7000_0800;
+const THREAD: usize = CLONE_VM | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD | CLONE_SETTLS;
+
+// a writable page at BASE in the null process
+fn map_page(pm: &mut ProcessManager) {
+    let running = pm.running;
+    let rw = EntryBits::R.val() | EntryBits::W.val() | EntryBits::U.val();
+    get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow_mut()
+        .map_anonymous(BASE, 0x1000, rw, usize::MAX)
+        .unwrap();
+}
+
+fn unmap_page(pm: &mut ProcessManager) {
+    let running = pm.running;
+    get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow_mut()
+        .unmap_anonymous(BASE, 0x1000);
+}
+
+// runs in the kernel in place of the user code of a thread, in the address space of the thread
+fn worker() {
+    let pm = unsafe { process::process_manager() };
+    put_user(pm, BASE, &0x5a5a_u64).unwrap();
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_thread_clone() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let tid = pm.clone_process(THREAD, STACK, TLS).unwrap();
+
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, running).unwrap();
+    let thread = get_process!(ptable, tid).unwrap();
+    assert_eq!(thread.tgid, proc.tgid);
+    assert!(Rc::ptr_eq(
+        thread.arch_proc.addr_space(),
+        proc.arch_proc.addr_space()
+    ));
+
+    // the threads have one root table, so satp stays when switching between them, and a trap
+    // frame each in it
+    assert_eq!(thread.arch_proc.page_table(), proc.arch_proc.page_table());
+    let slot = thread.arch_proc.thread_slot.unwrap();
+    assert_eq!(
+        thread.arch_proc.trap_frame_va,
+        trampoline::thread_trapframe(slot)
+    );
+    let page_table = unsafe { proc.arch_proc.page_table().as_ref() };
+    let (paddr, flags) = translate(page_table, thread.arch_proc.trap_frame_va).unwrap();
+    assert_eq!(paddr, thread.arch_proc.trap_frame as usize);
+    assert_eq!(flags & EntryBits::U.val(), 0);
+
+    // the new thread starts on its stack with its TLS in tp
+    let tf = unsafe { &*thread.arch_proc.trap_frame };
+    assert_eq!(tf.sp, STACK);
+    assert_eq!(tf.tp, TLS);
+    assert_eq!(tf.a0, 0);
+    let tgid = proc.tgid;
+    drop(ptable);
+
+    assert!(pm.threads_of(tgid).contains(&tid));
+    pm.kill(tid).unwrap();
+    assert!(!pm.threads_of(tgid).contains(&tid));
+}
+
+#[test_case]
+fn test_thread_join() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    map_page(pm);
+    let tid = pm.clone_process(THREAD, STACK, TLS).unwrap();
+    {
+        let mut ptable = pm.ptable_lock_mut();
+        let thread = get_process_mut!(ptable, tid).unwrap();
+        let stack = thread.kernel_stack + KERNEL_STACK_SIZE;
+        thread.arch_proc.init_context(worker as usize, stack);
+    }
+    pm.ready(tid).unwrap();
+
+    // the write of the thread is seen once it has exited
+    assert_eq!(pm.join_thread(tid), Ok(()));
+    assert_eq!(pm.get_process_state(tid), Ok(State::Free));
+    assert_eq!(get_user::<u64>(pm, BASE), Ok(0x5a5a));
+    unmap_page(pm);
+
+    assert_eq!(
+        pm.join_thread(running),
+        Err(ProcessError::ProcessNotFound(running))
+    );
+}
@@ -0,0 +1,81 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use citron::timer::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+fn run_until_expired(tw: &mut TimerWheel, id: TimerId, limit: usize) -> Option<usize> {
+    for tick in 1..=limit {
+        for timer in tw.advance() {
+            if timer.id == id {
+                return Some(tick);
+            }
+        }
+    }
+    None
+}
+
+#[test_case]
+fn test_timer_expires() {
+    let mut tw = TimerWheel::new();
+    let short = tw.add(10, None, TimerAction::Wakeup(0));
+    assert_eq!(run_until_expired(&mut tw, short, 100), Some(10));
+
+    // long enough to be cascaded from the upper levels
+    let long = tw.add(100000, None, TimerAction::Wakeup(0));
+    assert_eq!(run_until_expired(&mut tw, long, 200000), Some(100000));
+    assert_eq!(tw.len(), 0);
+}
+
+#[test_case]
+fn test_timer_cancel() {
+    let mut tw = TimerWheel::new();
+    let id = tw.add(5, None, TimerAction::Wakeup(0));
+    assert!(tw.is_pending(id));
+    assert!(tw.cancel(id).is_some());
+    assert_eq!(run_until_expired(&mut tw, id, 100), None);
+}
+
+#[test_case]
+fn test_timer_periodic() {
+    static mut COUNT: usize = 0;
+
+    let mut tw = TimerWheel::new();
+    tw.add(
+        3,
+        Some(3),
+        TimerAction::Callback(Box::new(|_| unsafe { COUNT += 1 })),
+    );
+    for _ in 0..30 {
+        for mut timer in tw.advance() {
+            if let TimerAction::Callback(ref mut callback) = timer.action {
+                callback(timer.id);
+            }
+            tw.rearm(timer);
+        }
+    }
+    assert_eq!(unsafe { COUNT }, 10);
+}
+
+#[test_case]
+fn test_timer_periodic_cancel() {
+    // cancelled by its own action, between advance and rearm
+    let mut tw = TimerWheel::new();
+    let id = tw.add(2, Some(2), TimerAction::Wakeup(0));
+    tw.advance();
+    let timer = tw.advance().pop().unwrap();
+    assert!(tw.cancel(id).is_none());
+    tw.rearm(timer);
+    assert!(!tw.is_pending(id));
+    assert_eq!(run_until_expired(&mut tw, id, 10), None);
+}
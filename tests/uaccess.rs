@@ -0,0 +1,113 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use citron::arch::target::paging::EntryBits;
+use citron::process::*;
+use citron::uaccess::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+const BASE: usize = 0x7000_0000;
+
+// two writable pages at BASE, then an unmapped page and a read only one, in the null process
+fn map_pages(pm: &mut ProcessManager) {
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let mut addr_space = get_process!(ptable, running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow_mut();
+    let r = EntryBits::R.val() | EntryBits::U.val();
+    let rw = r | EntryBits::W.val();
+    addr_space
+        .map_anonymous(BASE, 0x2000, rw, usize::MAX)
+        .unwrap();
+    addr_space
+        .map_anonymous(BASE + 0x3000, 0x1000, r, usize::MAX)
+        .unwrap();
+}
+
+fn unmap_pages(pm: &mut ProcessManager) {
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    get_process!(ptable, running)
+        .unwrap()
+        .arch_proc
+        .addr_space()
+        .borrow_mut()
+        .unmap_anonymous(BASE, 0x4000);
+}
+
+#[test_case]
+fn test_uaccess_copy() {
+    let pm = unsafe { process_manager() };
+    map_pages(pm);
+
+    // a copy across the page boundary
+    let data = [0x5a; 0x20];
+    assert_eq!(copy_to_user(pm, BASE + 0xff0, &data), Ok(()));
+    let mut buffer = [0; 0x20];
+    assert_eq!(copy_from_user(pm, BASE + 0xff0, &mut buffer), Ok(()));
+    assert_eq!(buffer, data);
+
+    // the read only page can be read but not written
+    let mut buffer = [0xff; 0x10];
+    assert_eq!(copy_from_user(pm, BASE + 0x3000, &mut buffer), Ok(()));
+    assert_eq!(buffer, [0; 0x10]);
+    assert_eq!(
+        copy_to_user(pm, BASE + 0x3000, &data),
+        Err(ProcessError::BadAddress)
+    );
+    assert_eq!(
+        copy_from_user(pm, BASE + 0x1ff8, &mut buffer),
+        Err(ProcessError::BadAddress)
+    );
+    assert_eq!(
+        copy_from_user(pm, usize::MAX - 4, &mut buffer),
+        Err(ProcessError::BadAddress)
+    );
+
+    assert_eq!(put_user(pm, BASE + 0x100, &0x1234_5678_u32), Ok(()));
+    assert_eq!(get_user::<u32>(pm, BASE + 0x100), Ok(0x1234_5678));
+    assert_eq!(
+        get_user::<u64>(pm, BASE + 0x2000),
+        Err(ProcessError::BadAddress)
+    );
+    unmap_pages(pm);
+}
+
+#[test_case]
+fn test_uaccess_strncpy() {
+    let pm = unsafe { process_manager() };
+    map_pages(pm);
+
+    // a string across the page boundary
+    assert_eq!(copy_to_user(pm, BASE + 0xffc, b"citron\0"), Ok(()));
+    assert_eq!(
+        strncpy_from_user(pm, BASE + 0xffc, 16),
+        Ok(Some(String::from("citron")))
+    );
+    assert_eq!(
+        strncpy_from_user(pm, BASE + 0xffc, 7).unwrap().unwrap(),
+        "citron"
+    );
+    assert_eq!(strncpy_from_user(pm, BASE + 0xffc, 6), Ok(None));
+
+    // the string runs into the unmapped page
+    assert_eq!(copy_to_user(pm, BASE + 0x1ffc, b"abcd"), Ok(()));
+    assert_eq!(
+        strncpy_from_user(pm, BASE + 0x1ffc, 16),
+        Err(ProcessError::BadAddress)
+    );
+    unmap_pages(pm);
+}
@@ -0,0 +1,67 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+use citron::arch::target::clint;
+use citron::arch::target::paging::{translate, EntryBits};
+use citron::arch::target::vdso::*;
+use citron::process::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+#[test_case]
+fn test_vdso_mapping() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let ptable = pm.ptable_lock();
+    let proc = get_process!(ptable, running).unwrap();
+    let addr_space = proc.arch_proc.addr_space().borrow();
+    let page_table = unsafe { addr_space.page_table.as_ref() };
+    let bits = EntryBits::R.val() | EntryBits::W.val() | EntryBits::X.val() | EntryBits::U.val();
+
+    // the data page is read only for user mode
+    let (paddr, flags) = translate(page_table, VDSO_DATA).unwrap();
+    assert_eq!(paddr, addr_space.vdso_data.as_ptr() as usize);
+    assert_eq!(paddr, proc.arch_proc.vdso_data as usize);
+    assert_eq!(flags & bits, EntryBits::R.val() | EntryBits::U.val());
+
+    let (paddr, flags) = translate(page_table, VDSO_CODE).unwrap();
+    assert_eq!(paddr, vdso as usize);
+    assert_eq!(
+        flags & bits,
+        EntryBits::R.val() | EntryBits::X.val() | EntryBits::U.val()
+    );
+
+    // the kernel mapped area isn't user memory for system calls
+    assert_eq!(addr_space.user_to_phys(VDSO_DATA), None);
+}
+
+#[test_case]
+fn test_vdso_data() {
+    let pm = unsafe { process_manager() };
+    let running = pm.running;
+    let data = get_process!(pm.ptable_lock(), running)
+        .unwrap()
+        .arch_proc
+        .vdso_data;
+    let data = unsafe { &*data };
+    assert_eq!(data.timebase_freq, clint::TIMEBASE_FREQ as u64);
+    assert_eq!(data.ticks_per_sec, clint::TICKS_PER_SEC as u64);
+    assert_eq!(data.boot_time, clint::boot_time() as u64);
+    assert_eq!(data.pid, running as u64);
+
+    // every update leaves the counter even and moved on
+    let seq = data.seq;
+    assert_eq!(seq % 2, 0);
+    pm.charge_cpu_tick().unwrap();
+    assert_eq!(data.seq, seq + 2);
+    assert_eq!(
+        data.cpu_time,
+        pm.get_resource_usage(running).unwrap().cpu as u64
+    );
+}
@@ -0,0 +1,74 @@
+#![feature(panic_info_message)]
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+#![test_runner(citron::test_runner)]
+
+extern crate alloc;
+
+use alloc::format;
+use citron::process::*;
+use citron::sync::*;
+use citron::watchdog::*;
+use citron::*;
+use core::arch::asm;
+
+test_harness!();
+
+static mut QUEUE: Option<WaitQueue> = None;
+static mut SID: Sid = 0;
+
+fn sleeper() {
+    let pm = unsafe { process_manager() };
+    unsafe { QUEUE.as_ref().unwrap() }.wait().unwrap();
+
+    pm.kill(pm.running).unwrap();
+}
+
+fn waiter() {
+    let pm = unsafe { process_manager() };
+    pm.wait_semaphore(unsafe { SID }).unwrap();
+
+    pm.kill(pm.running).unwrap();
+}
+
+#[test_case]
+fn test_wait_target() {
+    arch::target::interrupt::interrupt_on();
+    arch::target::interrupt::timer_interrupt_on();
+
+    let pm = unsafe { process_manager() };
+    unsafe {
+        QUEUE = Some(WaitQueue::named("test queue"));
+        SID = pm.create_semaphore(0);
+    }
+
+    let sleeper_pid = pm
+        .create_kernel_process("sleeper", 1, sleeper as usize)
+        .unwrap();
+    pm.ready(sleeper_pid).unwrap();
+    let waiter_pid = pm
+        .create_kernel_process("waiter", 1, waiter as usize)
+        .unwrap();
+    pm.ready(waiter_pid).unwrap();
+
+    while pm.get_process_state(sleeper_pid).unwrap() != State::Blocked
+        || pm.get_process_state(waiter_pid).unwrap() != State::SemaWait
+    {
+        pm.schedule().unwrap();
+    }
+    assert_eq!(wait_target(sleeper_pid).unwrap(), "test queue");
+    assert_eq!(
+        wait_target(waiter_pid).unwrap(),
+        format!("semaphore {}", unsafe { SID })
+    );
+
+    unsafe { QUEUE.as_ref().unwrap() }.wake_all().unwrap();
+    pm.signal_semaphore(unsafe { SID }).unwrap();
+    while pm.get_process_state(sleeper_pid).unwrap() != State::Free
+        || pm.get_process_state(waiter_pid).unwrap() != State::Free
+    {
+        pm.schedule().unwrap();
+    }
+}